@@ -1,49 +1,222 @@
-use oxidize_pdf::page;
+use crate::color::{self, ColorDetection};
+use crate::composite_source::SourcePart;
+use crate::cover::{CoverPrint, CoverSpec};
+use crate::crop::{self, CropMode};
+use crate::distribution::{Distribution, SheetRange};
+use crate::encoding::ImageEncoding;
+use crate::error::BookletError;
+use crate::manifest::{self, Manifest};
+use crate::output::{self, FileNameFields, OverwritePolicy};
+use crate::page_source::PageSource;
+use crate::pdf_creator::BookletSummary;
+use crate::progress::{CancelToken, ProgressListener, ProgressTracker};
+use crate::scan_cleanup::ScanCleanup;
+use crate::sheet_split::{self, SheetGrouping, SheetSplit};
+use crate::stamp::{Stamper, Stamps};
+use crate::verify;
+use crate::{pdf_creator, pdf_vector};
+use oxidize_pdf::Page;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt;
+use std::path::{Path, PathBuf};
 
-use crate::{pdf_creator, pdf_render::PdfDocumentHolder};
-use std::path::PathBuf;
+/// 中缝线样式
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CenterLineStyle {
+    /// 不画中缝线
+    Hidden,
+    /// 点线，参数为点间距(mm)
+    Dotted(f64),
+    /// 虚线，参数为线段长度和间隔(mm)
+    Dashed(f64, f64),
+    /// 实线
+    Solid,
+}
+
+/// 单页（A5）的页边距，单位mm
+///
+/// 页边距以源页面为准：`head`/`foot` 对应页面顶部/底部，
+/// `inner` 为装订侧（中缝），`outer` 为切口侧。
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Margins {
+    /// 切口侧页边距
+    pub outer: f64,
+    /// 装订侧页边距（中缝）
+    pub inner: f64,
+    /// 天头页边距
+    pub head: f64,
+    /// 地脚页边距
+    pub foot: f64,
+    /// 中缝线样式
+    pub center_line: CenterLineStyle,
+}
+
+impl Default for Margins {
+    fn default() -> Self {
+        Self {
+            outer: 1.3,
+            inner: 3.0,
+            head: 3.0,
+            foot: 3.0,
+            center_line: CenterLineStyle::Dotted(12.0),
+        }
+    }
+}
+
+impl Margins {
+    /// 检查页边距：不能为负，相对两侧的页边距之和要小于单页（半张A4纸）的宽高，否则放不下页面
+    pub fn check(&self) -> Result<(), BookletError> {
+        let sides = [
+            ("切口侧", self.outer),
+            ("装订侧", self.inner),
+            ("天头", self.head),
+            ("地脚", self.foot),
+        ];
+        if let Some((name, _)) = sides.iter().find(|(_, value)| value.is_nan()) {
+            return Err(BookletError::InvalidRule(format!(
+                "{}页边距不是有效的数值",
+                name
+            )));
+        }
+        if let Some((name, value)) = sides.iter().find(|(_, value)| *value < 0.0) {
+            return Err(BookletError::InvalidRule(format!(
+                "{}页边距 {} mm 不能为负",
+                name, value
+            )));
+        }
+        // 单页的宽高(mm)：A4纸高度的一半和A4纸的宽度
+        let a4 = Page::a4();
+        let slot_w = a4.height() / 2.0 / pdf_creator::MM_TO_PT;
+        let slot_h = a4.width() / pdf_creator::MM_TO_PT;
+        if self.outer + self.inner >= slot_w {
+            return Err(BookletError::InvalidRule(format!(
+                "切口侧和装订侧页边距之和 {} mm 超过了单页宽度 {:.1} mm",
+                self.outer + self.inner,
+                slot_w
+            )));
+        }
+        if self.head + self.foot >= slot_h {
+            return Err(BookletError::InvalidRule(format!(
+                "天头和地脚页边距之和 {} mm 超过了单页高度 {:.1} mm",
+                self.head + self.foot,
+                slot_h
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// 页面排版方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RenderMode {
+    /// 把源页面渲染为图像后排版，支持裁剪和扫描清理，需要pdfium
+    #[cfg_attr(feature = "pdfium", default)]
+    Raster,
+    /// 直接复制源页面的内容，保留文字和矢量图形，文件更小，不需要pdfium。
+    /// 支持手动裁剪，不支持扫描清理
+    #[cfg_attr(not(feature = "pdfium"), default)]
+    Vector,
+}
 
+impl fmt::Display for RenderMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderMode::Raster => write!(f, "位图"),
+            RenderMode::Vector => write!(f, "矢量"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct BindingRule {
-    /// 输入PDF文件路径
+    /// 源文件路径：PDF文件、图像文件（包括多页TIFF）或图像文件夹
     pub input_path: PathBuf,
+    /// 源图像的分辨率（DPI），不知道时为 `None`；高于300DPI的图像缩小到300DPI
+    pub image_dpi: Option<u32>,
+    /// 按顺序拼接成一本书的多个源文件，为空时只使用 `input_path`，
+    /// 不为空时 `input_path` 只用于输出文件名
+    pub sources: Vec<SourcePart>,
     /// 输出目录（默认源文件所在目录下的out文件夹）
     pub output_dir: PathBuf,
-    /// 每个小册子的A4纸数量（默认10张，即40页）
-    pub sheets_per_booklet: usize,
+    /// 每个小册子的A4纸数量范围（默认最多10张，即40页）
+    pub sheets_per_booklet: SheetRange,
+    /// 各册纸张数的分配方式
+    pub distribution: Distribution,
+    /// 按顺序指定每册的纸张数，不为空时代替按 `sheets_per_booklet` 分配，
+    /// 不能与强制分册同时使用
+    pub booklet_sheet_counts: Vec<u32>,
+    /// 在这些页之前开始新的一册（源页码，从1开始，不含单独的封面文件中的页面），
+    /// 前一册不足的页面用空白页填满
+    pub booklet_breaks: Vec<u16>,
 
     /// 装订方式（默认为true:在中间装订）
     pub binding_at_middle: bool,
-    // 是否有封面封底（第一页和最后一页）
-    pub has_cover: bool,
-    // 是否仅打印正文（不保留封面封底）
-    pub keep_cover: bool,
-    // /// 是否在首页前添加空白页作为封面
-    // pub add_blank_cover: bool,
-    // /// 是否添加页码
-    // pub add_page_numbers: bool,
-    // /// 页码格式
-    // pub page_number_format: PageNumberFormat,
-    // /// 页码位置
-    // pub page_number_position: PageNumberPosition,
+    /// 封面封底的页数和打印方式
+    pub cover: CoverSpec,
+    /// 页边距和中缝线样式
+    pub margins: Margins,
+    /// 页面裁剪方式（封面封底不裁剪），裁剪后的内容等比缩放填满页面
+    pub crop: CropMode,
+    /// 扫描页面清理（纠偏、去噪点、背景变白等），在裁剪之前执行
+    pub scan_cleanup: ScanCleanup,
+    /// 彩色页面检测，黑白页面按灰度渲染，并报告含有彩色页面的纸张
+    pub color_detection: ColorDetection,
+    /// 按纸张属性（彩色、含有图像）分组，生成插回表和各组单独的PDF文件
+    pub sheet_split: SheetSplit,
+    /// 水印和页眉页脚，只用于位图排版
+    pub stamps: Stamps,
+    /// 页面排版方式（位图或矢量），默认位图，没有启用pdfium时为矢量
+    pub render_mode: RenderMode,
+    /// 页面图像编码方式，只用于位图排版
+    pub image_encoding: ImageEncoding,
+    /// 处理页面图像的线程数，0表示按CPU核心数自动选择，1表示单线程
+    pub render_threads: usize,
+    /// 输出文件已存在时的处理方式
+    pub overwrite: OverwritePolicy,
+    /// 输出文件名模板，占位符见 `output::render_file_name`
+    pub file_name_template: String,
+    /// 生成前校验排版结果，发现缺页、重页或顺序错误时不生成
+    pub verify: bool,
 }
 
 impl Default for BindingRule {
     fn default() -> Self {
         Self {
             input_path: PathBuf::new(),
+            image_dpi: None,
+            sources: Vec::new(),
             output_dir: PathBuf::new(),
-            sheets_per_booklet: 10,
+            sheets_per_booklet: SheetRange::default(),
+            distribution: Distribution::default(),
+            booklet_sheet_counts: Vec::new(),
+            booklet_breaks: Vec::new(),
             binding_at_middle: true,
-            has_cover: false,
-            keep_cover: false,
+            cover: CoverSpec::default(),
+            margins: Margins::default(),
+            crop: CropMode::None,
+            scan_cleanup: ScanCleanup::default(),
+            color_detection: ColorDetection::default(),
+            sheet_split: SheetSplit::default(),
+            stamps: Stamps::default(),
+            render_mode: RenderMode::default(),
+            image_encoding: ImageEncoding::default(),
+            render_threads: 0,
+            overwrite: OverwritePolicy::default(),
+            file_name_template: output::DEFAULT_FILE_NAME_TEMPLATE.to_string(),
+            verify: true,
         }
     }
 }
 
 impl BindingRule {
-    pub fn new(input_path: &PathBuf) -> Self {
+    pub fn new(input_path: &Path) -> Self {
         Self {
-            input_path: input_path.clone(),
+            input_path: input_path.to_path_buf(),
             output_dir: input_path.parent().unwrap().join("out"),
             ..Default::default()
         }
@@ -55,137 +228,880 @@ impl BindingRule {
         }
         self
     }
+
+    /// 实际使用的线程数
+    ///
+    /// 自动选择时最多4个线程，每个线程同时持有一页300DPI的图像，线程过多会占用大量内存
+    pub fn render_thread_count(&self) -> usize {
+        if self.render_threads > 0 {
+            self.render_threads
+        } else {
+            std::thread::available_parallelism()
+                .map(|n| n.get().min(4))
+                .unwrap_or(1)
+        }
+    }
+
+    /// 正文（不含封面封底）的页面索引范围 `[start, end)`
+    pub fn body_page_range(&self, page_count: u16) -> (u16, u16) {
+        let (front, back) = self.cover.page_counts(page_count);
+        (front, page_count - back)
+    }
+
+    /// 有源页码的页面索引范围 `[start, end)`
+    ///
+    /// 源页码从拼接后的源文件的第一页开始编号。封面封底在源文件中时与正文一起编号；
+    /// 在单独的封面文件中时不编号，这时即为正文的范围
+    pub fn numbered_page_range(&self, page_count: u16) -> (u16, u16) {
+        if self.cover.file.is_some() {
+            self.body_page_range(page_count)
+        } else {
+            (0, page_count)
+        }
+    }
+
+    /// 源页码（从1开始）在拼接后的页面序列中的索引，单独的封面文件的封面页排在前面
+    pub fn page_index(&self, page: u16) -> u16 {
+        let offset = if self.cover.file.is_some() {
+            self.cover.front_pages
+        } else {
+            0
+        };
+        page.saturating_sub(1).saturating_add(offset)
+    }
+
+    /// 全部源文件的路径
+    #[cfg_attr(not(feature = "pdfium"), allow(dead_code))]
+    pub fn source_paths(&self) -> Vec<&Path> {
+        let mut paths = if self.sources.is_empty() {
+            vec![self.input_path.as_path()]
+        } else {
+            self.sources
+                .iter()
+                .map(|part| part.path.as_path())
+                .collect()
+        };
+        paths.extend(self.cover.file.as_deref());
+        paths
+    }
+
+    /// 在强制分册处把页面索引范围 `[start, end)` 分为几段，每段至少一页
+    pub fn split_at_breaks(&self, start: u16, end: u16) -> Vec<(u16, u16)> {
+        let mut breaks: Vec<u16> = self
+            .booklet_breaks
+            .iter()
+            .map(|&page| self.page_index(page))
+            .filter(|&page_idx| page_idx > start && page_idx < end)
+            .collect();
+        breaks.sort_unstable();
+        breaks.dedup();
+        let mut segments = Vec::with_capacity(breaks.len() + 1);
+        let mut segment_start = start;
+        for page_idx in breaks {
+            segments.push((segment_start, page_idx));
+            segment_start = page_idx;
+        }
+        segments.push((segment_start, end));
+        segments
+    }
 }
 
+/// 各册纸张数量的分配
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct BookletConfig {
+    /// 每册纸张数
     pub booklet_sheets: u32,
-    // 加1张纸的册子数量
+    /// 加1张纸的册子数量
     pub add_sheet_booklet_count: u32,
     /// 最后一册的填充页数
     pub tail_pad_page: u32,
-    // /// 源文件有封面封底
-    // has_cover: bool,
-    // /// 保留封面封底
-    // keep_cover: bool,
 }
 
 /// 计算每册的纸张数量
-fn calc_booklet_sheets(
-    page_count: u32,
-    sheets_per_booklet: u32,
-    has_cover: bool,
-    keep_cover: bool,
-) -> BookletConfig {
-    // let last_add = page_count % 4;
+fn calc_booklet_sheets(page_count: u32, sheets_per_booklet: u32) -> BookletConfig {
     // 对齐到4的倍数
-    // let mut keep_cover = keep_cover;
-    let page_count = if has_cover {
-        if keep_cover {
-            // 封面和封底背面各增加一张空白页
-            page_count + 2
-        } else {
-            page_count - 2
-        }
-    } else {
-        // keep_cover = false;
-        page_count
-    };
-
-    let total = ((page_count + 3) / 4) * 4;
+    let total = page_count.div_ceil(4) * 4;
     let last_add = total - page_count;
-    // println!(
-    //     "末尾添加{}页空白页。若在其他位置插入请先自行修改源PDF",
-    //     last_add
-    // );
     // 每册对应的页数
-    let pages_per_booklet = sheets_per_booklet * 4;
+    let pages_per_booklet = sheets_per_booklet.max(1) * 4;
     // 获取册数
     let mut booklet_count = total / pages_per_booklet;
     // 最后一册的页数
     let last_booklet_sheets = total % pages_per_booklet;
-    let mut booklet_sheets = sheets_per_booklet;
+    let mut booklet_sheets = sheets_per_booklet.max(1);
     // 重新分配每册页数
     if last_booklet_sheets / 4 <= booklet_count {
         // 最后一册全部分给前几册，每册多分1张纸
-        let res = BookletConfig {
+        BookletConfig {
             booklet_sheets,
             add_sheet_booklet_count: last_booklet_sheets / 4,
             tail_pad_page: last_add,
-            // has_cover,
-            // keep_cover,
-        };
-        // booklet_sheets += 1;
-        return res;
+        }
     } else if last_booklet_sheets * 4 < pages_per_booklet * 3 {
         // 最后一册纸张数小于期望页数的3/4，册数不变，页数均分
         booklet_count += 1;
         // booklet_sheets 一定会小于 paper_count_per_booklet
         booklet_sheets = total / booklet_count / 4;
-        // remain_booklet_sheets 一定会小于 booklet_sheets
-        let remain_booklet_sheets = (booklet_sheets * 4 * booklet_count - total) / 4;
-        let booklet_config = BookletConfig {
+        // remain_booklet_sheets 一定会小于 booklet_count
+        let remain_booklet_sheets = (total - booklet_sheets * 4 * booklet_count) / 4;
+        BookletConfig {
             booklet_sheets,
             add_sheet_booklet_count: remain_booklet_sheets,
             tail_pad_page: last_add,
-            // has_cover,
-            // keep_cover,
-        };
-        return booklet_config;
+        }
     } else {
         BookletConfig {
             booklet_sheets,
             add_sheet_booklet_count: 0,
             tail_pad_page: last_add,
-            // has_cover,
-            // keep_cover,
         }
     }
 }
 
-pub fn create_booklet(src_pdf: &PdfDocumentHolder, binding_rule: &BindingRule) {
-    let has_cover = binding_rule.has_cover;
-    let keep_cover = binding_rule.keep_cover;
-    let (mut page_idx, page_count) = if has_cover && !keep_cover {
-        (1u16, src_pdf.get_page_count() - 2)
+/// 按 `BookletConfig` 逐册计算纸张数：前几册各加1张，最后一册放剩下的页面和填充页
+///
+/// # 参数
+/// * `page_count` - 需要排版的页面位置数
+/// * `sheets_per_booklet` - 每册最多纸张数
+fn auto_sheet_counts(page_count: u32, sheets_per_booklet: u32) -> Vec<u32> {
+    let config = calc_booklet_sheets(page_count, sheets_per_booklet);
+    let total = page_count + config.tail_pad_page;
+    let mut counts = Vec::new();
+    let mut page_idx = 0;
+    while page_idx < page_count {
+        let mut pages = config.booklet_sheets * 4;
+        if (counts.len() as u32) < config.add_sheet_booklet_count {
+            pages += 4;
+        }
+        if page_idx + pages >= page_count {
+            pages = total - page_idx;
+        }
+        counts.push(pages / 4);
+        page_idx += pages;
+    }
+    counts
+}
+
+/// 计算各册纸张数量的分配，用于 `Distribution::Auto`
+///
+/// # 参数
+/// * `src_page_count` - 源文件页数
+/// * `binding_rule` - 装订规则
+pub fn booklet_config(src_page_count: u16, binding_rule: &BindingRule) -> BookletConfig {
+    calc_booklet_sheets(
+        layout_page_count(src_page_count, binding_rule),
+        binding_rule.sheets_per_booklet.max,
+    )
+}
+
+/// 需要排版的页面位置数，不含凑满纸张的空白页，也不含单独成册的封面封底
+///
+/// 与正文一起打印封面封底时包含封面封底和它们背面的空白页
+fn layout_page_count(src_page_count: u16, binding_rule: &BindingRule) -> u32 {
+    layout_segments(src_page_count, binding_rule)
+        .iter()
+        .map(|segment| segment.sequence.len() as u32)
+        .sum()
+}
+
+/// 检查每册纸张数：范围有效；指定了每册纸张数时纸张足够排下全部页面，并且最后一册不是空白的
+///
+/// # 参数
+/// * `src_page_count` - 源文件页数
+/// * `binding_rule` - 装订规则，需要已经加入源文件要求的强制分册
+pub fn check_sheet_counts(
+    src_page_count: u16,
+    binding_rule: &BindingRule,
+) -> Result<(), BookletError> {
+    let range = binding_rule.sheets_per_booklet;
+    if !range.is_valid() {
+        return Err(BookletError::InvalidRule(format!(
+            "每册纸张数范围 {}-{} 无效",
+            range.min, range.max
+        )));
+    }
+    let counts = &binding_rule.booklet_sheet_counts;
+    let Some(&last) = counts.last() else {
+        return Ok(());
+    };
+    if counts.contains(&0) {
+        return Err(BookletError::InvalidRule("每册纸张数不能为0".to_string()));
+    }
+    if !binding_rule.booklet_breaks.is_empty() {
+        return Err(BookletError::InvalidRule(
+            "指定每册纸张数时不能强制分册".to_string(),
+        ));
+    }
+    let needed = layout_page_count(src_page_count, binding_rule) as u64;
+    let sheets: u64 = counts.iter().map(|&n| n as u64).sum();
+    if sheets * 4 < needed {
+        return Err(BookletError::InvalidRule(format!(
+            "指定的{}册共{}张纸，最多排{}页，需要{}页",
+            counts.len(),
+            sheets,
+            sheets * 4,
+            needed
+        )));
+    }
+    // 最后一册至少有一页；填充的空白页在封底之前，封底总在最后一册
+    let front_sheets = sheets - last as u64;
+    if front_sheets * 4 >= needed {
+        return Err(BookletError::InvalidRule(format!(
+            "指定的纸张太多，前{}册的{}张纸已经能排下全部{}页",
+            counts.len() - 1,
+            front_sheets,
+            needed
+        )));
+    }
+    Ok(())
+}
+
+/// 连续排版的一段页面，段末尾用空白页填满纸张
+struct Segment {
+    /// 按阅读顺序的源页面索引，空白页为 `None`
+    sequence: Vec<Option<u16>>,
+    /// 填充的空白页插入的位置：正文之后，封底之前
+    pad_at: usize,
+}
+
+impl Segment {
+    fn new(sequence: Vec<Option<u16>>) -> Self {
+        let pad_at = sequence.len();
+        Self { sequence, pad_at }
+    }
+}
+
+/// 与正文一起排版的各段：正文在强制分册处分段，封面在第一段开头，封底在最后一段末尾
+///
+/// 指定了每册纸张数时不分段
+///
+/// # 参数
+/// * `src_page_count` - 源文件页数
+/// * `binding_rule` - 装订规则
+fn layout_segments(src_page_count: u16, binding_rule: &BindingRule) -> Vec<Segment> {
+    let (body_start, body_end) = binding_rule.body_page_range(src_page_count);
+    let ranges = if binding_rule.booklet_sheet_counts.is_empty() {
+        binding_rule.split_at_breaks(body_start, body_end)
     } else {
-        (0u16, src_pdf.get_page_count())
+        vec![(body_start, body_end)]
     };
-    let booklet_config = calc_booklet_sheets(
-        page_count as u32,
-        binding_rule.sheets_per_booklet as u32,
-        has_cover,
-        keep_cover,
+    let mut segments: Vec<Segment> = ranges
+        .into_iter()
+        .map(|(start, end)| Segment::new((start..end).map(Some).collect()))
+        .collect();
+    let cover = &binding_rule.cover;
+    if cover.print == CoverPrint::WithBody {
+        let front = cover.front_sequence(src_page_count);
+        let first = &mut segments[0];
+        first.pad_at += front.len();
+        first.sequence.splice(0..0, front);
+        let last = segments.last_mut().expect("至少有一段");
+        last.sequence.extend(cover.back_sequence(src_page_count));
+    }
+    segments
+}
+
+/// 单独成册的封面封底：封面、空白页、封底，不单独打印时为 `None`
+fn cover_segment(src_page_count: u16, binding_rule: &BindingRule) -> Option<Segment> {
+    let cover = &binding_rule.cover;
+    if cover.print != CoverPrint::Separate || !cover.is_enabled() {
+        return None;
+    }
+    let mut segment = Segment::new(cover.front_sequence(src_page_count));
+    segment.sequence.extend(cover.back_sequence(src_page_count));
+    Some(segment)
+}
+
+/// 一段各册的纸张数：指定了每册纸张数时按指定的数量，否则按分配方式
+///
+/// `Auto` 按最多张数分配，不足最少张数的册子与其他分配方式一样用空白页填满
+fn segment_sheet_counts(page_count: u32, binding_rule: &BindingRule) -> Vec<u32> {
+    let range = binding_rule.sheets_per_booklet;
+    binding_rule
+        .distribution
+        .sheet_counts(page_count, range)
+        .unwrap_or_else(|| {
+            let range = range.clamped();
+            auto_sheet_counts(page_count, range.max)
+                .into_iter()
+                .map(|count| count.max(range.min))
+                .collect()
+        })
+}
+
+/// 一册在全书阅读顺序中的范围
+#[derive(Clone, Debug)]
+pub struct BookletRange {
+    /// 册子编号（从1开始，单独成册的封面封底为0）
+    pub booklet_num: u16,
+    /// 册子总数，不含单独成册的封面封底
+    pub booklet_count: u16,
+    /// 在全书阅读顺序中的开始位置(包含)
+    pub start_page: u16,
+    /// 在全书阅读顺序中的结束位置(不包含)，包含填充的空白页
+    pub end_page: u16,
+    /// 本册按阅读顺序的源页面索引，空白页为 `None`，页数是4的倍数
+    pub pages: Vec<Option<u16>>,
+}
+
+/// 把一段按各册纸张数分成几册，多出的纸张用空白页填在正文之后
+///
+/// 纸张数不够时（见 `check_sheet_counts`）舍弃排不下的页面
+fn push_segment_booklets(
+    booklets: &mut Vec<BookletRange>,
+    position: &mut u32,
+    segment: Segment,
+    counts: &[u32],
+) {
+    let total = counts.iter().sum::<u32>() as usize * 4;
+    let mut sequence = segment.sequence;
+    let pad = total.saturating_sub(sequence.len());
+    sequence.splice(
+        segment.pad_at..segment.pad_at,
+        std::iter::repeat_n(None, pad),
     );
-    let mut booklet_idx = 0u16;
+    sequence.truncate(total);
+    let mut pages = sequence.into_iter();
+    let to_u16 = |position: u32| position.min(u16::MAX as u32) as u16;
+    for &sheets in counts {
+        let start = *position;
+        *position += sheets * 4;
+        booklets.push(BookletRange {
+            booklet_num: 0,
+            booklet_count: 0,
+            start_page: to_u16(start),
+            end_page: to_u16(*position),
+            pages: pages.by_ref().take(sheets as usize * 4).collect(),
+        });
+    }
+}
 
-    let pages_per_booklet = (booklet_config.booklet_sheets * 4) as u16;
-    while page_idx < page_count {
-        let booklet_start_page = page_idx;
-        let mut booklet_end_page = booklet_start_page + pages_per_booklet;
-        if (booklet_idx as u32) < booklet_config.add_sheet_booklet_count {
-            booklet_end_page += 4;
-        }
-        let is_last_booklet = booklet_end_page >= page_count;
-        if booklet_end_page > page_count {
-            booklet_end_page = page_count + booklet_config.tail_pad_page as u16;
-        }
-        if has_cover && keep_cover {
-            if booklet_idx == 0 {
-                booklet_end_page -= 1;
-            } else if is_last_booklet {
-                // 封面背面的空白页已经包含，只需要加1
-                booklet_end_page += 1;
-            }
+/// 计算每一册在全书阅读顺序中的范围
+///
+/// 先按阅读顺序排列全部页面：封面、封面背面的空白页、正文、封底背面的空白页、封底。
+/// 有强制分册时分段计算，每段按单独的一本书分配纸张，段末尾用空白页填满；
+/// 指定了每册纸张数时按指定的数量，否则按分配方式。
+/// 封面封底单独成册时排在最前，册号为0
+///
+/// # 参数
+/// * `src_page_count` - 源文件页数
+/// * `binding_rule` - 装订规则
+pub fn plan_booklets(src_page_count: u16, binding_rule: &BindingRule) -> Vec<BookletRange> {
+    let mut booklets = Vec::new();
+    let mut position = 0;
+    if let Some(segment) = cover_segment(src_page_count, binding_rule) {
+        let sheets = (segment.sequence.len() as u32).div_ceil(4);
+        push_segment_booklets(&mut booklets, &mut position, segment, &[sheets]);
+    }
+    let body_first = booklets.len();
+    for segment in layout_segments(src_page_count, binding_rule) {
+        let counts = if binding_rule.booklet_sheet_counts.is_empty() {
+            segment_sheet_counts(segment.sequence.len() as u32, binding_rule)
+        } else {
+            binding_rule.booklet_sheet_counts.clone()
+        };
+        push_segment_booklets(&mut booklets, &mut position, segment, &counts);
+    }
+    // 正文从1开始编号，单独成册的封面封底为0
+    let booklet_count = (booklets.len() - body_first) as u16;
+    for (i, booklet) in booklets.iter_mut().enumerate() {
+        booklet.booklet_num = (i + 1 - body_first) as u16;
+        booklet.booklet_count = booklet_count;
+    }
+    booklets
+}
+
+/// 加入源文件要求的强制分册，如拼接源文件时单独成册的部分
+///
+/// # 参数
+/// * `binding_rule` - 装订规则
+/// * `breaks` - 源文件要求新起一册的页面索引，见 `PageSource::get_booklet_breaks`
+pub fn with_source_breaks<'a>(
+    binding_rule: &'a BindingRule,
+    breaks: &[u16],
+) -> Cow<'a, BindingRule> {
+    let offset = binding_rule.page_index(1);
+    let breaks: Vec<u16> = breaks
+        .iter()
+        .map(|page_idx| page_idx.saturating_sub(offset) + 1)
+        .filter(|page| !binding_rule.booklet_breaks.contains(page))
+        .collect();
+    if breaks.is_empty() {
+        return Cow::Borrowed(binding_rule);
+    }
+    let mut binding_rule = binding_rule.clone();
+    binding_rule.booklet_breaks.extend(breaks);
+    binding_rule.booklet_breaks.sort_unstable();
+    Cow::Owned(binding_rule)
+}
+
+/// 按装订规则生成全部册子，输出目录不存在时自动创建
+///
+/// 开启校验时先检查排版结果，有问题时返回 `BookletError::Verification`，不生成任何文件。
+/// 完成后在输出目录写入任务清单 `{源文件名}_manifest.json`，全部册子都被跳过时不写
+///
+/// # 参数
+/// * `src_pdf` - 源页面
+/// * `binding_rule` - 装订规则
+/// * `listener` - 进度监听器
+/// * `cancel` - 取消令牌，取消后正在生成的一册不会写入文件
+/// * `save_manifest` - 是否写入任务清单，按清单重新生成时为 `false`，不覆盖原来的记录
+///
+/// # 返回
+/// 返回每一册的信息
+pub fn create_booklet(
+    src_pdf: &dyn PageSource,
+    binding_rule: &BindingRule,
+    listener: &dyn ProgressListener,
+    cancel: &CancelToken,
+    save_manifest: bool,
+) -> Result<Vec<BookletSummary>, BookletError> {
+    binding_rule.margins.check()?;
+    binding_rule.stamps.check()?;
+    let binding_rule = resolve_auto_crop(src_pdf, binding_rule)?;
+    let binding_rule = &*with_source_breaks(&binding_rule, &src_pdf.get_booklet_breaks());
+    if binding_rule.render_mode == RenderMode::Vector {
+        pdf_vector::check_rule(binding_rule)?;
+    }
+    let page_count = src_pdf.get_page_count();
+    if page_count == 0 {
+        return Err(BookletError::Source("源文件没有页面".to_string()));
+    }
+    check_sheet_counts(page_count, binding_rule)?;
+    if binding_rule.verify {
+        let report = verify::verify_layout(page_count, binding_rule);
+        if !report.is_ok() {
+            return Err(BookletError::Verification(report));
         }
-        booklet_idx += 1;
-        pdf_creator::create_booklet(
+        log::info!("排版校验通过");
+    }
+    let booklets = plan_booklets(page_count, binding_rule);
+    // 先生成全部文件名，模板有误时不创建任何文件
+    let file_names = booklet_file_names(page_count, &booklets, binding_rule)
+        .map_err(BookletError::InvalidRule)?;
+    let page_colors = detect_page_colors(src_pdf, binding_rule)?;
+    if let Some(page_colors) = &page_colors {
+        log_color_report(&booklets, page_colors, binding_rule);
+    }
+    let stamper = match binding_rule.render_mode {
+        RenderMode::Raster => Stamper::new(src_pdf, binding_rule)?,
+        RenderMode::Vector => None,
+    };
+    std::fs::create_dir_all(&binding_rule.output_dir)?;
+    let total_pages = booklets
+        .iter()
+        .flat_map(|booklet| pdf_creator::calc_sheet_sides(booklet, binding_rule))
+        .flat_map(|side| [side.low, side.high])
+        .filter(|&page_idx| page_idx < page_count)
+        .count();
+    let tracker = ProgressTracker::new(listener, cancel, booklets.len() as u16, total_pages);
+    let mut summaries = Vec::new();
+    for (booklet, file_name) in booklets.iter().zip(file_names) {
+        summaries.push(pdf_creator::create_booklet(
+            src_pdf,
+            binding_rule,
+            booklet,
+            &binding_rule.output_dir.join(file_name),
+            page_colors.as_deref(),
+            stamper.as_ref(),
+            &tracker,
+        )?);
+    }
+    log_size_report(&summaries, binding_rule);
+    if binding_rule.sheet_split.is_enabled() {
+        write_sheet_split(
             src_pdf,
+            &booklets,
+            &summaries,
+            page_colors.as_deref(),
             binding_rule,
-            booklet_idx,
-            is_last_booklet,
-            booklet_start_page,
-            booklet_end_page,
+        )?;
+    }
+    if !save_manifest {
+        return Ok(summaries);
+    }
+    let manifest_path = manifest::manifest_path(binding_rule);
+    if summaries.iter().all(|summary| summary.skipped) {
+        log::warn!("没有生成任何册子，不更新任务清单");
+        return Ok(summaries);
+    }
+    Manifest::new(
+        page_count,
+        binding_rule,
+        &booklets,
+        &summaries,
+        page_colors.as_deref(),
+    )?
+    .save(&manifest_path)?;
+    log::info!(path:% = manifest_path.display(); "任务清单: {}", manifest_path.display());
+    Ok(summaries)
+}
+
+/// 自动裁剪时检测出奇偶页的裁剪框，替换为手动裁剪，其他情况原样返回
+///
+/// # 参数
+/// * `src_pdf` - 源页面，自动裁剪时需要能够渲染
+/// * `binding_rule` - 装订规则
+pub fn resolve_auto_crop<'a>(
+    src_pdf: &dyn PageSource,
+    binding_rule: &'a BindingRule,
+) -> Result<Cow<'a, BindingRule>, BookletError> {
+    if let CropMode::Auto { threshold, padding } = binding_rule.crop {
+        let (start, end) = binding_rule.body_page_range(src_pdf.get_page_count());
+        Ok(Cow::Owned(BindingRule {
+            crop: crop::detect_crop_mode(src_pdf, start, end, threshold, padding)?,
+            ..binding_rule.clone()
+        }))
+    } else {
+        Ok(Cow::Borrowed(binding_rule))
+    }
+}
+
+/// 开启彩色页面检测或按彩色分组时检测每页是否是彩色
+///
+/// 矢量排版不能渲染页面时给出警告并跳过检测
+///
+/// # 返回
+/// 没有开启检测时返回 `None`
+fn detect_page_colors(
+    src_pdf: &dyn PageSource,
+    binding_rule: &BindingRule,
+) -> Result<Option<Vec<bool>>, BookletError> {
+    if !binding_rule.color_detection.enabled
+        && binding_rule.sheet_split.group_by != SheetGrouping::Color
+    {
+        return Ok(None);
+    }
+    match color::classify_pages(src_pdf, binding_rule) {
+        Ok(page_colors) => Ok(Some(page_colors)),
+        Err(e) if binding_rule.render_mode == RenderMode::Vector => {
+            log::warn!("无法检测彩色页面: {}", e);
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// 输出各册含有彩色页面的纸张，这些纸张需要用彩色打印机打印
+fn log_color_report(booklets: &[BookletRange], page_colors: &[bool], binding_rule: &BindingRule) {
+    let mut color_count = 0;
+    let mut sheet_count = 0;
+    for booklet in booklets {
+        let sides = pdf_creator::calc_sheet_sides(booklet, binding_rule);
+        let sheets = color::color_sheets(&sides, page_colors);
+        sheet_count += sides.len().div_ceil(2);
+        color_count += sheets.len();
+        if sheets.is_empty() {
+            continue;
+        }
+        let list: Vec<String> = sheets.iter().map(|sheet| sheet.to_string()).collect();
+        log::info!(
+            booklet = booklet.booklet_num,
+            color_sheets = sheets.len();
+            "  第{:>3}册 彩色纸张: 第{}张",
+            booklet.booklet_num,
+            list.join("、")
         );
-        page_idx = booklet_end_page;
+    }
+    log::info!(
+        color_sheets = color_count,
+        sheets = sheet_count;
+        "含有彩色页面的纸张{}张，黑白纸张{}张",
+        color_count,
+        sheet_count - color_count
+    );
+}
+
+/// 按纸张属性分组，写出插回表，按需为每组生成单独的PDF文件
+///
+/// # 参数
+/// * `src_pdf` - 源页面
+/// * `booklets` - 全部册子
+/// * `summaries` - 各册的生成结果
+/// * `page_colors` - 每页是否是彩色，按彩色分组但无法检测时不分组
+/// * `binding_rule` - 装订规则
+fn write_sheet_split(
+    src_pdf: &dyn PageSource,
+    booklets: &[BookletRange],
+    summaries: &[BookletSummary],
+    page_colors: Option<&[bool]>,
+    binding_rule: &BindingRule,
+) -> Result<(), BookletError> {
+    let page_marks = match binding_rule.sheet_split.group_by {
+        SheetGrouping::None => return Ok(()),
+        SheetGrouping::Color => match page_colors {
+            Some(page_colors) => page_colors.to_vec(),
+            None => {
+                log::warn!("没有检测出彩色页面，不按彩色分组");
+                return Ok(());
+            }
+        },
+        SheetGrouping::Images => sheet_split::classify_images(src_pdf),
+    };
+    let entries = sheet_split::group_sheets(booklets, binding_rule, &page_marks);
+    let group_names = binding_rule.sheet_split.group_by.group_names();
+    for (group, name) in group_names.iter().enumerate() {
+        let count = entries.iter().filter(|entry| entry.group == group).count();
+        log::info!(group = *name, sheets = count; "{}纸张{}张", name, count);
+    }
+    // 先写分组文件，插回表中记录按覆盖策略实际写入的文件名
+    let group_files = if binding_rule.sheet_split.separate_files {
+        let booklet_paths: Vec<(u16, &Path)> = summaries
+            .iter()
+            .filter(|summary| !summary.skipped)
+            .map(|summary| (summary.booklet_num, summary.path.as_path()))
+            .collect();
+        sheet_split::write_group_files(src_pdf, &entries, &booklet_paths, binding_rule)?
+    } else {
+        Vec::new()
+    };
+    if let Some(list_path) = sheet_split::write_sheet_list(&entries, &group_files, binding_rule)? {
+        log::info!(path:% = list_path.display(); "插回表: {}", list_path.display());
+    }
+    for file in group_files.iter().filter(|file| !file.skipped) {
+        log::info!(
+            path:% = file.path.display(), sheets = file.sheets;
+            "  {}张  {}",
+            file.sheets,
+            file.path.display()
+        );
+    }
+    Ok(())
+}
+
+/// 按文件名模板生成每一册的文件名
+///
+/// # 返回
+/// 模板有误或文件名重复时返回错误说明
+pub fn booklet_file_names(
+    page_count: u16,
+    booklets: &[BookletRange],
+    binding_rule: &BindingRule,
+) -> Result<Vec<String>, String> {
+    let name = binding_rule
+        .input_path
+        .file_prefix()
+        .expect("没有文件名")
+        .to_string_lossy();
+    let date = chrono::Local::now().date_naive();
+    let mut file_names: Vec<String> = Vec::with_capacity(booklets.len());
+    for booklet in booklets {
+        let sides = pdf_creator::calc_sheet_sides(booklet, binding_rule);
+        let (first_page, last_page) =
+            pdf_creator::source_page_range(&sides, page_count).unwrap_or((0, 0));
+        let file_name = output::render_file_name(
+            &binding_rule.file_name_template,
+            &FileNameFields {
+                name: &name,
+                booklet_num: booklet.booklet_num,
+                booklet_count: booklet.booklet_count,
+                first_page,
+                last_page,
+                sheet_count: sides.len().div_ceil(2),
+                date,
+            },
+        )?;
+        if file_names.contains(&file_name) {
+            return Err(format!(
+                "文件名模板 \"{}\" 生成了重复的文件名 {}，请加入 {{num}}",
+                binding_rule.file_name_template, file_name
+            ));
+        }
+        file_names.push(file_name);
+    }
+    Ok(file_names)
+}
+
+/// 输出各册文件大小
+fn log_size_report(summaries: &[BookletSummary], binding_rule: &BindingRule) {
+    match binding_rule.render_mode {
+        RenderMode::Raster => {
+            let encoding = binding_rule.image_encoding;
+            log::info!(encoding:% = encoding; "图像编码: {}", encoding);
+        }
+        RenderMode::Vector => log::info!("矢量排版"),
+    }
+    for summary in summaries {
+        if summary.skipped {
+            log::info!(
+                booklet = summary.booklet_num,
+                path:% = summary.path.display();
+                "  第{:>3}册 已跳过  {}",
+                summary.booklet_num,
+                summary.path.display()
+            );
+            continue;
+        }
+        log::info!(
+            booklet = summary.booklet_num,
+            sheet_pages = summary.sheet_pages,
+            file_size = summary.file_size,
+            path:% = summary.path.display();
+            "  第{:>3}册 {:>4}页 {:>10}  {}",
+            summary.booklet_num,
+            summary.sheet_pages,
+            pdf_creator::format_file_size(summary.file_size),
+            summary.path.display()
+        );
+    }
+    let total: u64 = summaries.iter().map(|s| s.file_size).sum();
+    log::info!(file_size = total; "  合计 {}", pdf_creator::format_file_size(total));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule_with_counts(counts: &[u32]) -> BindingRule {
+        BindingRule {
+            booklet_sheet_counts: counts.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    fn is_invalid_rule(result: Result<(), BookletError>) -> bool {
+        matches!(result, Err(BookletError::InvalidRule(_)))
+    }
+
+    #[test]
+    fn sheet_counts_that_fit_are_accepted() {
+        // 40页正好10张纸
+        assert!(check_sheet_counts(40, &rule_with_counts(&[5, 5])).is_ok());
+        // 最后一册有空白页
+        assert!(check_sheet_counts(40, &rule_with_counts(&[6, 5])).is_ok());
+        assert!(check_sheet_counts(1, &rule_with_counts(&[1])).is_ok());
+        // 不指定每册纸张数时只检查范围
+        assert!(check_sheet_counts(40, &BindingRule::default()).is_ok());
+    }
+
+    #[test]
+    fn too_few_sheets_are_rejected() {
+        assert!(is_invalid_rule(check_sheet_counts(
+            40,
+            &rule_with_counts(&[4, 4])
+        )));
+    }
+
+    #[test]
+    fn empty_last_booklet_is_rejected() {
+        // 前一册已经能排下全部页面，最后一册全是空白页
+        assert!(is_invalid_rule(check_sheet_counts(
+            40,
+            &rule_with_counts(&[10, 1])
+        )));
+    }
+
+    #[test]
+    fn zero_sheets_are_rejected() {
+        assert!(is_invalid_rule(check_sheet_counts(
+            40,
+            &rule_with_counts(&[5, 0, 5])
+        )));
+    }
+
+    #[test]
+    fn sheet_counts_with_breaks_are_rejected() {
+        let rule = BindingRule {
+            booklet_breaks: vec![20],
+            ..rule_with_counts(&[5, 5])
+        };
+        assert!(is_invalid_rule(check_sheet_counts(40, &rule)));
+    }
+
+    #[test]
+    fn invalid_sheet_range_is_rejected() {
+        let rule = BindingRule {
+            sheets_per_booklet: SheetRange { min: 6, max: 4 },
+            ..Default::default()
+        };
+        assert!(is_invalid_rule(check_sheet_counts(40, &rule)));
+    }
+
+    #[test]
+    fn auto_distribution_applies_min_sheets() {
+        let rule = |min| BindingRule {
+            sheets_per_booklet: SheetRange { min, max: 4 },
+            ..Default::default()
+        };
+        let total_sheets = |page_count, rule: &BindingRule| -> usize {
+            plan_booklets(page_count, rule)
+                .iter()
+                .map(|booklet| booklet.pages.len() / 4)
+                .sum()
+        };
+        // 10页排成一册3张纸，最少4张时补到4张
+        assert_eq!(total_sheets(10, &rule(1)), 3);
+        assert_eq!(total_sheets(10, &rule(4)), 4);
+        // 40页正好10张纸，自动分配的各册都不少于最少张数，不需要补纸
+        assert_eq!(total_sheets(40, &rule(4)), 10);
+    }
+
+    #[test]
+    fn sheet_counts_include_cover_pages() {
+        // 封面和封底背面各加一页空白页，超过40页，10张纸不够
+        let rule = BindingRule {
+            cover: CoverSpec {
+                front_pages: 1,
+                back_pages: 1,
+                ..Default::default()
+            },
+            ..rule_with_counts(&[5, 5])
+        };
+        assert!(is_invalid_rule(check_sheet_counts(40, &rule)));
+        let rule = BindingRule {
+            booklet_sheet_counts: vec![5, 6],
+            ..rule
+        };
+        assert!(check_sheet_counts(40, &rule).is_ok());
+    }
+
+    #[test]
+    fn breaks_count_source_pages() {
+        let rule = BindingRule {
+            booklet_breaks: vec![5],
+            cover: CoverSpec {
+                front_pages: 2,
+                back_pages: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        // 封面在源文件中时与正文一起编号
+        assert_eq!(rule.numbered_page_range(20), (0, 20));
+        assert_eq!(rule.split_at_breaks(2, 19), vec![(2, 4), (4, 19)]);
+        // 封面文件的2页排在前面，源文件的第5页在拼接后的第7页
+        let rule = BindingRule {
+            cover: CoverSpec {
+                file: Some(std::path::PathBuf::from("cover.pdf")),
+                ..rule.cover.clone()
+            },
+            ..rule
+        };
+        assert_eq!(rule.numbered_page_range(20), (2, 19));
+        assert_eq!(rule.split_at_breaks(2, 19), vec![(2, 6), (6, 19)]);
+        // 拼接源文件要求的断点是拼接后的页面索引，换算为同样的源页码
+        let rule = with_source_breaks(&rule, &[12]);
+        assert_eq!(rule.booklet_breaks, vec![5, 11]);
+        assert_eq!(rule.split_at_breaks(2, 19), vec![(2, 6), (6, 12), (12, 19)]);
+    }
+
+    #[test]
+    fn margins_check() {
+        assert!(Margins::default().check().is_ok());
+        let margins = |outer, inner, head, foot| Margins {
+            outer,
+            inner,
+            head,
+            foot,
+            ..Margins::default()
+        };
+        assert!(margins(0.0, 0.0, 0.0, 0.0).check().is_ok());
+        assert!(is_invalid_rule(margins(-1.0, 3.0, 3.0, 3.0).check()));
+        assert!(is_invalid_rule(margins(1.0, 3.0, 3.0, f64::NAN).check()));
+        // 单页宽148.5mm，高210mm
+        assert!(margins(70.0, 78.0, 3.0, 3.0).check().is_ok());
+        assert!(is_invalid_rule(margins(70.0, 80.0, 3.0, 3.0).check()));
+        assert!(margins(1.0, 3.0, 100.0, 109.0).check().is_ok());
+        assert!(is_invalid_rule(margins(1.0, 3.0, 110.0, 100.0).check()));
     }
 }
@@ -0,0 +1,237 @@
+use serde::{Deserialize, Serialize};
+
+use crate::booklet::BindingRule;
+use crate::crop::{self, luminance};
+use crate::error::BookletError;
+use crate::page_source::PageSource;
+use crate::pdf_creator::SheetSide;
+
+/// 彩色页面检测
+///
+/// 彩色页面单独打印时，按检测结果把黑白页面渲染为灰度图像，并报告含有彩色页面的纸张
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorDetection {
+    /// 检测每页是否是彩色
+    pub enabled: bool,
+    /// 像素的RGB最大值与最小值之差超过这个值时算作彩色像素，用来忽略扫描件的轻微偏色
+    pub chroma_threshold: u8,
+    /// 彩色像素占页面的比例(%)超过这个值时为彩色页面
+    pub min_color_percent: f64,
+}
+
+impl Default for ColorDetection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            chroma_threshold: 32,
+            min_color_percent: 0.05,
+        }
+    }
+}
+
+impl ColorDetection {
+    /// 判断RGBA图像是否是彩色的
+    pub fn is_color_image(&self, rgba: &[u8]) -> bool {
+        let pixels = rgba.len() / 4;
+        let color_pixels = rgba
+            .chunks_exact(4)
+            .filter(|px| {
+                let max = px[0].max(px[1]).max(px[2]);
+                let min = px[0].min(px[1]).min(px[2]);
+                max - min > self.chroma_threshold
+            })
+            .count();
+        color_pixels as f64 > pixels as f64 * self.min_color_percent / 100.0
+    }
+}
+
+/// 检测每一页是否是彩色页面
+///
+/// 以较低分辨率渲染每一页，按装订规则裁剪后按 `color_detection` 判断，
+/// 裁掉的页边上的彩色标记不会使页面成为彩色页面
+///
+/// # 参数
+/// * `src_pdf` - 源页面
+/// * `binding_rule` - 装订规则，自动裁剪需要已经换算为裁剪框，见 `booklet::resolve_auto_crop`
+///
+/// # 返回
+/// 返回每页是否是彩色，无法渲染页面时返回错误
+pub fn classify_pages(
+    src_pdf: &dyn PageSource,
+    binding_rule: &BindingRule,
+) -> Result<Vec<bool>, BookletError> {
+    // 分析用的渲染宽度，足以发现彩色的插图和标记
+    let preview_width = 300;
+    let page_count = src_pdf.get_page_count();
+    let mut page_colors = Vec::with_capacity(page_count as usize);
+    for page_idx in 0..page_count {
+        let (width, height, rgba) = src_pdf.get_page_preview(page_idx, preview_width)?;
+        let is_color = is_color_page(binding_rule, page_count, page_idx, width, height, rgba);
+        log::trace!(page = page_idx + 1, is_color; "第{}页{}", page_idx + 1, if is_color { "彩色" } else { "黑白" });
+        page_colors.push(is_color);
+    }
+    let color_pages: Vec<String> = page_colors
+        .iter()
+        .enumerate()
+        .filter(|(_, is_color)| **is_color)
+        .map(|(page_idx, _)| (page_idx + 1).to_string())
+        .collect();
+    log::info!(
+        color_pages = color_pages.len();
+        "彩色页面{}页{}",
+        color_pages.len(),
+        if color_pages.is_empty() {
+            String::new()
+        } else {
+            format!(": {}", color_pages.join(", "))
+        }
+    );
+    Ok(page_colors)
+}
+
+/// 按装订规则裁剪页面的预览图像后判断是否是彩色，与排版时一样只裁剪正文页面
+///
+/// # 参数
+/// * `page_count` - 源页数
+/// * `page_idx` - 页面索引（从0开始）
+/// * `width`, `height`, `rgba` - 不旋转的预览图像
+fn is_color_page(
+    binding_rule: &BindingRule,
+    page_count: u16,
+    page_idx: u16,
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+) -> bool {
+    let (body_start, body_end) = binding_rule.body_page_range(page_count);
+    let is_body = page_idx >= body_start && page_idx < body_end;
+    let rgba = match binding_rule.crop.crop_box(page_idx) {
+        Some(crop_box) if is_body => crop::crop_rgba(width, height, rgba, crop_box).2,
+        _ => rgba,
+    };
+    binding_rule.color_detection.is_color_image(&rgba)
+}
+
+/// 一册中含有彩色页面的纸张编号（从1开始）
+///
+/// # 参数
+/// * `sides` - 本册每面纸上的页面，第2n面和第2n+1面是同一张纸的正反面
+/// * `page_colors` - 每页是否是彩色
+pub fn color_sheets(sides: &[SheetSide], page_colors: &[bool]) -> Vec<usize> {
+    let is_color = |page_idx: u16| page_colors.get(page_idx as usize) == Some(&true);
+    let mut sheets: Vec<usize> = sides
+        .iter()
+        .enumerate()
+        .filter(|(_, side)| is_color(side.low) || is_color(side.high))
+        .map(|(i, _)| i / 2 + 1)
+        .collect();
+    sheets.dedup();
+    sheets
+}
+
+/// 把RGBA图像就地转为灰度，透明度不变
+pub fn to_gray(rgba: &mut [u8]) {
+    for px in rgba.chunks_exact_mut(4) {
+        let gray = luminance(px);
+        px[..3].fill(gray);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cover::{CoverPrint, CoverSpec};
+    use crate::crop::{CropBox, CropMode};
+
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+
+    /// 白色图像，`paint` 返回 `Some` 的像素换成该颜色
+    fn image(width: u32, height: u32, paint: impl Fn(u32, u32) -> Option<[u8; 4]>) -> Vec<u8> {
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .flat_map(|(x, y)| paint(x, y).unwrap_or(WHITE))
+            .collect()
+    }
+
+    #[test]
+    fn chroma_threshold() {
+        let detection = ColorDetection::default();
+        // 相差正好32为轻微偏色，超过才算彩色
+        let tinted = image(10, 10, |_, _| Some([100, 132, 100, 255]));
+        assert!(!detection.is_color_image(&tinted));
+        let colored = image(10, 10, |_, _| Some([100, 133, 100, 255]));
+        assert!(detection.is_color_image(&colored));
+        let gray = image(10, 10, |_, _| Some([0, 0, 0, 255]));
+        assert!(!detection.is_color_image(&gray));
+    }
+
+    #[test]
+    fn min_color_percent() {
+        let detection = ColorDetection {
+            min_color_percent: 1.0,
+            ..Default::default()
+        };
+        // 100x100的1%为100个像素，超过才算彩色页面
+        let with_red = |count: u32| {
+            image(100, 100, |x, y| {
+                (y * 100 + x < count).then_some([255, 0, 0, 255])
+            })
+        };
+        assert!(!detection.is_color_image(&with_red(100)));
+        assert!(detection.is_color_image(&with_red(101)));
+    }
+
+    #[test]
+    fn cropped_margins_are_ignored() {
+        // 正文在中间，左上角的页边上有红色标记
+        let page = image(100, 100, |x, y| {
+            if x < 10 && y < 10 {
+                Some([255, 0, 0, 255])
+            } else if (30..70).contains(&x) && (30..70).contains(&y) {
+                Some([0, 0, 0, 255])
+            } else {
+                None
+            }
+        });
+        let content = CropBox {
+            left: 0.2,
+            top: 0.2,
+            right: 0.8,
+            bottom: 0.8,
+        };
+        let mut binding_rule = BindingRule {
+            color_detection: ColorDetection {
+                enabled: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(is_color_page(&binding_rule, 3, 1, 100, 100, page.clone()));
+        binding_rule.crop = CropMode::Manual {
+            odd: content,
+            even: content,
+        };
+        assert!(!is_color_page(&binding_rule, 3, 1, 100, 100, page.clone()));
+        // 封面不裁剪，仍是彩色
+        binding_rule.cover = CoverSpec::single(CoverPrint::WithBody);
+        assert!(is_color_page(&binding_rule, 3, 0, 100, 100, page.clone()));
+        assert!(!is_color_page(&binding_rule, 3, 1, 100, 100, page));
+    }
+
+    #[test]
+    fn color_sheets_of_booklet() {
+        let side = |low, high, is_back| SheetSide { low, high, is_back };
+        let sides = [
+            side(0, 7, false),
+            side(1, 6, true),
+            side(2, 5, false),
+            side(3, u16::MAX, true),
+        ];
+        let mut page_colors = vec![false; 8];
+        page_colors[6] = true;
+        page_colors[5] = true;
+        assert_eq!(color_sheets(&sides, &page_colors), vec![1, 2]);
+        assert!(color_sheets(&sides, &[false; 8]).is_empty());
+    }
+}
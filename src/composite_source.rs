@@ -0,0 +1,314 @@
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::booklet::BindingRule;
+use crate::error::BookletError;
+use crate::page_source::PageSource;
+
+/// 拼接成一本书的一个源文件
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SourcePart {
+    /// PDF文件、图像文件或图像文件夹
+    pub path: PathBuf,
+    /// 使用的页码范围（从1开始，包含两端），不指定时使用全部页面
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pages: Option<(u16, u16)>,
+    /// 从这一部分开始新的一册，前一册不足的页面用空白页填满
+    #[serde(default)]
+    pub new_booklet: bool,
+}
+
+impl SourcePart {
+    /// 解析命令行中的源文件 `文件[:起始页-结束页]`
+    ///
+    /// 冒号后不是页码范围时整体作为文件路径（如 Windows 的盘符）
+    pub fn parse(arg: &Path) -> Self {
+        let text = arg.to_string_lossy();
+        let range = text.rsplit_once(':').and_then(|(path, range)| {
+            let (first, last) = range.split_once('-')?;
+            Some((path, (first.parse().ok()?, last.parse().ok()?)))
+        });
+        match range {
+            Some((path, pages)) if !path.is_empty() => Self {
+                path: PathBuf::from(path),
+                pages: Some(pages),
+                new_booklet: false,
+            },
+            _ => Self {
+                path: arg.to_path_buf(),
+                pages: None,
+                new_booklet: false,
+            },
+        }
+    }
+}
+
+/// 拼接后页面序列中的一部分
+struct Part<'a> {
+    /// 封面封底来自同一个文件，两部分共用
+    source: Rc<dyn PageSource + 'a>,
+    /// 使用的第一页在源文件中的索引
+    first: u16,
+    /// 在拼接后的页面序列中的开始索引
+    start: u16,
+    /// 页数
+    count: u16,
+    /// 从这一部分开始新的一册
+    new_booklet: bool,
+    /// 单独文件中的封面或封底
+    is_cover: bool,
+}
+
+/// 多个源文件按顺序拼接成的页面序列，排版时与一个源文件相同
+///
+/// 文档信息取第一个正文部分的。每部分可以是不同类型的源文件，
+/// 全部来自PDF文件时可以按矢量排版。
+pub struct CompositeSource<'a> {
+    parts: Vec<Part<'a>>,
+    page_count: u16,
+}
+
+impl<'a> CompositeSource<'a> {
+    /// 依次打开各部分
+    ///
+    /// # 参数
+    /// * `parts` - 各部分的源文件和页码范围
+    /// * `open` - 打开一个源文件
+    ///
+    /// # 返回
+    /// 页码范围超出源文件页数或总页数太多时返回错误
+    pub fn open(
+        parts: &[SourcePart],
+        open: impl Fn(&Path) -> Result<Box<dyn PageSource + 'a>, BookletError>,
+    ) -> Result<Self, BookletError> {
+        let mut opened = Vec::with_capacity(parts.len());
+        let mut page_count = 0u16;
+        for part in parts {
+            let source = open(&part.path)?;
+            let src_count = source.get_page_count();
+            let (first, last) = part.pages.unwrap_or((1, src_count));
+            if first == 0 || first > last || last > src_count {
+                return Err(BookletError::InvalidRule(format!(
+                    "{} 的页码范围 {}-{} 无效，共{}页",
+                    part.path.display(),
+                    first,
+                    last,
+                    src_count
+                )));
+            }
+            let count = last - first + 1;
+            log::info!(
+                path:% = part.path.display(), first = first, last = last;
+                "第{}部分: {} 第{}-{}页",
+                opened.len() + 1,
+                part.path.display(),
+                first,
+                last
+            );
+            opened.push(Part {
+                source: Rc::from(source),
+                first: first - 1,
+                start: page_count,
+                count,
+                new_booklet: part.new_booklet,
+                is_cover: false,
+            });
+            page_count = page_count
+                .checked_add(count)
+                .ok_or_else(|| BookletError::Source("拼接后的页数太多".to_string()))?;
+        }
+        Ok(Self {
+            parts: opened,
+            page_count,
+        })
+    }
+
+    /// 在开头加入封面文件的前 `front` 页，在末尾加入它的后 `back` 页
+    ///
+    /// # 参数
+    /// * `path` - 封面文件路径，用于日志和错误信息
+    /// * `source` - 已打开的封面文件
+    /// * `front`, `back` - 封面和封底页数
+    ///
+    /// # 返回
+    /// 封面文件页数不够或总页数太多时返回错误
+    fn add_cover(
+        &mut self,
+        path: &Path,
+        source: Box<dyn PageSource + 'a>,
+        front: u16,
+        back: u16,
+    ) -> Result<(), BookletError> {
+        let src_count = source.get_page_count();
+        if front as u32 + back as u32 > src_count as u32 {
+            return Err(BookletError::InvalidRule(format!(
+                "封面文件 {} 只有{}页，需要封面{}页和封底{}页",
+                path.display(),
+                src_count,
+                front,
+                back
+            )));
+        }
+        log::info!(
+            path:% = path.display(), front = front, back = back;
+            "封面文件: {}，封面{}页，封底{}页",
+            path.display(),
+            front,
+            back
+        );
+        let source: Rc<dyn PageSource + 'a> = Rc::from(source);
+        let cover_part = |first: u16, count: u16| Part {
+            source: Rc::clone(&source),
+            first,
+            start: 0,
+            count,
+            new_booklet: false,
+            is_cover: true,
+        };
+        self.parts.insert(0, cover_part(0, front));
+        self.parts.push(cover_part(src_count - back, back));
+        self.parts.retain(|part| part.count > 0);
+        let mut page_count = 0u16;
+        for part in &mut self.parts {
+            part.start = page_count;
+            page_count = page_count
+                .checked_add(part.count)
+                .ok_or_else(|| BookletError::Source("拼接后的页数太多".to_string()))?;
+        }
+        self.page_count = page_count;
+        Ok(())
+    }
+
+    /// 页面所在的部分和在该部分源文件中的页面索引
+    fn locate(&self, page_idx: u16) -> Option<(&Part<'a>, u16)> {
+        let part = self
+            .parts
+            .iter()
+            .find(|part| page_idx >= part.start && page_idx < part.start + part.count)?;
+        Some((part, part.first + page_idx - part.start))
+    }
+
+    fn locate_or_err(&self, page_idx: u16) -> Result<(&Part<'a>, u16), BookletError> {
+        self.locate(page_idx).ok_or_else(|| {
+            BookletError::Source(format!(
+                "第{}页超出拼接后的页数{}",
+                page_idx + 1,
+                self.page_count
+            ))
+        })
+    }
+}
+
+impl PageSource for CompositeSource<'_> {
+    fn get_page_count(&self) -> u16 {
+        self.page_count
+    }
+
+    fn get_info(&self, key: &str) -> Option<String> {
+        self.parts
+            .iter()
+            .find(|part| !part.is_cover)?
+            .source
+            .get_info(key)
+    }
+
+    fn get_page_image(
+        &self,
+        page_idx: u16,
+        reverse_image: bool,
+    ) -> Result<(u32, u32, Vec<u8>), BookletError> {
+        let (part, src_idx) = self.locate_or_err(page_idx)?;
+        part.source.get_page_image(src_idx, reverse_image)
+    }
+
+    fn get_page_preview(
+        &self,
+        page_idx: u16,
+        target_width: i32,
+    ) -> Result<(u32, u32, Vec<u8>), BookletError> {
+        let (part, src_idx) = self.locate_or_err(page_idx)?;
+        part.source.get_page_preview(src_idx, target_width)
+    }
+
+    fn get_page_size(&self, page_idx: u16) -> Option<(f64, f64)> {
+        let (part, src_idx) = self.locate(page_idx)?;
+        part.source.get_page_size(src_idx)
+    }
+
+    fn get_pdf_page(&self, page_idx: u16) -> Option<(&Path, u16)> {
+        let (part, src_idx) = self.locate(page_idx)?;
+        part.source.get_pdf_page(src_idx)
+    }
+
+    fn get_chapters(&self) -> Vec<(u16, String)> {
+        self.parts
+            .iter()
+            .filter(|part| !part.is_cover)
+            .flat_map(|part| {
+                part.source
+                    .get_chapters()
+                    .into_iter()
+                    .filter(|(page_idx, _)| {
+                        *page_idx >= part.first && *page_idx < part.first + part.count
+                    })
+                    .map(|(page_idx, title)| (part.start + page_idx - part.first, title))
+            })
+            .collect()
+    }
+
+    fn has_images(&self, page_idx: u16) -> Option<bool> {
+        let (part, src_idx) = self.locate(page_idx)?;
+        part.source.has_images(src_idx)
+    }
+
+    fn get_booklet_breaks(&self) -> Vec<u16> {
+        self.parts
+            .iter()
+            .filter(|part| part.new_booklet && part.start > 0)
+            .map(|part| part.start)
+            .collect()
+    }
+}
+
+/// 按装订规则打开源文件：`sources` 为空时打开 `input_path`，否则按顺序拼接各部分；
+/// 封面封底在单独的文件中时拼接在开头和末尾
+///
+/// # 参数
+/// * `binding_rule` - 装订规则
+/// * `open` - 打开一个源文件
+pub fn open_source<'a>(
+    binding_rule: &BindingRule,
+    open: impl Fn(&Path) -> Result<Box<dyn PageSource + 'a>, BookletError>,
+) -> Result<Box<dyn PageSource + 'a>, BookletError> {
+    let cover = &binding_rule.cover;
+    let Some(cover_path) = &cover.file else {
+        return if binding_rule.sources.is_empty() {
+            open(&binding_rule.input_path)
+        } else {
+            Ok(Box::new(CompositeSource::open(
+                &binding_rule.sources,
+                open,
+            )?))
+        };
+    };
+    let whole_input = [SourcePart {
+        path: binding_rule.input_path.clone(),
+        pages: None,
+        new_booklet: false,
+    }];
+    let parts = if binding_rule.sources.is_empty() {
+        &whole_input[..]
+    } else {
+        &binding_rule.sources[..]
+    };
+    let mut composite = CompositeSource::open(parts, &open)?;
+    composite.add_cover(
+        cover_path,
+        open(cover_path)?,
+        cover.front_pages,
+        cover.back_pages,
+    )?;
+    Ok(Box::new(composite))
+}
@@ -0,0 +1,187 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use toml::{Table, Value};
+
+use crate::booklet::BindingRule;
+use crate::cover::CoverSpec;
+use crate::error::BookletError;
+
+/// 配置文件中选择默认预设的键
+const PRESET_KEY: &str = "preset";
+/// 预设文件中说明文字的键
+const DESCRIPTION_KEY: &str = "description";
+/// `BindingRule` 中结构体类型的项，合并时逐项合并
+const STRUCT_KEYS: &[&str] = &[
+    "margins",
+    "scan_cleanup",
+    "cover",
+    "color_detection",
+    "sheet_split",
+];
+
+/// 用户配置目录，如 Linux 下的 `~/.config/bcfbh`
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(env!("CARGO_PKG_NAME")))
+}
+
+/// 默认配置文件 `config.toml`
+pub fn default_config_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("config.toml"))
+}
+
+/// 预设目录，每个 `.toml` 文件是一个预设，文件名为预设名称
+pub fn presets_dir() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("presets"))
+}
+
+/// 预设
+pub struct Preset {
+    /// 预设名称
+    pub name: String,
+    /// 预设文件路径
+    pub path: PathBuf,
+    /// 说明
+    pub description: Option<String>,
+}
+
+/// 列出预设目录中的全部预设，按名称排序
+pub fn list_presets() -> Result<Vec<Preset>, BookletError> {
+    let Some(dir) = presets_dir().filter(|dir| dir.is_dir()) else {
+        return Ok(Vec::new());
+    };
+    let mut presets = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "toml") {
+            continue;
+        }
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let description = read_table(&path)?
+            .get(DESCRIPTION_KEY)
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        presets.push(Preset {
+            name,
+            path,
+            description,
+        });
+    }
+    presets.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(presets)
+}
+
+/// 读取配置文件和预设，合并为装订规则
+///
+/// 优先级从低到高：内置默认值 → 配置文件 → 预设。命令行参数和对话框的值由调用方在之后覆盖。
+/// 配置文件和预设只需要写出与默认值不同的项，结构体类型的项（见 `STRUCT_KEYS`）按项合并。
+///
+/// # 参数
+/// * `config_path` - 配置文件路径，`None` 时使用默认配置文件（不存在时忽略）
+/// * `preset` - 预设名称，`None` 时使用配置文件中 `preset` 指定的预设
+///
+/// # 返回
+/// 返回装订规则和实际使用的预设名称
+pub fn load_binding_rule(
+    config_path: Option<&Path>,
+    preset: Option<&str>,
+) -> Result<(BindingRule, Option<String>), BookletError> {
+    let mut table = match config_path {
+        Some(path) => read_table(path)?,
+        None => match default_config_path().filter(|path| path.is_file()) {
+            Some(path) => read_table(&path)?,
+            None => Table::new(),
+        },
+    };
+    let default_preset = table
+        .remove(PRESET_KEY)
+        .and_then(|value| value.as_str().map(str::to_string));
+    let preset = preset.map(str::to_string).or(default_preset);
+    if let Some(name) = &preset {
+        let mut preset_table = read_table(&preset_path(name)?)?;
+        preset_table.remove(DESCRIPTION_KEY);
+        merge_table(&mut table, preset_table);
+    }
+    let known_keys = Table::try_from(BindingRule::default()).unwrap_or_default();
+    for key in table.keys().filter(|key| !known_keys.contains_key(*key)) {
+        log::warn!(key = key.as_str(); "忽略未知的配置项 {}", key);
+    }
+    let rule: BindingRule = Value::Table(table)
+        .try_into()
+        .map_err(|e| BookletError::InvalidRule(format!("配置有误: {}", e)))?;
+    rule.margins.check()?;
+    rule.stamps.check()?;
+    Ok((rule, preset))
+}
+
+/// 把装订规则转换为TOML文本，用于显示预设，省略未设置的源文件和输出目录
+pub fn to_toml(binding_rule: &BindingRule) -> String {
+    let mut table = match Table::try_from(binding_rule) {
+        Ok(table) => table,
+        Err(e) => return format!("# 无法显示: {}", e),
+    };
+    table.retain(|_, value| value.as_str().is_none_or(|s| !s.is_empty()));
+    toml::to_string_pretty(&table).unwrap_or_else(|e| format!("# 无法显示: {}", e))
+}
+
+/// 预设文件路径，预设不存在时返回错误
+fn preset_path(name: &str) -> Result<PathBuf, BookletError> {
+    let path = presets_dir()
+        .map(|dir| dir.join(format!("{}.toml", name)))
+        .filter(|path| path.is_file());
+    path.ok_or_else(|| {
+        let names: Vec<String> = list_presets()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|preset| preset.name)
+            .collect();
+        BookletError::InvalidRule(format!(
+            "找不到预设 \"{}\"，可用的预设: {}",
+            name,
+            if names.is_empty() {
+                "无".to_string()
+            } else {
+                names.join(", ")
+            }
+        ))
+    })
+}
+
+/// 读取TOML文件，旧版本的封面设置转换为 `cover`
+fn read_table(path: &Path) -> Result<Table, BookletError> {
+    let text = fs::read_to_string(path)?;
+    let mut table = text.parse::<Table>().map_err(|e| {
+        BookletError::InvalidRule(format!("无法读取配置 {}: {}", path.display(), e))
+    })?;
+    let has_cover = table.remove("has_cover").and_then(|value| value.as_bool());
+    let keep_cover = table.remove("keep_cover").and_then(|value| value.as_bool());
+    if let Some(has_cover) = has_cover {
+        let cover = CoverSpec::from_legacy(has_cover, keep_cover.unwrap_or(false));
+        if let Ok(value) = Value::try_from(cover) {
+            table.entry("cover").or_insert(value);
+        }
+    }
+    Ok(table)
+}
+
+/// 把 `overlay` 合并到 `base`
+///
+/// 结构体类型的项（`STRUCT_KEYS`）逐项合并，其他项（包括枚举类型的表，
+/// 如 `crop = { auto = { ... } }`）整体替换。
+fn merge_table(base: &mut Table, overlay: Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base_table)), Value::Table(overlay_table))
+                if STRUCT_KEYS.contains(&key.as_str()) =>
+            {
+                base_table.extend(overlay_table);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// 封面封底的打印方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum CoverPrint {
+    /// 与正文一起排版，封面在第一册开头，封底在最后一册末尾
+    #[default]
+    WithBody,
+    /// 封面封底单独排成一册（册号为0），可以用不同的纸张打印
+    Separate,
+    /// 不打印封面封底
+    Omit,
+}
+
+impl fmt::Display for CoverPrint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CoverPrint::WithBody => "与正文一起",
+            CoverPrint::Separate => "单独成册",
+            CoverPrint::Omit => "不打印",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// 封面封底的设置
+///
+/// 封面是源页面开头的 `front_pages` 页（如封面、封二），封底是末尾的 `back_pages` 页
+/// （如封三、封底），其余为正文。封面封底不裁剪，也不参与强制分册。
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CoverSpec {
+    /// 开头的封面页数，0表示没有封面
+    pub front_pages: u16,
+    /// 末尾的封底页数，0表示没有封底
+    pub back_pages: u16,
+    /// 打印方式
+    pub print: CoverPrint,
+    /// 在封面之后、封底之前各加一页空白页，让封面封底的背面（封二、封三）空白
+    pub blank_inside: bool,
+    /// 封面封底所在的单独文件：前 `front_pages` 页为封面，后 `back_pages` 页为封底，
+    /// 源文件全部为正文。为空时封面封底在源文件中
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+}
+
+impl Default for CoverSpec {
+    fn default() -> Self {
+        Self {
+            front_pages: 0,
+            back_pages: 0,
+            print: CoverPrint::default(),
+            blank_inside: true,
+            file: None,
+        }
+    }
+}
+
+impl CoverSpec {
+    /// 第一页和最后一页是封面封底，封面封底背面空白
+    ///
+    /// # 参数
+    /// * `print` - 打印方式
+    pub fn single(print: CoverPrint) -> Self {
+        Self {
+            front_pages: 1,
+            back_pages: 1,
+            print,
+            ..Self::default()
+        }
+    }
+
+    /// 由旧版本的 `has_cover`（第一页和最后一页是封面封底）和
+    /// `keep_cover`（打印封面封底）转换
+    pub fn from_legacy(has_cover: bool, keep_cover: bool) -> Self {
+        match (has_cover, keep_cover) {
+            (false, _) => Self::default(),
+            (true, true) => Self::single(CoverPrint::WithBody),
+            (true, false) => Self::single(CoverPrint::Omit),
+        }
+    }
+
+    /// 解析命令行中的封面封底页数 `封面页数,封底页数`，只写一个数时两者相同
+    pub fn parse_pages(s: &str) -> Result<(u16, u16), String> {
+        let parse = |n: &str| {
+            n.trim()
+                .parse::<u16>()
+                .map_err(|_| format!("无效的封面页数 \"{}\"", s))
+        };
+        match s.split_once(',') {
+            Some((front, back)) => Ok((parse(front)?, parse(back)?)),
+            None => parse(s).map(|n| (n, n)),
+        }
+    }
+
+    /// 是否有封面或封底
+    pub fn is_enabled(&self) -> bool {
+        self.front_pages > 0 || self.back_pages > 0
+    }
+
+    /// 按源页数修正后的封面和封底页数，页数不够时优先保证封面
+    ///
+    /// # 参数
+    /// * `page_count` - 源页数（有单独的封面文件时为拼接后的页数）
+    pub fn page_counts(&self, page_count: u16) -> (u16, u16) {
+        let front = self.front_pages.min(page_count);
+        (front, self.back_pages.min(page_count - front))
+    }
+
+    /// 封面的排版顺序：封面页之后按需加一页空白页，空白页为 `None`
+    ///
+    /// # 参数
+    /// * `page_count` - 源页数
+    pub fn front_sequence(&self, page_count: u16) -> Vec<Option<u16>> {
+        let (front, _) = self.page_counts(page_count);
+        let mut sequence: Vec<Option<u16>> = (0..front).map(Some).collect();
+        if front > 0 && self.blank_inside {
+            sequence.push(None);
+        }
+        sequence
+    }
+
+    /// 封底的排版顺序：封底页之前按需加一页空白页，空白页为 `None`
+    ///
+    /// # 参数
+    /// * `page_count` - 源页数
+    pub fn back_sequence(&self, page_count: u16) -> Vec<Option<u16>> {
+        let (_, back) = self.page_counts(page_count);
+        let mut sequence = Vec::new();
+        if back > 0 && self.blank_inside {
+            sequence.push(None);
+        }
+        sequence.extend((page_count - back..page_count).map(Some));
+        sequence
+    }
+}
+
+impl fmt::Display for CoverSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.is_enabled() {
+            return write!(f, "无");
+        }
+        write!(
+            f,
+            "封面{}页，封底{}页，{}",
+            self.front_pages, self.back_pages, self.print
+        )?;
+        if self.blank_inside {
+            write!(f, "，背面空白")?;
+        }
+        if let Some(path) = &self.file {
+            write!(f, "，来自 {}", path.display())?;
+        }
+        Ok(())
+    }
+}
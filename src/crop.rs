@@ -0,0 +1,351 @@
+use crate::error::BookletError;
+use crate::page_source::PageSource;
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+/// 裁剪框，表示页面上保留的区域
+///
+/// 坐标为相对页面宽高的比例（0.0~1.0），以源页面左上角为原点，
+/// 与渲染时的旋转方向无关。
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CropBox {
+    pub left: f64,
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+}
+
+impl Default for CropBox {
+    fn default() -> Self {
+        Self {
+            left: 0.0,
+            top: 0.0,
+            right: 1.0,
+            bottom: 1.0,
+        }
+    }
+}
+
+impl CropBox {
+    /// 合并两个裁剪框，取能同时包含两者的最小区域
+    pub fn union(&self, other: &CropBox) -> CropBox {
+        CropBox {
+            left: self.left.min(other.left),
+            top: self.top.min(other.top),
+            right: self.right.max(other.right),
+            bottom: self.bottom.max(other.bottom),
+        }
+    }
+
+    /// 向四周扩展，结果限制在页面范围内
+    pub fn expand(&self, padding: f64) -> CropBox {
+        CropBox {
+            left: (self.left - padding).max(0.0),
+            top: (self.top - padding).max(0.0),
+            right: (self.right + padding).min(1.0),
+            bottom: (self.bottom + padding).min(1.0),
+        }
+    }
+
+    /// 转换到渲染后图像的坐标系
+    ///
+    /// # 参数
+    /// * `reverse_image` - 图像是否旋转了270°，否则为90°（与 `get_page_image` 一致）
+    pub fn rotated(&self, reverse_image: bool) -> CropBox {
+        if reverse_image {
+            // 逆时针90°: x' = y, y' = 1 - x
+            CropBox {
+                left: self.top,
+                top: 1.0 - self.right,
+                right: self.bottom,
+                bottom: 1.0 - self.left,
+            }
+        } else {
+            // 顺时针90°: x' = 1 - y, y' = x
+            CropBox {
+                left: 1.0 - self.bottom,
+                top: self.left,
+                right: 1.0 - self.top,
+                bottom: self.right,
+            }
+        }
+    }
+
+    /// 是否为整页（不需要裁剪）
+    pub fn is_full_page(&self) -> bool {
+        *self == CropBox::default()
+    }
+}
+
+/// 裁剪模式
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CropMode {
+    /// 不裁剪
+    None,
+    /// 自动检测内容区域，奇数页和偶数页分别取所有页内容区域的并集
+    Auto {
+        /// 灰度低于该值的像素视为内容
+        threshold: u8,
+        /// 内容区域四周保留的空白，相对页面宽高的比例
+        padding: f64,
+    },
+    /// 手动指定奇数页和偶数页的裁剪框（页码从1开始）
+    Manual { odd: CropBox, even: CropBox },
+}
+
+impl CropMode {
+    /// 默认的自动裁剪参数
+    pub fn auto() -> Self {
+        CropMode::Auto {
+            threshold: 200,
+            padding: 0.01,
+        }
+    }
+
+    /// 获取指定页面的裁剪框
+    ///
+    /// # 参数
+    /// * `page_idx` - 页面索引（从0开始，索引0为第1页即奇数页）
+    pub fn crop_box(&self, page_idx: u16) -> Option<&CropBox> {
+        match self {
+            CropMode::Manual { odd, even } => {
+                let crop_box = if page_idx.is_multiple_of(2) {
+                    odd
+                } else {
+                    even
+                };
+                if crop_box.is_full_page() {
+                    None
+                } else {
+                    Some(crop_box)
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// 自动检测页面内容区域，得到奇偶页的裁剪框
+///
+/// 先以较低分辨率渲染 `[start_page, end_page)` 中的每一页，检测内容区域，
+/// 然后奇数页和偶数页分别取并集，保证同侧页面裁剪一致。空白页不参与计算。
+///
+/// # 返回
+/// 返回 `CropMode::Manual`，没有检测到内容时返回 `CropMode::None`，无法渲染页面时返回错误
+pub fn detect_crop_mode(
+    src_pdf: &dyn PageSource,
+    start_page: u16,
+    end_page: u16,
+    threshold: u8,
+    padding: f64,
+) -> Result<CropMode, BookletError> {
+    // 分析用的渲染宽度，足以定位页边空白
+    let preview_width = 600;
+    let mut odd: Option<CropBox> = None;
+    let mut even: Option<CropBox> = None;
+    for page_idx in start_page..end_page {
+        let (width, height, rgba) = src_pdf.get_page_preview(page_idx, preview_width)?;
+        let Some(content_box) = detect_content_box(width, height, &rgba, threshold) else {
+            continue;
+        };
+        let side = if page_idx.is_multiple_of(2) {
+            &mut odd
+        } else {
+            &mut even
+        };
+        *side = Some(match side {
+            Some(crop_box) => crop_box.union(&content_box),
+            None => content_box,
+        });
+    }
+    if odd.is_none() && even.is_none() {
+        return Ok(CropMode::None);
+    }
+    // 只有一侧有内容时，两侧使用同一个裁剪框
+    Ok(CropMode::Manual {
+        odd: odd.or(even).unwrap().expand(padding),
+        even: even.or(odd).unwrap().expand(padding),
+    })
+}
+
+/// 检测图像中的内容区域
+///
+/// 灰度低于 `threshold` 的像素视为内容。为了忽略扫描件上的零星噪点，
+/// 一行（列）中内容像素数量不少于该行（列）长度的0.2%才算作有内容。
+///
+/// # 返回
+/// 返回内容区域对应的裁剪框，空白页返回 `None`
+pub fn detect_content_box(width: u32, height: u32, rgba: &[u8], threshold: u8) -> Option<CropBox> {
+    let (w, h) = (width as usize, height as usize);
+    let mut row_counts = vec![0u32; h];
+    let mut col_counts = vec![0u32; w];
+    for (y, row) in rgba.chunks_exact(w * 4).enumerate() {
+        for (x, px) in row.chunks_exact(4).enumerate() {
+            if luminance(px) < threshold {
+                row_counts[y] += 1;
+                col_counts[x] += 1;
+            }
+        }
+    }
+    let min_row = (w as u32 / 500).max(1);
+    let min_col = (h as u32 / 500).max(1);
+    let top = row_counts.iter().position(|&c| c >= min_row)?;
+    let bottom = row_counts.iter().rposition(|&c| c >= min_row)?;
+    let left = col_counts.iter().position(|&c| c >= min_col)?;
+    let right = col_counts.iter().rposition(|&c| c >= min_col)?;
+    Some(CropBox {
+        left: left as f64 / w as f64,
+        top: top as f64 / h as f64,
+        right: (right + 1) as f64 / w as f64,
+        bottom: (bottom + 1) as f64 / h as f64,
+    })
+}
+
+/// 按裁剪框裁剪RGBA图像
+///
+/// # 参数
+/// * `crop_box` - 图像坐标系下的裁剪框
+///
+/// # 返回
+/// 返回 (width, height, rgba_bytes) 元组
+pub fn crop_rgba(
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+    crop_box: &CropBox,
+) -> (u32, u32, Vec<u8>) {
+    let x = (crop_box.left * width as f64).floor() as u32;
+    let y = (crop_box.top * height as f64).floor() as u32;
+    let right = ((crop_box.right * width as f64).ceil() as u32).min(width);
+    let bottom = ((crop_box.bottom * height as f64).ceil() as u32).min(height);
+    if x >= right || y >= bottom {
+        return (width, height, rgba);
+    }
+    let img = RgbaImage::from_raw(width, height, rgba).expect("RGBA数据与尺寸不符");
+    let cropped = image::imageops::crop_imm(&img, x, y, right - x, bottom - y).to_image();
+    (cropped.width(), cropped.height(), cropped.into_raw())
+}
+
+/// 计算RGBA像素的灰度
+pub fn luminance(px: &[u8]) -> u8 {
+    ((px[0] as u32 * 299 + px[1] as u32 * 587 + px[2] as u32 * 114) / 1000) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 白色图像，`[left, right) x [top, bottom)` 的像素为黑色
+    fn page(width: u32, height: u32, content: Option<(u32, u32, u32, u32)>) -> Vec<u8> {
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .flat_map(|(x, y)| match content {
+                Some((left, top, right, bottom))
+                    if x >= left && x < right && y >= top && y < bottom =>
+                {
+                    [0, 0, 0, 255]
+                }
+                _ => [255, 255, 255, 255],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn blank_page_has_no_content() {
+        assert_eq!(detect_content_box(100, 50, &page(100, 50, None), 200), None);
+    }
+
+    #[test]
+    fn full_bleed_page_keeps_whole_page() {
+        let rgba = page(100, 50, Some((0, 0, 100, 50)));
+        let content_box = detect_content_box(100, 50, &rgba, 200).unwrap();
+        assert!(content_box.is_full_page());
+        assert!(content_box.expand(0.01).is_full_page());
+    }
+
+    #[test]
+    fn uneven_margins() {
+        let rgba = page(200, 100, Some((20, 5, 150, 90)));
+        let content_box = detect_content_box(200, 100, &rgba, 200).unwrap();
+        assert_eq!(
+            content_box,
+            CropBox {
+                left: 0.1,
+                top: 0.05,
+                right: 0.75,
+                bottom: 0.9,
+            }
+        );
+        // 扩展后不超出页面
+        let expanded = content_box.expand(0.08);
+        assert_eq!(expanded.top, 0.0);
+        assert!((expanded.left - 0.02).abs() < 1e-9);
+        assert!((expanded.right - 0.83).abs() < 1e-9);
+        assert_eq!(expanded.bottom, 0.98);
+    }
+
+    #[test]
+    fn sparse_noise_is_ignored() {
+        // 1000x1000时一行（列）至少2个内容像素，页边上的单个噪点不算内容
+        let mut rgba = page(1000, 1000, Some((100, 100, 900, 900)));
+        let noise = (990 * 1000 + 10) * 4;
+        rgba[noise..noise + 3].fill(0);
+        let content_box = detect_content_box(1000, 1000, &rgba, 200).unwrap();
+        assert_eq!(
+            content_box,
+            CropBox {
+                left: 0.1,
+                top: 0.1,
+                right: 0.9,
+                bottom: 0.9,
+            }
+        );
+    }
+
+    #[test]
+    fn threshold_decides_content() {
+        let gray: Vec<u8> = [180, 180, 180, 255].repeat(100);
+        assert!(detect_content_box(10, 10, &gray, 200).is_some());
+        assert!(detect_content_box(10, 10, &gray, 180).is_none());
+    }
+
+    #[test]
+    fn union_covers_both_pages() {
+        let a = CropBox {
+            left: 0.1,
+            top: 0.2,
+            right: 0.7,
+            bottom: 0.8,
+        };
+        let b = CropBox {
+            left: 0.15,
+            top: 0.1,
+            right: 0.9,
+            bottom: 0.75,
+        };
+        assert_eq!(
+            a.union(&b),
+            CropBox {
+                left: 0.1,
+                top: 0.1,
+                right: 0.9,
+                bottom: 0.8,
+            }
+        );
+    }
+
+    #[test]
+    fn crop_rgba_uses_box() {
+        let rgba = page(200, 100, None);
+        let crop_box = CropBox {
+            left: 0.1,
+            top: 0.05,
+            right: 0.75,
+            bottom: 0.9,
+        };
+        let (width, height, cropped) = crop_rgba(200, 100, rgba, &crop_box);
+        assert_eq!((width, height), (130, 85));
+        assert_eq!(cropped.len(), 130 * 85 * 4);
+    }
+}
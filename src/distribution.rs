@@ -0,0 +1,273 @@
+use std::fmt;
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// 每册纸张数的范围（包含两端）
+///
+/// 配置文件中可以写成一个数 `8`（最多8张，不限最少），或 `{ min = 6, max = 10 }`
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "SheetRangeRepr")]
+pub struct SheetRange {
+    /// 每册最少纸张数，不足时用空白页填满
+    pub min: u32,
+    /// 每册最多纸张数
+    pub max: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SheetRangeRepr {
+    Max(u32),
+    Range { min: u32, max: u32 },
+}
+
+impl From<SheetRangeRepr> for SheetRange {
+    fn from(repr: SheetRangeRepr) -> Self {
+        match repr {
+            SheetRangeRepr::Max(max) => Self::up_to(max),
+            SheetRangeRepr::Range { min, max } => Self { min, max },
+        }
+    }
+}
+
+impl SheetRange {
+    /// 最多 `max` 张，不限最少
+    pub fn up_to(max: u32) -> Self {
+        Self { min: 1, max }
+    }
+
+    /// 范围是否有效：最少1张，且不大于最多的张数
+    pub fn is_valid(&self) -> bool {
+        self.min >= 1 && self.min <= self.max
+    }
+
+    /// 修正为有效的范围，用于无效的范围也要能排版的地方
+    pub fn clamped(self) -> Self {
+        let max = self.max.max(1);
+        Self {
+            min: self.min.clamp(1, max),
+            max,
+        }
+    }
+}
+
+impl Default for SheetRange {
+    fn default() -> Self {
+        Self::up_to(10)
+    }
+}
+
+impl fmt::Display for SheetRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.min == 1 {
+            write!(f, "{}", self.max)
+        } else {
+            write!(f, "{}-{}", self.min, self.max)
+        }
+    }
+}
+
+/// 解析 `最多张数` 或 `最少张数-最多张数`
+impl FromStr for SheetRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |n: &str| {
+            n.trim()
+                .parse::<u32>()
+                .map_err(|_| format!("无效的纸张数 \"{}\"", s))
+        };
+        let range = match s.split_once('-') {
+            Some((min, max)) => Self {
+                min: parse(min)?,
+                max: parse(max)?,
+            },
+            None => Self::up_to(parse(s)?),
+        };
+        if range.is_valid() {
+            Ok(range)
+        } else {
+            Err(format!("无效的纸张数范围 \"{}\"", s))
+        }
+    }
+}
+
+/// 各册纸张数的分配方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Distribution {
+    /// 余数不多时前几册各加1张，最后一册不足3/4时各册均分；按最多张数分配，个别册子会多1张
+    #[default]
+    Auto,
+    /// 每册都是最多张数，最后一册放剩下的纸张
+    Strict,
+    /// 册数最少，各册相差不超过1张，多1张的册子均匀分布
+    Balanced,
+    /// 册数最少，各册相差不超过1张，多1张的册子在前面
+    RemainderFront,
+    /// 册数最少，各册相差不超过1张，多1张的册子在后面
+    RemainderBack,
+    /// 尽量每册都是最多张数，最后一册不足最少张数时从前面的册子匀过来，不额外加空白页
+    MinimizeBlanks,
+}
+
+impl fmt::Display for Distribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Distribution::Auto => "自动",
+            Distribution::Strict => "固定张数",
+            Distribution::Balanced => "均分",
+            Distribution::RemainderFront => "余数在前",
+            Distribution::RemainderBack => "余数在后",
+            Distribution::MinimizeBlanks => "最少空白页",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Distribution {
+    /// 按分配方式计算每册的纸张数
+    ///
+    /// 纸张数不足最少张数的册子用空白页填满
+    ///
+    /// # 参数
+    /// * `page_count` - 需要排版的页面位置数
+    /// * `range` - 每册纸张数的范围，无效时按最接近的有效范围
+    ///
+    /// # 返回
+    /// 按顺序的每册纸张数，没有页面时为空；`Auto` 按原来的方式逐册计算，返回 `None`
+    pub fn sheet_counts(self, page_count: u32, range: SheetRange) -> Option<Vec<u32>> {
+        let SheetRange { min, max } = range.clamped();
+        let sheets = page_count.div_ceil(4);
+        let booklet_count = sheets.div_ceil(max);
+        let mut counts = match self {
+            Distribution::Auto => return None,
+            _ if sheets == 0 => return Some(Vec::new()),
+            Distribution::Strict => {
+                let mut counts = vec![max; booklet_count as usize];
+                counts[booklet_count as usize - 1] = sheets - max * (booklet_count - 1);
+                counts
+            }
+            Distribution::MinimizeBlanks => {
+                let mut counts = vec![max; booklet_count as usize];
+                let last = sheets - max * (booklet_count - 1);
+                counts[booklet_count as usize - 1] = last;
+                // 从前面的册子往后匀，从后往前每册最多匀到只剩最少张数
+                let mut shortage = min.saturating_sub(last);
+                let mut moved = 0;
+                for count in counts.iter_mut().rev().skip(1) {
+                    let n = shortage.min(*count - min);
+                    *count -= n;
+                    shortage -= n;
+                    moved += n;
+                }
+                counts[booklet_count as usize - 1] = last + moved;
+                counts
+            }
+            Distribution::Balanced | Distribution::RemainderFront | Distribution::RemainderBack => {
+                let base = sheets / booklet_count;
+                let extra = sheets % booklet_count;
+                (0..booklet_count)
+                    .map(|i| {
+                        let is_extra = match self {
+                            Distribution::RemainderFront => i < extra,
+                            Distribution::RemainderBack => i >= booklet_count - extra,
+                            // 第i册之前应有的多1张的册数向下取整，变化时这一册多1张
+                            _ => (i + 1) * extra / booklet_count > i * extra / booklet_count,
+                        };
+                        base + is_extra as u32
+                    })
+                    .collect()
+            }
+        };
+        for count in &mut counts {
+            *count = (*count).max(min);
+        }
+        Some(counts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// README中的例子：23张纸，每册4-5张
+    const PAGES: u32 = 23 * 4;
+    const RANGE: SheetRange = SheetRange { min: 4, max: 5 };
+
+    fn counts(distribution: Distribution) -> Vec<u32> {
+        distribution.sheet_counts(PAGES, RANGE).unwrap()
+    }
+
+    #[test]
+    fn strict_fills_booklets_to_max() {
+        // 最后一册3张，补到最少4张
+        assert_eq!(counts(Distribution::Strict), [5, 5, 5, 5, 4]);
+    }
+
+    #[test]
+    fn balanced_spreads_extra_sheets() {
+        assert_eq!(counts(Distribution::Balanced), [4, 5, 4, 5, 5]);
+        assert_eq!(counts(Distribution::RemainderFront), [5, 5, 5, 4, 4]);
+        assert_eq!(counts(Distribution::RemainderBack), [4, 4, 5, 5, 5]);
+    }
+
+    #[test]
+    fn minimize_blanks_moves_sheets_to_last_booklet() {
+        let counts = counts(Distribution::MinimizeBlanks);
+        assert_eq!(counts, [5, 5, 5, 4, 4]);
+        // 不额外加空白页
+        assert_eq!(counts.iter().sum::<u32>(), 23);
+    }
+
+    #[test]
+    fn sheet_counts_cover_all_pages() {
+        for distribution in Distribution::value_variants() {
+            let Some(counts) = distribution.sheet_counts(PAGES + 1, RANGE) else {
+                continue;
+            };
+            assert!(counts.iter().sum::<u32>() * 4 > PAGES, "{}", distribution);
+            assert!(
+                counts
+                    .iter()
+                    .all(|count| RANGE.min <= *count && *count <= RANGE.max),
+                "{}: {:?}",
+                distribution,
+                counts
+            );
+        }
+    }
+
+    #[test]
+    fn auto_is_computed_elsewhere() {
+        assert_eq!(Distribution::Auto.sheet_counts(PAGES, RANGE), None);
+    }
+
+    #[test]
+    fn no_pages_no_booklets() {
+        assert_eq!(
+            Distribution::Strict.sheet_counts(0, RANGE),
+            Some(Vec::new())
+        );
+    }
+
+    #[test]
+    fn invalid_range_is_clamped() {
+        let range = SheetRange { min: 6, max: 4 };
+        assert_eq!(
+            Distribution::Strict.sheet_counts(40, range),
+            Some(vec![4, 4, 4])
+        );
+    }
+
+    #[test]
+    fn parse_sheet_range() {
+        assert_eq!("8".parse(), Ok(SheetRange::up_to(8)));
+        assert_eq!("6-10".parse(), Ok(SheetRange { min: 6, max: 10 }));
+        assert!("10-6".parse::<SheetRange>().is_err());
+        assert!("0".parse::<SheetRange>().is_err());
+        assert!("abc".parse::<SheetRange>().is_err());
+    }
+}
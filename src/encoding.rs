@@ -0,0 +1,458 @@
+use std::fmt;
+use std::io::Write;
+
+use crate::crop::luminance;
+use crate::error::BookletError;
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use image::ExtendedColorType;
+use image::codecs::jpeg::JpegEncoder;
+use oxidize_pdf::{ColorSpace, Image, MaskType, PdfError};
+use serde::{Deserialize, Serialize};
+
+/// 嵌入PDF的页面图像编码方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageEncoding {
+    /// Flate无损压缩，黑白页面按灰度图保存
+    #[default]
+    Flate,
+    /// JPEG有损压缩，参数为质量（1~100），黑白页面按灰度图保存
+    Jpeg(u8),
+    /// 1位黑白图像，参数为阈值，灰度低于阈值为黑色。适合纯文字页面
+    ///
+    /// 同样用Flate压缩，不是CCITT G4或JBIG2，文件比这两种编码大一些
+    #[serde(alias = "bilevel")]
+    Mono(u8),
+}
+
+impl ImageEncoding {
+    /// 解析命令行中的编码方式：`flate`、`jpeg[:质量]` 或 `mono[:阈值]`
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (name, value) = match s.split_once(':') {
+            Some((name, value)) => (name, Some(value)),
+            None => (s, None),
+        };
+        let parse_value = |default: u8, range: std::ops::RangeInclusive<u8>| match value {
+            Some(value) => value
+                .trim()
+                .parse::<u8>()
+                .ok()
+                .filter(|value| range.contains(value))
+                .ok_or_else(|| {
+                    format!(
+                        "无效的参数 \"{}\"，应为{}~{}",
+                        value,
+                        range.start(),
+                        range.end()
+                    )
+                }),
+            None => Ok(default),
+        };
+        match name.trim().to_ascii_lowercase().as_str() {
+            "flate" if value.is_none() => Ok(ImageEncoding::Flate),
+            "jpeg" | "jpg" => parse_value(85, 1..=100).map(ImageEncoding::Jpeg),
+            "mono" => parse_value(128, 0..=255).map(ImageEncoding::Mono),
+            _ => Err(format!(
+                "无效的编码方式 \"{}\"，可选 flate、jpeg[:质量]、mono[:阈值]",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ImageEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageEncoding::Flate => write!(f, "Flate"),
+            ImageEncoding::Jpeg(quality) => write!(f, "JPEG(质量{})", quality),
+            ImageEncoding::Mono(threshold) => write!(f, "1位黑白Flate(阈值{})", threshold),
+        }
+    }
+}
+
+/// 把RGBA图像编码为PDF图像
+///
+/// 渲染结果总是不透明的，透明度通道直接丢弃。Flate和1位黑白编码的图像数据在这里压缩，
+/// 生成PDF后要用 `mark_flate_images` 标记压缩方式。
+pub fn encode_image(
+    width: u32,
+    height: u32,
+    rgba: &[u8],
+    encoding: ImageEncoding,
+) -> Result<Image, BookletError> {
+    let is_gray = rgba
+        .chunks_exact(4)
+        .all(|px| px[0] == px[1] && px[1] == px[2]);
+    match encoding {
+        ImageEncoding::Flate => {
+            if is_gray {
+                flate_image(&to_gray(rgba), width, height, ColorSpace::DeviceGray, 8)
+            } else {
+                flate_image(&to_rgb(rgba), width, height, ColorSpace::DeviceRGB, 8)
+            }
+        }
+        ImageEncoding::Jpeg(quality) => {
+            let mut jpeg = Vec::new();
+            let mut encoder = JpegEncoder::new_with_quality(&mut jpeg, quality.clamp(1, 100));
+            if is_gray {
+                encoder.encode(&to_gray(rgba), width, height, ExtendedColorType::L8)
+            } else {
+                encoder.encode(&to_rgb(rgba), width, height, ExtendedColorType::Rgb8)
+            }
+            .map_err(|e| PdfError::InvalidImage(format!("JPEG编码失败: {}", e)))?;
+            Ok(Image::from_jpeg_data(jpeg)?)
+        }
+        ImageEncoding::Mono(threshold) => {
+            let bits = to_bilevel(width, rgba, threshold);
+            flate_image(&bits, width, height, ColorSpace::DeviceGray, 1)
+        }
+    }
+}
+
+/// 把带透明度的RGBA图像编码为PDF图像，透明度通道作为软遮罩保留，用于水印图像
+pub fn encode_transparent_image(
+    width: u32,
+    height: u32,
+    rgba: &[u8],
+) -> Result<Image, BookletError> {
+    let alpha: Vec<u8> = rgba.chunks_exact(4).map(|px| px[3]).collect();
+    let mask = Image::from_gray_data(alpha, width, height)?;
+    Ok(
+        Image::from_raw_data(to_rgb(rgba), width, height, ColorSpace::DeviceRGB, 8)
+            .with_mask(mask, MaskType::Soft),
+    )
+}
+
+/// 创建Flate压缩的图像
+///
+/// oxidize-pdf只压缩带遮罩的图像，不带遮罩的图像按原始数据写出且没有 `/Filter`。
+/// 这里先压缩好数据，作为原始数据交给oxidize-pdf，生成PDF后再由 `mark_flate_images`
+/// 补上 `/Filter /FlateDecode`。
+fn flate_image(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    color_space: ColorSpace,
+    bits_per_component: u8,
+) -> Result<Image, BookletError> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    let compressed = encoder.finish()?;
+    Ok(Image::from_raw_data(
+        compressed,
+        width,
+        height,
+        color_space,
+        bits_per_component,
+    ))
+}
+
+/// 给 `encode_image` 压缩过的图像补上 `/Filter /FlateDecode`
+///
+/// 只处理oxidize-pdf生成的文件：使用交叉引用表而不是交叉引用流，每个对象以
+/// `N 0 obj` 开头、按写出顺序排列。没有 `/Filter` 的图像流都视为已压缩——带遮罩的
+/// 图像（如水印）由oxidize-pdf自己压缩并写出 `/Filter`，不受影响。
+/// 修改流字典后对象位置会变化，交叉引用表按新位置重写。
+///
+/// oxidize-pdf没有给不带遮罩的图像设置压缩方式的接口，只能这样补写。这依赖它写出的
+/// 文件结构（Cargo.toml中固定了版本），结构不符时返回错误而不是生成损坏的文件；
+/// 升级oxidize-pdf后由本模块的测试检查。
+///
+/// # 参数
+/// * `pdf` - `Document::to_bytes` 生成的PDF
+///
+/// # 返回
+/// 返回修改后的PDF
+pub fn mark_flate_images(pdf: &[u8]) -> Result<Vec<u8>, BookletError> {
+    let invalid = |what: &str| {
+        BookletError::Pdf(PdfError::InvalidStructure(format!(
+            "无法标记图像的压缩方式，{}",
+            what
+        )))
+    };
+    let tail_start = pdf.len().saturating_sub(64);
+    let startxref = find(&pdf[tail_start..], b"startxref\n")
+        .map(|pos| tail_start + pos)
+        .ok_or_else(|| invalid("找不到startxref"))?;
+    let xref_pos = parse_number(&pdf[startxref + b"startxref\n".len()..])
+        .filter(|&pos| {
+            pdf.get(pos..)
+                .is_some_and(|xref| xref.starts_with(b"xref\n"))
+        })
+        .ok_or_else(|| invalid("找不到交叉引用表"))?;
+    // oxidize-pdf只写一个从0号对象开始的子段，每项20字节
+    let header_start = xref_pos + b"xref\n".len();
+    let header_len =
+        find(&pdf[header_start..], b"\n").ok_or_else(|| invalid("交叉引用表不完整"))?;
+    let header = std::str::from_utf8(&pdf[header_start..header_start + header_len])
+        .map_err(|_| invalid("交叉引用表不完整"))?;
+    let count = match header.split_whitespace().collect::<Vec<_>>()[..] {
+        ["0", count] => count.parse::<usize>().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| invalid("交叉引用表有多个子段"))?;
+    let entries_start = header_start + header_len + 1;
+    let trailer_start = entries_start + count * 20;
+    if !pdf
+        .get(trailer_start..startxref)
+        .is_some_and(|trailer| trailer.starts_with(b"trailer"))
+    {
+        return Err(invalid("交叉引用表后没有trailer"));
+    }
+    let entries: Vec<&[u8]> = pdf[entries_start..trailer_start].chunks(20).collect();
+    // (表项序号, 对象位置)，按位置排序
+    let mut objects = Vec::new();
+    for (i, entry) in entries.iter().enumerate() {
+        if entry[17] == b'n' {
+            let offset = parse_number(entry)
+                .filter(|&offset| offset < xref_pos)
+                .ok_or_else(|| invalid("交叉引用表有误"))?;
+            objects.push((i, offset));
+        }
+    }
+    objects.sort_by_key(|&(_, offset)| offset);
+    let Some(&(_, first_offset)) = objects.first() else {
+        return Ok(pdf.to_vec());
+    };
+
+    let mut out = Vec::with_capacity(pdf.len() + 64);
+    out.extend_from_slice(&pdf[..first_offset]);
+    let mut new_offsets = vec![None; entries.len()];
+    for (k, &(i, offset)) in objects.iter().enumerate() {
+        let end = objects.get(k + 1).map_or(xref_pos, |&(_, next)| next);
+        let object = &pdf[offset..end];
+        new_offsets[i] = Some(out.len());
+        let dict_end = find(object, b"\nstream\n").filter(|&pos| {
+            let dict = &object[..pos];
+            find(dict, b"/Subtype /Image").is_some() && find(dict, b"/Filter").is_none()
+        });
+        match dict_end {
+            Some(pos) if object[..pos].ends_with(b"\n>>") => {
+                out.extend_from_slice(&object[..pos - 3]);
+                out.extend_from_slice(b"\n/Filter /FlateDecode");
+                out.extend_from_slice(&object[pos - 3..]);
+            }
+            Some(_) => return Err(invalid("图像字典格式有误")),
+            None => out.extend_from_slice(object),
+        }
+    }
+
+    let new_xref_pos = out.len();
+    out.extend_from_slice(&pdf[xref_pos..entries_start]);
+    for (entry, new_offset) in entries.iter().zip(&new_offsets) {
+        match new_offset {
+            Some(offset) => {
+                out.extend_from_slice(format!("{:010}", offset).as_bytes());
+                out.extend_from_slice(&entry[10..]);
+            }
+            None => out.extend_from_slice(entry),
+        }
+    }
+    out.extend_from_slice(&pdf[trailer_start..startxref]);
+    out.extend_from_slice(format!("startxref\n{}\n%%EOF\n", new_xref_pos).as_bytes());
+    Ok(out)
+}
+
+/// 查找字节串第一次出现的位置
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// 解析开头的十进制数字
+fn parse_number(data: &[u8]) -> Option<usize> {
+    let digits = data.iter().take_while(|b| b.is_ascii_digit()).count();
+    std::str::from_utf8(&data[..digits]).ok()?.parse().ok()
+}
+
+/// RGBA转为RGB
+fn to_rgb(rgba: &[u8]) -> Vec<u8> {
+    rgba.chunks_exact(4)
+        .flat_map(|px| [px[0], px[1], px[2]])
+        .collect()
+}
+
+/// RGBA转为8位灰度
+fn to_gray(rgba: &[u8]) -> Vec<u8> {
+    rgba.chunks_exact(4).map(luminance).collect()
+}
+
+/// RGBA转为1位黑白，每行按字节对齐，1为白色
+fn to_bilevel(width: u32, rgba: &[u8], threshold: u8) -> Vec<u8> {
+    let row_bytes = (width as usize).div_ceil(8);
+    let mut bits = Vec::with_capacity(row_bytes * rgba.len() / 4 / width.max(1) as usize);
+    for row in rgba.chunks_exact(width as usize * 4) {
+        let mut packed = vec![0u8; row_bytes];
+        for (x, px) in row.chunks_exact(4).enumerate() {
+            if luminance(px) >= threshold {
+                packed[x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+        bits.extend_from_slice(&packed);
+    }
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidize_pdf::parser::{ParseOptions, PdfObject, PdfReader};
+    use oxidize_pdf::{Document, Page};
+    use std::io::Cursor;
+
+    /// 交叉引用表中使用中的对象：(对象号, 位置)
+    fn xref_objects(pdf: &[u8]) -> Vec<(u32, usize)> {
+        let startxref = pdf.windows(9).rposition(|w| w == b"startxref").unwrap();
+        let xref_pos = parse_number(&pdf[startxref + 10..]).unwrap();
+        let table = std::str::from_utf8(&pdf[xref_pos..startxref]).unwrap();
+        table
+            .lines()
+            .skip(2)
+            .take_while(|line| !line.starts_with("trailer"))
+            .enumerate()
+            .filter(|(_, line)| line.trim_end().ends_with('n'))
+            .map(|(num, line)| (num as u32, line[..10].parse().unwrap()))
+            .collect()
+    }
+
+    /// 对象的原始文本，从 `N 0 obj` 到 `stream`（不含流数据）
+    fn object_header(pdf: &[u8], offset: usize) -> String {
+        let object = &pdf[offset..];
+        let end = find(object, b"endobj").unwrap();
+        let end = find(&object[..end], b"stream\n").unwrap_or(end);
+        String::from_utf8_lossy(&pdf[offset..offset + end]).into_owned()
+    }
+
+    fn gray_rgba(width: u32, height: u32) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| {
+                let v = (i * 7 % 256) as u8;
+                [v, v, v, 255]
+            })
+            .collect()
+    }
+
+    /// 生成一页带两张不透明图像和一张带遮罩图像的PDF并标记压缩方式
+    fn sample_pdf() -> (Vec<u8>, Vec<u8>) {
+        let (width, height) = (16, 8);
+        let mut page = Page::a4();
+        let gray = encode_image(
+            width,
+            height,
+            &gray_rgba(width, height),
+            ImageEncoding::Flate,
+        );
+        page.add_image("Gray", gray.unwrap());
+        let mono = encode_image(
+            width,
+            height,
+            &gray_rgba(width, height),
+            ImageEncoding::Mono(128),
+        );
+        page.add_image("Mono", mono.unwrap());
+        let rgba: Vec<u8> = (0..width * height)
+            .flat_map(|i| [255, 0, 0, (i % 256) as u8])
+            .collect();
+        page.add_image(
+            "Mask",
+            encode_transparent_image(width, height, &rgba).unwrap(),
+        );
+        let mut doc = Document::new();
+        doc.add_page(page);
+        let original = doc.to_bytes().unwrap();
+        let marked = mark_flate_images(&original).unwrap();
+        (original, marked)
+    }
+
+    #[test]
+    fn parse_encoding() {
+        assert_eq!(ImageEncoding::parse("flate"), Ok(ImageEncoding::Flate));
+        assert_eq!(ImageEncoding::parse("JPEG"), Ok(ImageEncoding::Jpeg(85)));
+        assert_eq!(ImageEncoding::parse("jpg:60"), Ok(ImageEncoding::Jpeg(60)));
+        assert_eq!(ImageEncoding::parse("mono"), Ok(ImageEncoding::Mono(128)));
+        assert_eq!(
+            ImageEncoding::parse("mono:200"),
+            Ok(ImageEncoding::Mono(200))
+        );
+        assert!(ImageEncoding::parse("jpeg:0").is_err());
+        assert!(ImageEncoding::parse("flate:1").is_err());
+        assert!(ImageEncoding::parse("g4").is_err());
+    }
+
+    #[test]
+    fn xref_offsets_point_at_objects() {
+        let (original, marked) = sample_pdf();
+        let objects = xref_objects(&marked);
+        assert_eq!(objects.len(), xref_objects(&original).len());
+        for (num, offset) in objects {
+            assert!(
+                marked[offset..].starts_with(format!("{} 0 obj", num).as_bytes()),
+                "对象{}的位置{}有误",
+                num,
+                offset
+            );
+        }
+        let startxref = marked.windows(9).rposition(|w| w == b"startxref").unwrap();
+        let xref_pos = parse_number(&marked[startxref + 10..]).unwrap();
+        assert!(marked[xref_pos..].starts_with(b"xref\n"));
+    }
+
+    #[test]
+    fn filter_added_once_per_image() {
+        let (original, marked) = sample_pdf();
+        let headers: Vec<String> = xref_objects(&marked)
+            .into_iter()
+            .map(|(_, offset)| object_header(&marked, offset))
+            .filter(|header| header.contains("/Subtype /Image"))
+            .collect();
+        // 两张不透明图像、带遮罩的图像和它的遮罩
+        assert_eq!(headers.len(), 4);
+        for header in &headers {
+            assert_eq!(header.matches("/Filter").count(), 1, "{}", header);
+            assert!(header.contains("/Filter /FlateDecode"), "{}", header);
+        }
+        // 带遮罩的图像和遮罩本来就有 /Filter，没有改动
+        let unchanged: Vec<String> = xref_objects(&original)
+            .into_iter()
+            .map(|(_, offset)| object_header(&original, offset))
+            .filter(|header| header.contains("/Subtype /Image") && header.contains("/Filter"))
+            .collect();
+        assert_eq!(unchanged.len(), 2);
+        for header in &unchanged {
+            assert!(headers.contains(header), "{}", header);
+        }
+    }
+
+    #[test]
+    fn marked_images_decode_to_samples() {
+        let (width, height) = (16, 8);
+        let (_, marked) = sample_pdf();
+        let mut reader = PdfReader::new(Cursor::new(marked.clone())).unwrap();
+        let mut decoded = Vec::new();
+        for (num, _) in xref_objects(&marked) {
+            if let PdfObject::Stream(stream) = reader.get_object(num, 0).unwrap() {
+                let is_image = stream
+                    .dict
+                    .get("Subtype")
+                    .and_then(PdfObject::as_name)
+                    .is_some_and(|name| name.0 == "Image");
+                if is_image {
+                    decoded.push(stream.decode(&ParseOptions::default()).unwrap());
+                }
+            }
+        }
+        let gray = to_gray(&gray_rgba(width, height));
+        let mono = to_bilevel(width, &gray_rgba(width, height), 128);
+        let alpha: Vec<u8> = (0..width * height).map(|i| (i % 256) as u8).collect();
+        assert!(decoded.contains(&gray));
+        assert!(decoded.contains(&mono));
+        assert!(decoded.contains(&alpha));
+    }
+
+    #[test]
+    fn rejects_other_layouts() {
+        assert!(mark_flate_images(b"%PDF-1.7\n1 0 obj\n<< >>\nendobj\n").is_err());
+    }
+}
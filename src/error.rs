@@ -0,0 +1,76 @@
+use std::fmt;
+use std::io;
+
+use crate::verify::VerifyReport;
+
+/// 生成册子时的错误
+#[derive(Debug)]
+pub enum BookletError {
+    /// 已取消
+    Cancelled,
+    /// 装订规则有误
+    InvalidRule(String),
+    /// 排版校验失败
+    Verification(VerifyReport),
+    /// 无法加载pdfium库，内容为尝试过的位置及失败原因
+    #[cfg_attr(not(feature = "pdfium"), allow(dead_code))]
+    PdfiumNotFound(Vec<String>),
+    /// 无法读取或渲染源文件的页面
+    Source(String),
+    /// 读写文件失败
+    Io(io::Error),
+    /// 生成PDF失败
+    Pdf(oxidize_pdf::PdfError),
+}
+
+impl fmt::Display for BookletError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BookletError::Cancelled => write!(f, "已取消"),
+            BookletError::InvalidRule(msg) => write!(f, "装订规则有误: {}", msg),
+            BookletError::Verification(report) => write!(f, "排版校验失败，{}", report),
+            BookletError::PdfiumNotFound(attempts) => {
+                write!(f, "无法加载pdfium库，已尝试:")?;
+                for attempt in attempts {
+                    write!(f, "\n  - {}", attempt)?;
+                }
+                Ok(())
+            }
+            BookletError::Source(msg) => write!(f, "无法读取源文件: {}", msg),
+            BookletError::Io(e) => write!(f, "读写文件失败: {}", e),
+            BookletError::Pdf(e) => write!(f, "生成PDF失败: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BookletError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BookletError::Cancelled
+            | BookletError::InvalidRule(_)
+            | BookletError::Verification(_)
+            | BookletError::PdfiumNotFound(_)
+            | BookletError::Source(_) => None,
+            BookletError::Io(e) => Some(e),
+            BookletError::Pdf(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for BookletError {
+    fn from(e: io::Error) -> Self {
+        BookletError::Io(e)
+    }
+}
+
+impl From<oxidize_pdf::PdfError> for BookletError {
+    fn from(e: oxidize_pdf::PdfError) -> Self {
+        BookletError::Pdf(e)
+    }
+}
+
+impl From<oxidize_pdf::parser::ParseError> for BookletError {
+    fn from(e: oxidize_pdf::parser::ParseError) -> Self {
+        BookletError::Pdf(e.into())
+    }
+}
@@ -0,0 +1,1500 @@
+use std::borrow::Cow;
+use std::mem;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use eframe::egui::{self, emath::Numeric};
+use image::RgbaImage;
+use native_dialog::DialogBuilder;
+
+use crate::booklet::{self, BindingRule, CenterLineStyle, RenderMode};
+use crate::composite_source::{self, SourcePart};
+use crate::config::{self, Preset};
+use crate::cover::{CoverPrint, CoverSpec};
+use crate::crop::{CropBox, CropMode};
+use crate::distribution::Distribution;
+use crate::encoding::ImageEncoding;
+use crate::error::BookletError;
+use crate::image_source::{self, ImageSource};
+use crate::output::OverwritePolicy;
+use crate::page_source::PageSource;
+use crate::pdf_creator::{self, BookletSummary};
+use crate::pdf_render::{self, PdfDocumentHolder};
+use crate::preview;
+use crate::progress::{CancelToken, Progress};
+use crate::scan_cleanup::{Binarize, ScanCleanup};
+use crate::sheet_split::SheetGrouping;
+use crate::stamp::{PageFilter, RunningText, StampPosition, Stamps, TextAlign, Watermark};
+use crate::verify;
+
+/// 预览图中每页缩略图的宽度(px)
+const THUMB_WIDTH: u32 = 80;
+/// 设置改变后等待一段时间再更新预览，避免拖动数值时反复渲染
+const PREVIEW_DELAY: Duration = Duration::from_millis(500);
+/// 包含中文字形的系统字体，按顺序使用第一个存在的
+const CJK_FONTS: &[&str] = &[
+    "C:\\Windows\\Fonts\\msyh.ttc",
+    "C:\\Windows\\Fonts\\simhei.ttf",
+    "C:\\Windows\\Fonts\\simsun.ttc",
+    "/System/Library/Fonts/PingFang.ttc",
+    "/System/Library/Fonts/STHeiti Light.ttc",
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/google-noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/truetype/wqy/wqy-microhei.ttc",
+    "/usr/share/fonts/wenquanyi/wqy-microhei/wqy-microhei.ttc",
+];
+
+/// 打开图形界面，关闭窗口后返回
+///
+/// # 参数
+/// * `binding_rule` - 初始装订规则，已指定源文件时自动打开
+/// * `preset` - 初始装订规则使用的预设名称
+/// * `config_path` - 配置文件路径，切换预设时使用
+/// * `pdfium_path` - 命令行指定的pdfium库位置
+pub fn run(
+    binding_rule: BindingRule,
+    preset: Option<String>,
+    config_path: Option<PathBuf>,
+    pdfium_path: Option<PathBuf>,
+) -> eframe::Result {
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default()
+            .with_title(env!("CARGO_PKG_DESCRIPTION"))
+            .with_inner_size([1280.0, 800.0])
+            .with_drag_and_drop(true),
+        ..Default::default()
+    };
+    eframe::run_native(
+        env!("CARGO_PKG_NAME"),
+        options,
+        Box::new(|cc| {
+            install_cjk_font(&cc.egui_ctx);
+            let (request_tx, request_rx) = mpsc::channel();
+            let (response_tx, response_rx) = mpsc::channel();
+            let ctx = cc.egui_ctx.clone();
+            thread::spawn(move || worker(request_rx, response_tx, ctx, pdfium_path));
+            let input_path = binding_rule.input_path.clone();
+            let mut app = BookletApp {
+                binding_rule,
+                config_path,
+                presets: config::list_presets().unwrap_or_default(),
+                preset,
+                source: None,
+                requests: request_tx,
+                responses: response_rx,
+                preview: None,
+                preview_pending: false,
+                preview_dirty: None,
+                generating: None,
+                progress: None,
+                status: Status::Info("打开或拖入PDF文件".to_string()),
+            };
+            if !input_path.as_os_str().is_empty() {
+                app.open(input_path);
+            }
+            Ok(Box::new(app))
+        }),
+    )
+}
+
+/// 发给后台线程的请求，pdfium只在后台线程中使用
+enum Request {
+    /// 按装订规则打开源文件（拼接多个源文件、图像分辨率）
+    Open(BindingRule),
+    /// 绘制预览图
+    Preview(BindingRule),
+    /// 生成全部册子
+    Generate(BindingRule, CancelToken),
+}
+
+/// 后台线程的处理结果
+enum Response {
+    Opened(Result<SourceInfo, String>),
+    Preview(Result<RgbaImage, String>),
+    Progress(Progress),
+    Generated(Result<Vec<BookletSummary>, String>),
+}
+
+/// 源文件信息
+struct SourceInfo {
+    path: PathBuf,
+    /// 打开时拼接的各部分
+    sources: Vec<SourcePart>,
+    /// 打开时的封面文件和封面封底页数
+    cover_file: Option<(PathBuf, u16, u16)>,
+    /// 源文件要求新起一册的页面索引
+    booklet_breaks: Vec<u16>,
+    page_count: u16,
+    /// 第一页的宽高(mm)
+    page_size: Option<(f32, f32)>,
+    file_size: u64,
+    /// 文档信息中的标题、作者等
+    metadata: Vec<(&'static str, String)>,
+}
+
+/// 状态栏消息
+enum Status {
+    Info(String),
+    Error(String),
+}
+
+struct BookletApp {
+    binding_rule: BindingRule,
+    config_path: Option<PathBuf>,
+    presets: Vec<Preset>,
+    preset: Option<String>,
+    source: Option<SourceInfo>,
+    requests: Sender<Request>,
+    responses: Receiver<Response>,
+    preview: Option<egui::TextureHandle>,
+    /// 已发出预览请求，还没有收到结果
+    preview_pending: bool,
+    /// 设置改变的时间，预览需要更新
+    preview_dirty: Option<Instant>,
+    /// 正在生成时的取消令牌
+    generating: Option<CancelToken>,
+    progress: Option<Progress>,
+    status: Status,
+}
+
+impl eframe::App for BookletApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.handle_responses(ctx);
+        let dropped: Vec<PathBuf> = ctx.input(|input| {
+            input
+                .raw
+                .dropped_files
+                .iter()
+                .filter_map(|file| file.path.clone())
+                .collect()
+        });
+        if let Some(path) = dropped.into_iter().next() {
+            self.open(path);
+        }
+
+        let before = self.binding_rule.clone();
+        egui::TopBottomPanel::top("toolbar").show(ctx, |ui| self.toolbar_ui(ui));
+        egui::TopBottomPanel::bottom("status").show(ctx, |ui| match &self.status {
+            Status::Info(msg) => ui.label(msg),
+            Status::Error(msg) => ui.colored_label(ui.visuals().error_fg_color, msg),
+        });
+        egui::SidePanel::left("settings")
+            .resizable(true)
+            .default_width(360.0)
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    self.source_ui(ui);
+                    ui.add_enabled_ui(self.generating.is_none(), |ui| {
+                        settings_ui(ui, &mut self.binding_rule)
+                    });
+                });
+            });
+        egui::CentralPanel::default().show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| self.plan_ui(ui));
+        });
+        // 分辨率决定图像源的页面尺寸和缩小比例，需要重新打开
+        let image_dpi_changed = self.binding_rule.image_dpi != before.image_dpi
+            && self
+                .binding_rule
+                .source_paths()
+                .into_iter()
+                .any(image_source::is_image_input);
+        if self.binding_rule.input_path == before.input_path
+            && (image_dpi_changed
+                || self.binding_rule.sources != before.sources
+                || cover_file(&self.binding_rule) != cover_file(&before))
+        {
+            self.reopen();
+        }
+        if self.binding_rule != before {
+            self.preview_dirty = Some(Instant::now());
+        }
+        self.request_preview(ctx);
+    }
+}
+
+impl BookletApp {
+    /// 处理后台线程的结果
+    fn handle_responses(&mut self, ctx: &egui::Context) {
+        while let Ok(response) = self.responses.try_recv() {
+            match response {
+                Response::Opened(Ok(source)) => {
+                    self.status = Status::Info(format!(
+                        "已打开 {}，共{}页",
+                        source.path.display(),
+                        source.page_count
+                    ));
+                    self.source = Some(source);
+                    // 立即更新预览
+                    self.preview_dirty = Some(Instant::now() - PREVIEW_DELAY);
+                }
+                Response::Opened(Err(e)) => {
+                    self.source = None;
+                    self.status = Status::Error(e);
+                }
+                Response::Preview(Ok(img)) => {
+                    let size = [img.width() as usize, img.height() as usize];
+                    let img = egui::ColorImage::from_rgba_unmultiplied(size, img.as_raw());
+                    self.preview =
+                        Some(ctx.load_texture("preview", img, egui::TextureOptions::LINEAR));
+                    self.preview_pending = false;
+                }
+                Response::Preview(Err(e)) => {
+                    self.preview = None;
+                    self.preview_pending = false;
+                    self.status = Status::Error(e);
+                }
+                Response::Progress(progress) => self.progress = Some(progress),
+                Response::Generated(result) => {
+                    self.generating = None;
+                    self.progress = None;
+                    self.status = match result {
+                        Ok(summaries) => {
+                            let skipped = summaries.iter().filter(|s| s.skipped).count();
+                            Status::Info(format!(
+                                "已生成{}册（跳过{}册）到 {}",
+                                summaries.len() - skipped,
+                                skipped,
+                                self.binding_rule.output_dir.display()
+                            ))
+                        }
+                        Err(e) => Status::Error(format!("生成失败: {}", e)),
+                    };
+                }
+            }
+        }
+    }
+
+    /// 打开一个源文件（不再拼接），输出目录未设置或是原源文件的默认目录时改为新源文件的默认目录
+    fn open(&mut self, path: PathBuf) {
+        if self.generating.is_some() {
+            return;
+        }
+        let old_default = (!self.binding_rule.input_path.as_os_str().is_empty())
+            .then(|| BindingRule::new(&self.binding_rule.input_path).output_dir);
+        if self.binding_rule.output_dir.as_os_str().is_empty()
+            || old_default.as_ref() == Some(&self.binding_rule.output_dir)
+        {
+            self.binding_rule.output_dir = BindingRule::new(&path).output_dir;
+        }
+        self.binding_rule.input_path = path;
+        self.binding_rule.sources.clear();
+        self.reopen();
+    }
+
+    /// 按当前的装订规则重新打开源文件
+    fn reopen(&mut self) {
+        self.source = None;
+        self.preview = None;
+        self.status = Status::Info(format!(
+            "正在打开 {}",
+            self.binding_rule.input_path.display()
+        ));
+        let _ = self.requests.send(Request::Open(self.binding_rule.clone()));
+    }
+
+    /// 源文件已打开，且与装订规则中的源文件相同
+    fn source_ready(&self) -> bool {
+        self.source.as_ref().is_some_and(|source| {
+            source.path == self.binding_rule.input_path
+                && source.sources == self.binding_rule.sources
+                && source.cover_file == cover_file(&self.binding_rule)
+        })
+    }
+
+    /// 加入源文件要求的强制分册后的装订规则
+    fn planned_rule(&self) -> Cow<'_, BindingRule> {
+        match &self.source {
+            Some(source) => booklet::with_source_breaks(&self.binding_rule, &source.booklet_breaks),
+            None => Cow::Borrowed(&self.binding_rule),
+        }
+    }
+
+    /// 设置改变一段时间后请求更新预览，同一时间只有一个预览请求
+    fn request_preview(&mut self, ctx: &egui::Context) {
+        let Some(changed) = self.preview_dirty else {
+            return;
+        };
+        if !self.source_ready() || self.preview_pending || self.generating.is_some() {
+            return;
+        }
+        let elapsed = changed.elapsed();
+        if elapsed < PREVIEW_DELAY {
+            ctx.request_repaint_after(PREVIEW_DELAY - elapsed);
+            return;
+        }
+        self.preview_dirty = None;
+        self.preview_pending = true;
+        let _ = self
+            .requests
+            .send(Request::Preview(self.planned_rule().into_owned()));
+    }
+
+    fn generate(&mut self) {
+        let cancel = CancelToken::new();
+        self.generating = Some(cancel.clone());
+        self.progress = None;
+        self.status = Status::Info("正在生成".to_string());
+        let _ = self
+            .requests
+            .send(Request::Generate(self.planned_rule().into_owned(), cancel));
+    }
+
+    /// 切换预设，保留源文件和输出目录
+    fn apply_preset(&mut self, name: Option<String>) {
+        match config::load_binding_rule(self.config_path.as_deref(), name.as_deref()) {
+            Ok((mut binding_rule, preset)) => {
+                binding_rule.input_path = self.binding_rule.input_path.clone();
+                binding_rule.sources = self.binding_rule.sources.clone();
+                if binding_rule.output_dir.as_os_str().is_empty() {
+                    binding_rule.output_dir = self.binding_rule.output_dir.clone();
+                }
+                self.binding_rule = binding_rule;
+                self.preset = preset;
+            }
+            Err(e) => self.status = Status::Error(e.to_string()),
+        }
+    }
+
+    fn toolbar_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let idle = self.generating.is_none();
+            if ui.add_enabled(idle, egui::Button::new("打开…")).clicked() {
+                let path = DialogBuilder::file()
+                    .add_filter("PDF", ["pdf"])
+                    .add_filter("图像", image_source::IMAGE_EXTENSIONS)
+                    .set_title("选择源文件")
+                    .open_single_file()
+                    .show();
+                if let Ok(Some(path)) = path {
+                    self.open(path);
+                }
+            }
+            if ui
+                .add_enabled(idle, egui::Button::new("打开图像文件夹…"))
+                .clicked()
+            {
+                let dir = DialogBuilder::file()
+                    .set_title("选择图像文件夹")
+                    .open_single_dir()
+                    .show();
+                if let Ok(Some(dir)) = dir {
+                    self.open(dir);
+                }
+            }
+            if ui
+                .add_enabled(idle, egui::Button::new("输出目录…"))
+                .clicked()
+            {
+                let dir = DialogBuilder::file()
+                    .set_title("选择输出目标文件夹")
+                    .open_single_dir()
+                    .show();
+                if let Ok(Some(dir)) = dir {
+                    self.binding_rule.output_dir = dir;
+                }
+            }
+            ui.separator();
+            ui.label("预设");
+            let mut preset = self.preset.clone();
+            ui.add_enabled_ui(idle, |ui| {
+                egui::ComboBox::from_id_salt("preset")
+                    .selected_text(preset.as_deref().unwrap_or("（配置文件）"))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut preset, None, "（配置文件）");
+                        for p in &self.presets {
+                            ui.selectable_value(&mut preset, Some(p.name.clone()), &p.name)
+                                .on_hover_text(p.description.as_deref().unwrap_or_default());
+                        }
+                    });
+            });
+            if preset != self.preset {
+                self.apply_preset(preset);
+            }
+            ui.separator();
+            match &self.generating {
+                None => {
+                    let enabled =
+                        self.source_ready() && !self.binding_rule.output_dir.as_os_str().is_empty();
+                    if ui.add_enabled(enabled, egui::Button::new("生成")).clicked() {
+                        self.generate();
+                    }
+                }
+                Some(cancel) => {
+                    if ui.button("取消").clicked() {
+                        cancel.cancel();
+                    }
+                    let (fraction, text) = match &self.progress {
+                        Some(p) => (
+                            (p.percent / 100.0) as f32,
+                            format!(
+                                "第{}/{}册 第{}/{}张 {}/{}页",
+                                p.booklet_num,
+                                p.booklet_count,
+                                p.sheet_num,
+                                p.sheet_count,
+                                p.done_pages,
+                                p.total_pages
+                            ),
+                        ),
+                        None => (0.0, "准备中".to_string()),
+                    };
+                    ui.add(
+                        egui::ProgressBar::new(fraction)
+                            .text(text)
+                            .show_percentage(),
+                    );
+                }
+            }
+        });
+    }
+
+    fn source_ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("源文件")
+            .default_open(true)
+            .show(ui, |ui| {
+                ui.add_enabled_ui(self.generating.is_none(), |ui| self.parts_ui(ui));
+                let Some(source) = &self.source else {
+                    ui.label("未打开");
+                    return;
+                };
+                egui::Grid::new("source").num_columns(2).show(ui, |ui| {
+                    ui.label("文件");
+                    ui.label(source.path.display().to_string());
+                    ui.end_row();
+                    ui.label("页数");
+                    ui.label(source.page_count.to_string());
+                    ui.end_row();
+                    if let Some((w, h)) = source.page_size {
+                        ui.label("页面尺寸");
+                        ui.label(format!("{:.0} x {:.0} mm", w, h));
+                        ui.end_row();
+                    }
+                    ui.label("文件大小");
+                    ui.label(format!(
+                        "{:.1} MB",
+                        source.file_size as f64 / 1024.0 / 1024.0
+                    ));
+                    ui.end_row();
+                    for (name, value) in &source.metadata {
+                        ui.label(*name);
+                        ui.label(value);
+                        ui.end_row();
+                    }
+                });
+            });
+    }
+
+    /// 拼接的各部分：页码范围、是否新起一册，可以添加和删除
+    fn parts_ui(&mut self, ui: &mut egui::Ui) {
+        let sources = &mut self.binding_rule.sources;
+        let mut removed = None;
+        if !sources.is_empty() {
+            egui::Grid::new("parts").num_columns(4).show(ui, |ui| {
+                for header in ["部分", "页码范围", "新起一册", ""] {
+                    ui.strong(header);
+                }
+                ui.end_row();
+                for (i, part) in sources.iter_mut().enumerate() {
+                    let name = part.path.file_name().unwrap_or_default().to_string_lossy();
+                    ui.label(name)
+                        .on_hover_text(part.path.display().to_string());
+                    ui.horizontal(|ui| {
+                        let mut ranged = part.pages.is_some();
+                        if ui.checkbox(&mut ranged, "").changed() {
+                            part.pages = ranged.then_some((1, 1));
+                        }
+                        match &mut part.pages {
+                            Some((first, last)) => {
+                                ui.add(egui::DragValue::new(first).range(1..=*last));
+                                ui.label("-");
+                                ui.add(egui::DragValue::new(last).range(*first..=u16::MAX));
+                            }
+                            None => {
+                                ui.label("全部");
+                            }
+                        }
+                    });
+                    ui.add_enabled(i > 0, egui::Checkbox::without_text(&mut part.new_booklet));
+                    if ui.small_button("✕").clicked() {
+                        removed = Some(i);
+                    }
+                    ui.end_row();
+                }
+            });
+        }
+        if let Some(i) = removed {
+            sources.remove(i);
+        }
+        if ui
+            .button("添加源文件…")
+            .on_hover_text("拼接到最后")
+            .clicked()
+        {
+            let path = DialogBuilder::file()
+                .add_filter("PDF", ["pdf"])
+                .add_filter("图像", image_source::IMAGE_EXTENSIONS)
+                .set_title("选择要拼接的源文件")
+                .open_single_file()
+                .show();
+            if let Ok(Some(path)) = path {
+                self.append_source(path);
+            }
+        }
+    }
+
+    /// 把源文件拼接到最后，还没有打开源文件时直接打开
+    fn append_source(&mut self, path: PathBuf) {
+        let binding_rule = &mut self.binding_rule;
+        if binding_rule.input_path.as_os_str().is_empty() {
+            self.open(path);
+            return;
+        }
+        if binding_rule.sources.is_empty() {
+            binding_rule.sources.push(SourcePart {
+                path: binding_rule.input_path.clone(),
+                pages: None,
+                new_booklet: false,
+            });
+        }
+        binding_rule.sources.push(SourcePart {
+            path,
+            pages: None,
+            new_booklet: false,
+        });
+    }
+
+    /// 各册的排版计划、校验结果和预览图
+    fn plan_ui(&self, ui: &mut egui::Ui) {
+        let Some(source) = self.source.as_ref().filter(|_| self.source_ready()) else {
+            ui.label("打开或拖入PDF文件后显示排版计划和预览");
+            return;
+        };
+        let page_count = source.page_count;
+        let binding_rule = &*self.planned_rule();
+        if let Err(e) = booklet::check_sheet_counts(page_count, binding_rule) {
+            ui.colored_label(ui.visuals().error_fg_color, e.to_string());
+            return;
+        }
+        let booklets = booklet::plan_booklets(page_count, binding_rule);
+        let file_names = booklet::booklet_file_names(page_count, &booklets, binding_rule);
+
+        ui.heading(format!("共{}册", booklets.len()));
+        ui.label(format!("输出目录: {}", binding_rule.output_dir.display()));
+        egui::Grid::new("plan").striped(true).show(ui, |ui| {
+            for header in ["册", "纸张", "页码", "空白页", "文件名"] {
+                ui.strong(header);
+            }
+            ui.end_row();
+            for (i, booklet) in booklets.iter().enumerate() {
+                let sides = pdf_creator::calc_sheet_sides(booklet, binding_rule);
+                let blanks = sides
+                    .iter()
+                    .flat_map(|side| [side.low, side.high])
+                    .filter(|&page_idx| page_idx >= page_count)
+                    .count();
+                if booklet.booklet_num == 0 {
+                    ui.label("封面");
+                } else {
+                    ui.label(booklet.booklet_num.to_string());
+                }
+                ui.label(sides.len().div_ceil(2).to_string());
+                ui.label(
+                    pdf_creator::source_page_range(&sides, page_count)
+                        .map(|(first, last)| format!("{}-{}", first, last))
+                        .unwrap_or_default(),
+                );
+                ui.label(blanks.to_string());
+                match &file_names {
+                    Ok(names) => ui.label(&names[i]),
+                    Err(_) => ui.label("-"),
+                };
+                ui.end_row();
+            }
+        });
+        if let Err(e) = &file_names {
+            ui.colored_label(ui.visuals().error_fg_color, e);
+        }
+
+        let report = verify::verify_layout(page_count, binding_rule);
+        if report.is_ok() {
+            ui.label("排版校验通过");
+        } else {
+            ui.colored_label(
+                ui.visuals().warn_fg_color,
+                format!("排版校验失败，{}", report),
+            );
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.heading("预览");
+            if self.preview_pending || self.preview_dirty.is_some() {
+                ui.spinner();
+            }
+        });
+        if let Some(texture) = &self.preview {
+            ui.add(egui::Image::new(texture).max_width(ui.available_width()));
+        }
+    }
+}
+
+/// 封面封底的页数、打印方式和单独的封面文件
+fn cover_ui(ui: &mut egui::Ui, cover: &mut CoverSpec) {
+    ui.horizontal(|ui| {
+        ui.label("封面");
+        ui.add(
+            egui::DragValue::new(&mut cover.front_pages)
+                .range(0..=8)
+                .suffix(" 页"),
+        );
+        ui.label("封底");
+        ui.add(
+            egui::DragValue::new(&mut cover.back_pages)
+                .range(0..=8)
+                .suffix(" 页"),
+        );
+    });
+    ui.add_enabled_ui(cover.is_enabled(), |ui| {
+        variant_combo(
+            ui,
+            "cover_print",
+            &mut cover.print,
+            &[
+                (CoverPrint::WithBody, "与正文一起打印"),
+                (CoverPrint::Separate, "单独成册"),
+                (CoverPrint::Omit, "不打印"),
+            ],
+        );
+        ui.checkbox(&mut cover.blank_inside, "封面封底背面空白")
+            .on_hover_text("源文件中有封二、封三时取消");
+        ui.horizontal(|ui| {
+            match &cover.file {
+                Some(path) => {
+                    let name = path.file_name().unwrap_or_default().to_string_lossy();
+                    ui.label(format!("封面文件: {}", name))
+                        .on_hover_text(path.display().to_string());
+                    if ui.small_button("✕").clicked() {
+                        cover.file = None;
+                    }
+                }
+                None => {
+                    ui.label("封面封底在源文件中");
+                }
+            }
+            if ui
+                .button("选择封面文件…")
+                .on_hover_text("封面封底在单独的文件中，源文件全部为正文")
+                .clicked()
+            {
+                let path = DialogBuilder::file()
+                    .add_filter("PDF", ["pdf"])
+                    .add_filter("图像", image_source::IMAGE_EXTENSIONS)
+                    .set_title("选择封面文件")
+                    .open_single_file()
+                    .show();
+                if let Ok(Some(path)) = path {
+                    cover.file = Some(path);
+                }
+            }
+        });
+    });
+}
+
+/// 装订规则的全部设置项
+fn settings_ui(ui: &mut egui::Ui, binding_rule: &mut BindingRule) {
+    egui::CollapsingHeader::new("装订")
+        .default_open(true)
+        .show(ui, |ui| {
+            egui::Grid::new("binding").num_columns(2).show(ui, |ui| {
+                ui.label("每册纸张数");
+                if binding_rule.booklet_sheet_counts.is_empty() {
+                    let range = &mut binding_rule.sheets_per_booklet;
+                    ui.horizontal(|ui| {
+                        ui.add(egui::DragValue::new(&mut range.min).range(1..=range.max));
+                        ui.label("-");
+                        ui.add(
+                            egui::DragValue::new(&mut range.max)
+                                .range(1..=64)
+                                .suffix(" 张"),
+                        );
+                    });
+                    range.min = range.min.min(range.max);
+                    ui.end_row();
+                    ui.label("分配方式");
+                    variant_combo(
+                        ui,
+                        "distribution",
+                        &mut binding_rule.distribution,
+                        &[
+                            (Distribution::Auto, "自动"),
+                            (Distribution::Strict, "固定张数，最后一册较少"),
+                            (Distribution::Balanced, "均分"),
+                            (Distribution::RemainderFront, "均分，多的在前"),
+                            (Distribution::RemainderBack, "均分，多的在后"),
+                            (Distribution::MinimizeBlanks, "最少空白页"),
+                        ],
+                    );
+                } else {
+                    // 配置文件或命令行指定了每册纸张数
+                    ui.horizontal(|ui| {
+                        let counts: Vec<String> = binding_rule
+                            .booklet_sheet_counts
+                            .iter()
+                            .map(|n| n.to_string())
+                            .collect();
+                        ui.label(format!("按指定: {} 张", counts.join(", ")));
+                        if ui.button("改为自动分配").clicked() {
+                            binding_rule.booklet_sheet_counts.clear();
+                        }
+                    });
+                }
+                ui.end_row();
+                ui.label("装订方式");
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut binding_rule.binding_at_middle, true, "中间装订");
+                    ui.radio_value(&mut binding_rule.binding_at_middle, false, "两边装订");
+                });
+                ui.end_row();
+                ui.label("封面封底");
+                ui.vertical(|ui| {
+                    cover_ui(ui, &mut binding_rule.cover);
+                });
+                ui.end_row();
+            });
+        });
+
+    egui::CollapsingHeader::new("页边距").show(ui, |ui| {
+        let margins = &mut binding_rule.margins;
+        egui::Grid::new("margins").num_columns(2).show(ui, |ui| {
+            for (label, value) in [
+                ("切口侧", &mut margins.outer),
+                ("装订侧", &mut margins.inner),
+                ("天头", &mut margins.head),
+                ("地脚", &mut margins.foot),
+            ] {
+                ui.label(label);
+                ui.add(mm_value(value));
+                ui.end_row();
+            }
+            ui.label("中缝线");
+            ui.horizontal(|ui| {
+                variant_combo(
+                    ui,
+                    "center_line",
+                    &mut margins.center_line,
+                    &[
+                        (CenterLineStyle::Hidden, "不画"),
+                        (CenterLineStyle::Dotted(12.0), "点线"),
+                        (CenterLineStyle::Dashed(6.0, 3.0), "虚线"),
+                        (CenterLineStyle::Solid, "实线"),
+                    ],
+                );
+                match &mut margins.center_line {
+                    CenterLineStyle::Dotted(gap) => {
+                        ui.add(mm_value(gap));
+                    }
+                    CenterLineStyle::Dashed(len, gap) => {
+                        ui.add(mm_value(len));
+                        ui.add(mm_value(gap));
+                    }
+                    CenterLineStyle::Hidden | CenterLineStyle::Solid => {}
+                }
+            });
+            ui.end_row();
+        });
+    });
+
+    egui::CollapsingHeader::new("裁剪").show(ui, |ui| {
+        variant_combo(
+            ui,
+            "crop",
+            &mut binding_rule.crop,
+            &[
+                (CropMode::None, "不裁剪"),
+                (CropMode::auto(), "自动检测内容区域"),
+                (
+                    CropMode::Manual {
+                        odd: CropBox::default(),
+                        even: CropBox::default(),
+                    },
+                    "手动指定",
+                ),
+            ],
+        );
+        match &mut binding_rule.crop {
+            CropMode::None => {}
+            CropMode::Auto { threshold, padding } => {
+                egui::Grid::new("auto_crop").num_columns(2).show(ui, |ui| {
+                    ui.label("内容灰度阈值");
+                    ui.add(egui::DragValue::new(threshold).range(0..=255));
+                    ui.end_row();
+                    ui.label("保留空白");
+                    ui.add(
+                        egui::DragValue::new(padding)
+                            .range(0.0..=0.2)
+                            .speed(0.001)
+                            .fixed_decimals(3),
+                    );
+                    ui.end_row();
+                });
+            }
+            CropMode::Manual { odd, even } => {
+                egui::Grid::new("manual_crop")
+                    .num_columns(5)
+                    .show(ui, |ui| {
+                        for header in ["", "左", "上", "右", "下"] {
+                            ui.label(header);
+                        }
+                        ui.end_row();
+                        for (label, crop_box) in [("奇数页", odd), ("偶数页", even)] {
+                            ui.label(label);
+                            for value in [
+                                &mut crop_box.left,
+                                &mut crop_box.top,
+                                &mut crop_box.right,
+                                &mut crop_box.bottom,
+                            ] {
+                                ui.add(
+                                    egui::DragValue::new(value)
+                                        .range(0.0..=1.0)
+                                        .speed(0.005)
+                                        .fixed_decimals(3),
+                                );
+                            }
+                            ui.end_row();
+                        }
+                    });
+            }
+        }
+    });
+
+    egui::CollapsingHeader::new("扫描清理").show(ui, |ui| {
+        let cleanup = &mut binding_rule.scan_cleanup;
+        let defaults = ScanCleanup::scanned_book();
+        egui::Grid::new("scan_cleanup")
+            .num_columns(2)
+            .show(ui, |ui| {
+                optional_value(
+                    ui,
+                    "纠偏（最大角度）",
+                    &mut cleanup.deskew,
+                    defaults.deskew.unwrap_or(5.0),
+                    0.0..=45.0,
+                    0.1,
+                );
+                optional_value(
+                    ui,
+                    "背景变白",
+                    &mut cleanup.whiten_background,
+                    defaults.whiten_background.unwrap_or(210),
+                    0..=255,
+                    1.0,
+                );
+                optional_value(
+                    ui,
+                    "对比度",
+                    &mut cleanup.contrast,
+                    defaults.contrast.unwrap_or(1.2),
+                    0.1..=5.0,
+                    0.01,
+                );
+                optional_value(ui, "gamma", &mut cleanup.gamma, 1.2, 0.1..=5.0, 0.01);
+                optional_value(
+                    ui,
+                    "去噪点（像素数）",
+                    &mut cleanup.despeckle,
+                    defaults.despeckle.unwrap_or(4),
+                    1..=100,
+                    1.0,
+                );
+                let mut binarize = cleanup.binarize.is_some();
+                if ui.checkbox(&mut binarize, "二值化").changed() {
+                    cleanup.binarize = binarize.then_some(Binarize::Otsu);
+                }
+                ui.horizontal(|ui| {
+                    if let Some(binarize) = &mut cleanup.binarize {
+                        variant_combo(
+                            ui,
+                            "binarize",
+                            binarize,
+                            &[
+                                (Binarize::Otsu, "自动阈值"),
+                                (Binarize::Fixed(128), "固定阈值"),
+                            ],
+                        );
+                        if let Binarize::Fixed(threshold) = binarize {
+                            ui.add(egui::DragValue::new(threshold).range(0..=255));
+                        }
+                    }
+                });
+                ui.end_row();
+            });
+        if ui.button("使用扫描书籍的默认参数").clicked() {
+            *cleanup = defaults;
+        }
+    });
+
+    egui::CollapsingHeader::new("水印和页眉页脚").show(ui, |ui| {
+        stamps_ui(ui, &mut binding_rule.stamps);
+    });
+
+    egui::CollapsingHeader::new("输出").show(ui, |ui| {
+        egui::Grid::new("output").num_columns(2).show(ui, |ui| {
+            ui.label("排版方式");
+            variant_combo(
+                ui,
+                "render_mode",
+                &mut binding_rule.render_mode,
+                &[(RenderMode::Raster, "位图"), (RenderMode::Vector, "矢量")],
+            );
+            ui.end_row();
+            optional_value(
+                ui,
+                "图像分辨率",
+                &mut binding_rule.image_dpi,
+                300,
+                1..=2400,
+                1.0,
+            );
+            ui.label("彩色页面");
+            ui.horizontal(|ui| {
+                let detection = &mut binding_rule.color_detection;
+                ui.checkbox(&mut detection.enabled, "检测")
+                    .on_hover_text("黑白页面按灰度渲染，生成后报告含有彩色页面的纸张");
+                ui.add_enabled_ui(detection.enabled, |ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut detection.chroma_threshold)
+                            .range(0..=255)
+                            .prefix("色度阈值 "),
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut detection.min_color_percent)
+                            .range(0.0..=100.0)
+                            .speed(0.01)
+                            .prefix("彩色像素 ")
+                            .suffix(" %"),
+                    );
+                });
+            });
+            ui.end_row();
+            ui.label("纸张分组");
+            ui.horizontal(|ui| {
+                let split = &mut binding_rule.sheet_split;
+                variant_combo(
+                    ui,
+                    "sheet_grouping",
+                    &mut split.group_by,
+                    &[
+                        (SheetGrouping::None, "不分组"),
+                        (SheetGrouping::Color, "彩色/黑白"),
+                        (SheetGrouping::Images, "有图/纯文字"),
+                    ],
+                );
+                ui.add_enabled_ui(split.is_enabled(), |ui| {
+                    ui.checkbox(&mut split.separate_files, "每组单独成文件")
+                        .on_hover_text("分别打印后按插回表把纸张放回原来的位置");
+                });
+            });
+            ui.end_row();
+            ui.label("图像编码");
+            ui.horizontal(|ui| {
+                variant_combo(
+                    ui,
+                    "image_encoding",
+                    &mut binding_rule.image_encoding,
+                    &[
+                        (ImageEncoding::Flate, "Flate无损"),
+                        (ImageEncoding::Jpeg(85), "JPEG"),
+                        (ImageEncoding::Mono(128), "1位黑白"),
+                    ],
+                );
+                match &mut binding_rule.image_encoding {
+                    ImageEncoding::Flate => {}
+                    ImageEncoding::Jpeg(quality) => {
+                        ui.add(egui::DragValue::new(quality).range(1..=100).prefix("质量 "));
+                    }
+                    ImageEncoding::Mono(threshold) => {
+                        ui.add(
+                            egui::DragValue::new(threshold)
+                                .range(0..=255)
+                                .prefix("阈值 "),
+                        );
+                    }
+                }
+            });
+            ui.end_row();
+            ui.label("线程数");
+            ui.add(egui::DragValue::new(&mut binding_rule.render_threads).range(0..=32))
+                .on_hover_text("0为自动");
+            ui.end_row();
+            ui.label("文件已存在");
+            variant_combo(
+                ui,
+                "overwrite",
+                &mut binding_rule.overwrite,
+                &[
+                    (OverwritePolicy::Ask, "询问"),
+                    (OverwritePolicy::Skip, "跳过"),
+                    (OverwritePolicy::Overwrite, "覆盖"),
+                    (OverwritePolicy::AutoSuffix, "自动加序号"),
+                ],
+            );
+            ui.end_row();
+            ui.label("文件名模板");
+            ui.text_edit_singleline(&mut binding_rule.file_name_template)
+                .on_hover_text("{name} {num} {count} {first} {last} {sheets} {date}");
+            ui.end_row();
+            ui.label("校验");
+            ui.checkbox(&mut binding_rule.verify, "生成前校验排版结果");
+            ui.end_row();
+        });
+    });
+}
+
+/// 水印列表、页眉页脚和嵌入的字体，只用于位图排版
+fn stamps_ui(ui: &mut egui::Ui, stamps: &mut Stamps) {
+    let mut removed = None;
+    for (i, watermark) in stamps.watermarks.iter_mut().enumerate() {
+        ui.push_id(("watermark", i), |ui| {
+            ui.horizontal(|ui| {
+                ui.label(format!("水印{}", i + 1));
+                if ui.small_button("✕").clicked() {
+                    removed = Some(i);
+                }
+            });
+            watermark_ui(ui, watermark);
+        });
+        ui.separator();
+    }
+    if let Some(i) = removed {
+        stamps.watermarks.remove(i);
+    }
+    if ui.button("添加水印").clicked() {
+        stamps.watermarks.push(Watermark {
+            text: "DRAFT".to_string(),
+            ..Default::default()
+        });
+    }
+    egui::Grid::new("running_text")
+        .num_columns(2)
+        .show(ui, |ui| {
+            for (label, running_text) in
+                [("页眉", &mut stamps.header), ("页脚", &mut stamps.footer)]
+            {
+                ui.label(label);
+                ui.push_id(label, |ui| running_text_ui(ui, running_text));
+                ui.end_row();
+            }
+            ui.label("页码偏移");
+            ui.add(egui::DragValue::new(&mut stamps.page_number_offset).range(-999..=999))
+                .on_hover_text("页眉页脚中的页码为源页码加上这个值，小于1时不印页码");
+            ui.end_row();
+            ui.label("字体");
+            file_field(
+                ui,
+                &mut stamps.font_file,
+                "内置西文字体",
+                ("TrueType字体", &["ttf", "otf"]),
+            );
+            ui.end_row();
+        });
+}
+
+/// 一个水印的内容、样式、位置和页面
+fn watermark_ui(ui: &mut egui::Ui, watermark: &mut Watermark) {
+    egui::Grid::new("watermark").num_columns(2).show(ui, |ui| {
+        ui.label("内容");
+        ui.horizontal(|ui| {
+            ui.add_enabled(
+                watermark.image.is_none(),
+                egui::TextEdit::singleline(&mut watermark.text).desired_width(120.0),
+            );
+            file_field(
+                ui,
+                &mut watermark.image,
+                "",
+                ("图像", image_source::IMAGE_EXTENSIONS),
+            );
+        });
+        ui.end_row();
+        ui.label("样式");
+        ui.horizontal(|ui| {
+            let suffix = if watermark.image.is_some() {
+                " %"
+            } else {
+                " pt"
+            };
+            ui.add(
+                egui::DragValue::new(&mut watermark.size)
+                    .range(1.0..=500.0)
+                    .prefix("大小 ")
+                    .suffix(suffix),
+            );
+            if watermark.image.is_none() {
+                ui.color_edit_button_srgb(&mut watermark.color);
+            }
+            ui.add(
+                egui::DragValue::new(&mut watermark.opacity)
+                    .range(0.0..=1.0)
+                    .speed(0.01)
+                    .prefix("不透明度 "),
+            );
+            ui.add(
+                egui::DragValue::new(&mut watermark.rotation)
+                    .range(-180.0..=180.0)
+                    .prefix("旋转 ")
+                    .suffix("°"),
+            );
+        });
+        ui.end_row();
+        ui.label("位置");
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt("position")
+                .selected_text(watermark.position.to_string())
+                .show_ui(ui, |ui| {
+                    for position in StampPosition::value_variants() {
+                        ui.selectable_value(
+                            &mut watermark.position,
+                            *position,
+                            position.to_string(),
+                        );
+                    }
+                });
+            ui.add_enabled(
+                watermark.position != StampPosition::Center,
+                mm_value(&mut watermark.margin).prefix("边距 "),
+            );
+        });
+        ui.end_row();
+        ui.label("页面");
+        page_filter_ui(ui, &mut watermark.pages);
+        ui.end_row();
+    });
+}
+
+/// 页眉或页脚的模板、对齐、大小、距离和页面
+fn running_text_ui(ui: &mut egui::Ui, running_text: &mut RunningText) {
+    ui.vertical(|ui| {
+        ui.text_edit_singleline(&mut running_text.template)
+            .on_hover_text("{title} {chapter} {page} {pages} {booklet}，为空时不加");
+        ui.add_enabled_ui(!running_text.template.is_empty(), |ui| {
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_salt("align")
+                    .selected_text(running_text.align.to_string())
+                    .show_ui(ui, |ui| {
+                        for align in TextAlign::value_variants() {
+                            ui.selectable_value(&mut running_text.align, *align, align.to_string());
+                        }
+                    });
+                ui.add(
+                    egui::DragValue::new(&mut running_text.size)
+                        .range(1.0..=72.0)
+                        .prefix("大小 ")
+                        .suffix(" pt"),
+                );
+                ui.add(mm_value(&mut running_text.margin).prefix("边距 "));
+                page_filter_ui(ui, &mut running_text.pages);
+            });
+        });
+    });
+}
+
+/// 选择页面范围，指定页码时输入 `1-10,15` 格式的范围
+fn page_filter_ui(ui: &mut egui::Ui, filter: &mut PageFilter) {
+    let options = [
+        (PageFilter::All, "全部页面"),
+        (PageFilter::Body, "正文"),
+        (PageFilter::Odd, "奇数页"),
+        (PageFilter::Even, "偶数页"),
+        (PageFilter::Pages(vec![(1, 1)]), "指定页码"),
+    ];
+    ui.horizontal(|ui| {
+        let selected = options
+            .iter()
+            .find(|(option, _)| mem::discriminant(option) == mem::discriminant(filter))
+            .map_or("", |(_, name)| *name);
+        egui::ComboBox::from_id_salt("pages")
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                for (option, name) in &options {
+                    let is_selected = mem::discriminant(option) == mem::discriminant(filter);
+                    if ui.selectable_label(is_selected, *name).clicked() && !is_selected {
+                        *filter = option.clone();
+                    }
+                }
+            });
+        if let PageFilter::Pages(ranges) = filter {
+            // 输入中的文字可能还不是有效的范围，保存在界面状态中
+            let id = ui.id().with("page_ranges");
+            let mut text = ui
+                .data_mut(|data| data.get_temp::<String>(id))
+                .unwrap_or_else(|| {
+                    let ranges: Vec<String> = ranges
+                        .iter()
+                        .map(|(first, last)| format!("{}-{}", first, last))
+                        .collect();
+                    ranges.join(",")
+                });
+            let response = ui.add(egui::TextEdit::singleline(&mut text).desired_width(80.0));
+            if response.changed() {
+                if let Ok(parsed @ PageFilter::Pages(_)) = PageFilter::parse(&text) {
+                    *filter = parsed;
+                }
+                ui.data_mut(|data| data.insert_temp(id, text));
+            }
+        }
+    });
+}
+
+/// 可以清除的文件路径，`empty` 为没有选择文件时的说明
+fn file_field(ui: &mut egui::Ui, path: &mut Option<PathBuf>, empty: &str, filter: (&str, &[&str])) {
+    match path {
+        Some(current) => {
+            let name = current.file_name().unwrap_or_default().to_string_lossy();
+            ui.label(name).on_hover_text(current.display().to_string());
+            if ui.small_button("✕").clicked() {
+                *path = None;
+            }
+        }
+        None => {
+            ui.label(empty);
+        }
+    }
+    if ui.button("选择…").clicked() {
+        let selected = DialogBuilder::file()
+            .add_filter(filter.0, filter.1)
+            .open_single_file()
+            .show();
+        if let Ok(Some(selected)) = selected {
+            *path = Some(selected);
+        }
+    }
+}
+
+/// 页边距等长度值(mm)
+fn mm_value(value: &mut f64) -> egui::DragValue<'_> {
+    egui::DragValue::new(value)
+        .range(0.0..=50.0)
+        .speed(0.1)
+        .suffix(" mm")
+}
+
+/// 选择枚举的类型，切换类型时使用 `options` 中对应的参数
+fn variant_combo<T: Copy>(ui: &mut egui::Ui, id: &str, value: &mut T, options: &[(T, &str)]) {
+    let selected = options
+        .iter()
+        .find(|(option, _)| mem::discriminant(option) == mem::discriminant(value))
+        .map_or("", |(_, name)| *name);
+    egui::ComboBox::from_id_salt(id)
+        .selected_text(selected)
+        .show_ui(ui, |ui| {
+            for (option, name) in options {
+                let is_selected = mem::discriminant(option) == mem::discriminant(value);
+                if ui.selectable_label(is_selected, *name).clicked() && !is_selected {
+                    *value = *option;
+                }
+            }
+        });
+}
+
+/// 可以关闭的数值项，开启时使用 `default`
+fn optional_value<T: Numeric>(
+    ui: &mut egui::Ui,
+    label: &str,
+    value: &mut Option<T>,
+    default: T,
+    range: RangeInclusive<T>,
+    speed: f64,
+) {
+    let mut enabled = value.is_some();
+    if ui.checkbox(&mut enabled, label).changed() {
+        *value = enabled.then_some(default);
+    }
+    match value {
+        Some(value) => ui.add(egui::DragValue::new(value).range(range).speed(speed)),
+        None => ui.label(""),
+    };
+    ui.end_row();
+}
+
+/// 使用系统中文字体，找不到时界面中的中文无法显示
+fn install_cjk_font(ctx: &egui::Context) {
+    let Some(data) = CJK_FONTS.iter().find_map(|path| std::fs::read(path).ok()) else {
+        log::warn!("找不到中文字体，界面中的中文可能无法显示");
+        return;
+    };
+    let mut fonts = egui::FontDefinitions::default();
+    fonts.font_data.insert(
+        "cjk".to_string(),
+        Arc::new(egui::FontData::from_owned(data)),
+    );
+    for family in [egui::FontFamily::Proportional, egui::FontFamily::Monospace] {
+        fonts
+            .families
+            .entry(family)
+            .or_default()
+            .push("cjk".to_string());
+    }
+    ctx.set_fonts(fonts);
+}
+
+/// 后台线程：持有pdfium和打开的源文件，依次处理请求
+fn worker(
+    requests: Receiver<Request>,
+    responses: Sender<Response>,
+    ctx: egui::Context,
+    pdfium_path: Option<PathBuf>,
+) {
+    let send = |response| {
+        // 界面已关闭时不再发送
+        let _ = responses.send(response);
+        ctx.request_repaint();
+    };
+    // 图像源不需要pdfium，加载失败时仍然可以打开图像
+    let pdfium = match pdf_render::init_pdfium(pdfium_path.as_deref()) {
+        Ok(pdfium) => Ok(pdfium),
+        Err(e) => {
+            pdf_render::report_pdfium_error(&e);
+            Err(e.to_string())
+        }
+    };
+    let mut src_pdf: Option<Box<dyn PageSource + '_>> = None;
+    let mut auto_crop = None;
+    while let Ok(request) = requests.recv() {
+        match request {
+            Request::Open(binding_rule) => {
+                src_pdf = None;
+                auto_crop = None;
+                let open_file = |path: &Path| {
+                    if image_source::is_image_input(path) {
+                        return ImageSource::open(path, binding_rule.image_dpi)
+                            .map(|src| Box::new(src) as Box<dyn PageSource + '_>);
+                    }
+                    let pdfium = pdfium
+                        .as_ref()
+                        .map_err(|e| BookletError::Source(e.clone()))?;
+                    PdfDocumentHolder::open(pdfium, &path.to_path_buf(), None)
+                        .map(|doc| Box::new(doc) as Box<dyn PageSource + '_>)
+                        .map_err(|e| BookletError::Source(format!("{}: {}", path.display(), e)))
+                };
+                match composite_source::open_source(&binding_rule, open_file) {
+                    Ok(src) => {
+                        send(Response::Opened(Ok(source_info(
+                            &binding_rule,
+                            src.as_ref(),
+                        ))));
+                        src_pdf = Some(src);
+                    }
+                    Err(e) => send(Response::Opened(Err(e.to_string()))),
+                }
+            }
+            Request::Preview(binding_rule) => {
+                let Some(src_pdf) = src_pdf.as_deref() else {
+                    continue;
+                };
+                let result = resolve_crop_cached(&mut auto_crop, src_pdf, &binding_rule)
+                    .and_then(|binding_rule| {
+                        preview::render_preview(src_pdf, &binding_rule, THUMB_WIDTH)
+                    })
+                    .map_err(|e| e.to_string());
+                send(Response::Preview(result));
+            }
+            Request::Generate(binding_rule, cancel) => {
+                let Some(src_pdf) = src_pdf.as_deref() else {
+                    continue;
+                };
+                let listener = |progress: &Progress| send(Response::Progress(progress.clone()));
+                let result =
+                    booklet::create_booklet(src_pdf, &binding_rule, &listener, &cancel, true)
+                        .map_err(|e| e.to_string());
+                send(Response::Generated(result));
+            }
+        }
+    }
+}
+
+/// 读取源文件信息
+/// 单独的封面文件和封面封底页数，改变时需要重新打开源文件
+fn cover_file(binding_rule: &BindingRule) -> Option<(PathBuf, u16, u16)> {
+    let cover = &binding_rule.cover;
+    let path = cover.file.clone()?;
+    Some((path, cover.front_pages, cover.back_pages))
+}
+
+fn source_info(binding_rule: &BindingRule, src_pdf: &dyn PageSource) -> SourceInfo {
+    let metadata = [
+        ("Title", "标题"),
+        ("Author", "作者"),
+        ("Subject", "主题"),
+        ("Keywords", "关键词"),
+        ("Creator", "创建程序"),
+        ("Producer", "生成程序"),
+    ]
+    .into_iter()
+    .filter_map(|(key, name)| Some((name, src_pdf.get_info(key)?)))
+    .collect();
+    SourceInfo {
+        path: binding_rule.input_path.clone(),
+        sources: binding_rule.sources.clone(),
+        cover_file: cover_file(binding_rule),
+        booklet_breaks: src_pdf.get_booklet_breaks(),
+        page_count: src_pdf.get_page_count(),
+        page_size: src_pdf.get_page_size(0).map(|(w, h)| {
+            (
+                (w / pdf_creator::MM_TO_PT) as f32,
+                (h / pdf_creator::MM_TO_PT) as f32,
+            )
+        }),
+        file_size: binding_rule
+            .source_paths()
+            .into_iter()
+            .map(source_file_size)
+            .sum(),
+        metadata,
+    }
+}
+
+/// 源文件大小，图像文件夹为其中全部图像的大小
+fn source_file_size(path: &Path) -> u64 {
+    let file_size = |path: &Path| std::fs::metadata(path).map_or(0, |m| m.len());
+    if path.is_dir() {
+        image_source::list_images(path)
+            .map_or(0, |files| files.iter().map(|file| file_size(file)).sum())
+    } else {
+        file_size(path)
+    }
+}
+
+/// 解析自动裁剪，检测结果按裁剪参数和正文范围缓存
+///
+/// 自动裁剪需要渲染全部正文页，每次更新预览都重新检测太慢
+fn resolve_crop_cached(
+    cache: &mut Option<(CropMode, (u16, u16), CropMode)>,
+    src_pdf: &dyn PageSource,
+    binding_rule: &BindingRule,
+) -> Result<BindingRule, BookletError> {
+    let range = binding_rule.body_page_range(src_pdf.get_page_count());
+    let crop = match cache {
+        Some((mode, cached_range, crop))
+            if *mode == binding_rule.crop && *cached_range == range =>
+        {
+            *crop
+        }
+        _ => {
+            let crop = booklet::resolve_auto_crop(src_pdf, binding_rule)?.crop;
+            *cache = Some((binding_rule.crop, range, crop));
+            crop
+        }
+    };
+    Ok(BindingRule {
+        crop,
+        ..binding_rule.clone()
+    })
+}
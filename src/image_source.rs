@@ -0,0 +1,329 @@
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, RgbaImage};
+use tiff::ColorType;
+use tiff::decoder::{Decoder, DecodingResult};
+
+use crate::error::BookletError;
+use crate::page_source::PageSource;
+
+/// 支持的图像文件扩展名（不区分大小写）
+pub const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "tif", "tiff", "bmp", "webp"];
+
+/// 高于这个分辨率的图像缩小后再排版，与PDF页面的渲染分辨率相同
+const MAX_DPI: u32 = 300;
+
+/// 源文件是否按图像读取：目录或图像文件
+pub fn is_image_input(path: &Path) -> bool {
+    path.is_dir() || is_image_file(path)
+}
+
+/// 按扩展名判断是否为图像文件
+fn is_image_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            IMAGE_EXTENSIONS
+                .iter()
+                .any(|image_ext| ext.eq_ignore_ascii_case(image_ext))
+        })
+}
+
+fn is_tiff(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("tif") || ext.eq_ignore_ascii_case("tiff"))
+}
+
+/// 列出目录中的图像文件，按文件名自然排序（`2.jpg` 在 `10.jpg` 之前）
+///
+/// 不包含子目录和隐藏文件
+pub fn list_images(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && is_image_file(path))
+        .filter(|path| {
+            !path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+        })
+        .collect();
+    files.sort_by(|a, b| {
+        natural_cmp(
+            &a.file_name().unwrap_or_default().to_string_lossy(),
+            &b.file_name().unwrap_or_default().to_string_lossy(),
+        )
+    });
+    Ok(files)
+}
+
+/// 自然排序：数字部分按数值比较，其他部分不区分大小写比较
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        match (a.chars().next(), b.chars().next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ca), Some(cb)) if ca.is_ascii_digit() && cb.is_ascii_digit() => {
+                let a_len = a.find(|c: char| !c.is_ascii_digit()).unwrap_or(a.len());
+                let b_len = b.find(|c: char| !c.is_ascii_digit()).unwrap_or(b.len());
+                let a_num = a[..a_len].trim_start_matches('0');
+                let b_num = b[..b_len].trim_start_matches('0');
+                let ordering = a_num
+                    .len()
+                    .cmp(&b_num.len())
+                    .then_with(|| a_num.cmp(b_num))
+                    // 数值相同时前导零少的在前
+                    .then_with(|| a_len.cmp(&b_len));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a = &a[a_len..];
+                b = &b[b_len..];
+            }
+            (Some(ca), Some(cb)) => {
+                let ordering = ca
+                    .to_lowercase()
+                    .cmp(cb.to_lowercase())
+                    .then_with(|| ca.cmp(&cb));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a = &a[ca.len_utf8()..];
+                b = &b[cb.len_utf8()..];
+            }
+        }
+    }
+}
+
+/// 图像序列中的一页
+struct ImagePage {
+    path: PathBuf,
+    /// 多页TIFF中的页索引，其他图像为0
+    frame: usize,
+}
+
+/// 扫描图像作为源页面：图像文件夹、单个图像文件或多页TIFF
+///
+/// 每个图像（多页TIFF的每一页）为一页，不经过PDF转换，直接进入位图排版流程。
+/// 不能按矢量排版。
+pub struct ImageSource {
+    pages: Vec<ImagePage>,
+    /// 图像的分辨率，不知道时为 `None`
+    dpi: Option<u32>,
+}
+
+impl ImageSource {
+    /// 打开图像源
+    ///
+    /// # 参数
+    /// * `path` - 图像文件夹或图像文件，文件夹中的图像按文件名自然排序
+    /// * `dpi` - 图像的分辨率，用于计算页面尺寸，高于300DPI的图像缩小到300DPI
+    ///
+    /// # 返回
+    /// 没有图像或无法读取时返回错误
+    pub fn open(path: &Path, dpi: Option<u32>) -> Result<Self, BookletError> {
+        let files = if path.is_dir() {
+            list_images(path)?
+        } else {
+            vec![path.to_path_buf()]
+        };
+        let mut pages = Vec::new();
+        for file in files {
+            let frames = if is_tiff(&file) {
+                tiff_frame_count(&file)?
+            } else {
+                1
+            };
+            pages.extend((0..frames).map(|frame| ImagePage {
+                path: file.clone(),
+                frame,
+            }));
+        }
+        if pages.is_empty() {
+            return Err(BookletError::Source(format!(
+                "{} 中没有图像（支持 {}）",
+                path.display(),
+                IMAGE_EXTENSIONS.join("、")
+            )));
+        }
+        if pages.len() > u16::MAX as usize {
+            return Err(BookletError::Source(format!("{} 页数太多", path.display())));
+        }
+        log::debug!(pages = pages.len(); "图像源共{}页", pages.len());
+        Ok(Self {
+            pages,
+            dpi: dpi.filter(|&dpi| dpi > 0),
+        })
+    }
+
+    /// 读取一页图像，按分辨率缩小
+    fn load(&self, page_idx: u16) -> Result<RgbaImage, BookletError> {
+        let page = self
+            .pages
+            .get(page_idx as usize)
+            .ok_or_else(|| BookletError::Source(format!("第{}页超出图像数量", page_idx + 1)))?;
+        let img = if page.frame == 0 {
+            image::open(&page.path)
+                .map(DynamicImage::into_rgba8)
+                .map_err(|e| {
+                    BookletError::Source(format!("无法读取 {}: {}", page.path.display(), e))
+                })?
+        } else {
+            read_tiff_frame(&page.path, page.frame)?
+        };
+        match self.dpi {
+            Some(dpi) if dpi > MAX_DPI => {
+                let scale = MAX_DPI as f64 / dpi as f64;
+                let w = ((img.width() as f64 * scale).round() as u32).max(1);
+                let h = ((img.height() as f64 * scale).round() as u32).max(1);
+                Ok(imageops::resize(&img, w, h, FilterType::Triangle))
+            }
+            _ => Ok(img),
+        }
+    }
+}
+
+impl PageSource for ImageSource {
+    fn get_page_count(&self) -> u16 {
+        self.pages.len() as u16
+    }
+
+    fn get_info(&self, _key: &str) -> Option<String> {
+        None
+    }
+
+    fn get_page_image(
+        &self,
+        page_idx: u16,
+        reverse_image: bool,
+    ) -> Result<(u32, u32, Vec<u8>), BookletError> {
+        let img = self.load(page_idx)?;
+        let img = if reverse_image {
+            imageops::rotate270(&img)
+        } else {
+            imageops::rotate90(&img)
+        };
+        Ok((img.width(), img.height(), img.into_raw()))
+    }
+
+    fn get_page_preview(
+        &self,
+        page_idx: u16,
+        target_width: i32,
+    ) -> Result<(u32, u32, Vec<u8>), BookletError> {
+        let img = self.load(page_idx)?;
+        let width = (target_width.max(1) as u32).min(img.width());
+        let height =
+            ((img.height() as f64 * width as f64 / img.width() as f64).round() as u32).max(1);
+        let img = imageops::resize(&img, width, height, FilterType::Triangle);
+        Ok((img.width(), img.height(), img.into_raw()))
+    }
+
+    fn get_page_size(&self, page_idx: u16) -> Option<(f64, f64)> {
+        let dpi = self.dpi? as f64;
+        let page = self.pages.get(page_idx as usize)?;
+        let (width, height) = if page.frame == 0 {
+            image::image_dimensions(&page.path).ok()?
+        } else {
+            let mut decoder = tiff_decoder(&page.path).ok()?;
+            decoder.seek_to_image(page.frame).ok()?;
+            decoder.dimensions().ok()?
+        };
+        Some((width as f64 / dpi * 72.0, height as f64 / dpi * 72.0))
+    }
+
+    fn has_images(&self, page_idx: u16) -> Option<bool> {
+        // 每页都是一幅图像
+        ((page_idx as usize) < self.pages.len()).then_some(true)
+    }
+}
+
+fn tiff_decoder(path: &Path) -> Result<Decoder<BufReader<File>>, BookletError> {
+    Decoder::new(BufReader::new(File::open(path)?)).map_err(|e| tiff_error(path, e))
+}
+
+fn tiff_error(path: &Path, e: tiff::TiffError) -> BookletError {
+    BookletError::Source(format!("无法读取 {}: {}", path.display(), e))
+}
+
+/// TIFF文件的页数
+fn tiff_frame_count(path: &Path) -> Result<usize, BookletError> {
+    let mut decoder = tiff_decoder(path)?;
+    let mut count = 1;
+    while decoder.more_images() {
+        decoder.next_image().map_err(|e| tiff_error(path, e))?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// 读取多页TIFF中的一页
+///
+/// 支持1/8/16位灰度、带透明度的灰度、RGB、RGBA和CMYK
+fn read_tiff_frame(path: &Path, frame: usize) -> Result<RgbaImage, BookletError> {
+    let mut decoder = tiff_decoder(path)?;
+    decoder
+        .seek_to_image(frame)
+        .map_err(|e| tiff_error(path, e))?;
+    let (width, height) = decoder.dimensions().map_err(|e| tiff_error(path, e))?;
+    let color_type = decoder.colortype().map_err(|e| tiff_error(path, e))?;
+    let samples: Vec<u8> = match decoder.read_image().map_err(|e| tiff_error(path, e))? {
+        DecodingResult::U8(data) => data,
+        // 16位只保留高8位
+        DecodingResult::U16(data) => data.iter().map(|v| (v >> 8) as u8).collect(),
+        _ => return Err(unsupported_tiff(path, frame, color_type)),
+    };
+    let pixels = width as usize * height as usize;
+    let rgba: Vec<u8> = match color_type {
+        ColorType::Gray(1) => {
+            // 每行按字节对齐，1为白色
+            let row_bytes = (width as usize).div_ceil(8);
+            (0..height as usize)
+                .flat_map(|y| (0..width as usize).map(move |x| (y, x)))
+                .flat_map(|(y, x)| {
+                    let bit = samples[y * row_bytes + x / 8] >> (7 - x % 8) & 1;
+                    let v = if bit == 1 { 255 } else { 0 };
+                    [v, v, v, 255]
+                })
+                .collect()
+        }
+        ColorType::Gray(8 | 16) => samples.iter().flat_map(|&v| [v, v, v, 255]).collect(),
+        ColorType::GrayA(8 | 16) => samples
+            .chunks_exact(2)
+            .flat_map(|px| [px[0], px[0], px[0], px[1]])
+            .collect(),
+        ColorType::RGB(8 | 16) => samples
+            .chunks_exact(3)
+            .flat_map(|px| [px[0], px[1], px[2], 255])
+            .collect(),
+        ColorType::RGBA(8 | 16) => samples,
+        ColorType::CMYK(8 | 16) => samples
+            .chunks_exact(4)
+            .flat_map(|px| {
+                let k = 255 - px[3] as u32;
+                let channel = |c: u8| ((255 - c as u32) * k / 255) as u8;
+                [channel(px[0]), channel(px[1]), channel(px[2]), 255]
+            })
+            .collect(),
+        color_type => return Err(unsupported_tiff(path, frame, color_type)),
+    };
+    if rgba.len() != pixels * 4 {
+        return Err(unsupported_tiff(path, frame, color_type));
+    }
+    Ok(RgbaImage::from_raw(width, height, rgba).expect("RGBA数据与尺寸不符"))
+}
+
+fn unsupported_tiff(path: &Path, frame: usize, color_type: ColorType) -> BookletError {
+    BookletError::Source(format!(
+        "{} 第{}页的颜色格式 {:?} 不支持",
+        path.display(),
+        frame + 1,
+        color_type
+    ))
+}
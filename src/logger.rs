@@ -0,0 +1,145 @@
+use std::io::Write;
+
+use clap::ValueEnum;
+use log::kv::{self, Key, Value, VisitSource, VisitValue};
+use log::{LevelFilter, Log, Metadata, Record};
+use serde_json::{Map, Number};
+
+/// 日志格式
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum LogFormat {
+    /// 文本，便于阅读
+    Text,
+    /// 每行一个JSON对象，便于程序解析
+    Json,
+}
+
+/// 输出到标准错误的日志记录器
+struct Logger {
+    level: LevelFilter,
+    format: LogFormat,
+}
+
+/// 初始化日志
+///
+/// # 参数
+/// * `level` - 最低输出级别
+/// * `format` - 日志格式
+pub fn init(level: LevelFilter, format: LogFormat) {
+    let logger = Logger { level, format };
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = match self.format {
+            LogFormat::Text => format_text(record),
+            LogFormat::Json => format_json(record),
+        };
+        let _ = writeln!(std::io::stderr().lock(), "{}", line);
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stderr().flush();
+    }
+}
+
+/// 文本格式：`[级别] 消息 key=value ...`
+fn format_text(record: &Record) -> String {
+    let mut line = format!("[{:<5}] {}", record.level(), record.args());
+    let mut visitor = TextVisitor(&mut line);
+    let _ = record.key_values().visit(&mut visitor);
+    line
+}
+
+/// JSON格式：`{"time":..., "level":..., "target":..., "message":..., "fields":{...}}`
+fn format_json(record: &Record) -> String {
+    let mut fields = Map::new();
+    let _ = record.key_values().visit(&mut JsonVisitor(&mut fields));
+    serde_json::json!({
+        "time": chrono::Local::now().to_rfc3339(),
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+        "fields": fields,
+    })
+    .to_string()
+}
+
+struct TextVisitor<'a>(&'a mut String);
+
+impl<'kvs> VisitSource<'kvs> for TextVisitor<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let mut json_value = serde_json::Value::Null;
+        value.visit(JsonValueVisitor(&mut json_value))?;
+        let text = match json_value {
+            serde_json::Value::Null => "-".to_string(),
+            serde_json::Value::String(s) => s,
+            other => other.to_string(),
+        };
+        self.0.push_str(&format!(" {}={}", key, text));
+        Ok(())
+    }
+}
+
+struct JsonVisitor<'a>(&'a mut Map<String, serde_json::Value>);
+
+impl<'kvs> VisitSource<'kvs> for JsonVisitor<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let mut json_value = serde_json::Value::Null;
+        value.visit(JsonValueVisitor(&mut json_value))?;
+        self.0.insert(key.to_string(), json_value);
+        Ok(())
+    }
+}
+
+/// 把日志字段的值转换为JSON值，未知类型按字符串输出
+struct JsonValueVisitor<'a>(&'a mut serde_json::Value);
+
+impl<'v> VisitValue<'v> for JsonValueVisitor<'_> {
+    fn visit_any(&mut self, value: Value) -> Result<(), kv::Error> {
+        *self.0 = serde_json::Value::String(value.to_string());
+        Ok(())
+    }
+
+    fn visit_null(&mut self) -> Result<(), kv::Error> {
+        *self.0 = serde_json::Value::Null;
+        Ok(())
+    }
+
+    fn visit_u64(&mut self, value: u64) -> Result<(), kv::Error> {
+        *self.0 = serde_json::Value::from(value);
+        Ok(())
+    }
+
+    fn visit_i64(&mut self, value: i64) -> Result<(), kv::Error> {
+        *self.0 = serde_json::Value::from(value);
+        Ok(())
+    }
+
+    fn visit_f64(&mut self, value: f64) -> Result<(), kv::Error> {
+        *self.0 = Number::from_f64(value)
+            .map(serde_json::Value::Number)
+            .unwrap_or(serde_json::Value::Null);
+        Ok(())
+    }
+
+    fn visit_bool(&mut self, value: bool) -> Result<(), kv::Error> {
+        *self.0 = serde_json::Value::Bool(value);
+        Ok(())
+    }
+
+    fn visit_str(&mut self, value: &str) -> Result<(), kv::Error> {
+        *self.0 = serde_json::Value::String(value.to_string());
+        Ok(())
+    }
+}
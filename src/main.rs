@@ -1,25 +1,642 @@
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use log::LevelFilter;
 use native_dialog::DialogBuilder;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use page_source::PageSource;
 
 mod booklet;
+mod color;
+mod composite_source;
+mod config;
+mod cover;
+mod crop;
+mod distribution;
+mod encoding;
+mod error;
+#[cfg(feature = "gui")]
+mod gui;
+mod image_source;
+mod logger;
+mod manifest;
+mod output;
+mod page_source;
 mod pdf_creator;
+mod pdf_info;
+#[cfg(feature = "pdfium")]
 mod pdf_render;
+mod pdf_vector;
+mod preview;
+mod progress;
+mod scan_cleanup;
+mod sheet_split;
+mod stamp;
+mod verify;
 
-fn main() {
-    let path = DialogBuilder::file()
-        // .set_location("~/Desktop")
-        .add_filter("PDF", ["pdf"])
-        .set_title("选择源文件")
-        .open_single_file()
-        .show()
-        .unwrap()
-        .expect("必须选择一个文件");
-    println!("{}", path.to_string_lossy());
+/// 手工装订小册子生成工具
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// 源文件：PDF文件、图像文件（包括多页TIFF）或图像文件夹，不指定时弹窗选择。
+    /// 指定多个时按顺序拼接成一本书，`文件:起始页-结束页` 只使用其中的页面
+    #[arg(value_name = "INPUT")]
+    input: Vec<PathBuf>,
+    /// 输出目录，不指定时使用配置中的目录或弹窗选择
+    #[arg(short, long, value_name = "DIR")]
+    output: Option<PathBuf>,
+    /// 配置文件，不指定时使用用户配置目录下的 config.toml
+    #[arg(long, value_name = "FILE", global = true)]
+    config: Option<PathBuf>,
+    /// 使用预设，指定预设后不再询问装订参数
+    #[arg(short, long, value_name = "NAME")]
+    preset: Option<String>,
+    /// 每册的纸张数：最多张数，或 最少-最多（如 6-10）
+    #[arg(long, value_name = "N|MIN-MAX")]
+    sheets: Option<distribution::SheetRange>,
+    /// 各册纸张数的分配方式
+    #[arg(long, value_enum)]
+    distribution: Option<distribution::Distribution>,
+    /// 按顺序指定每册的纸张数（用逗号分隔，如 6,8,8,6），代替自动分配
+    #[arg(
+        long,
+        value_name = "N",
+        value_delimiter = ',',
+        conflicts_with = "breaks"
+    )]
+    sheet_counts: Vec<u32>,
+    /// 装订方式
+    #[arg(long, value_enum)]
+    binding: Option<Binding>,
+    /// 在这些页之前开始新的一册（源页码，从1开始，不含 --cover-file 的页面，用逗号分隔）
+    #[arg(long, value_name = "PAGE", value_delimiter = ',')]
+    breaks: Vec<u16>,
+    /// 拼接多个源文件时，每个源文件从新的一册开始
+    #[arg(long)]
+    split_files: bool,
+    /// 封面和封底的页数，如 1,1、2,2（含封二、封三）或 1,0（只有封面），只写一个数时两者相同
+    #[arg(long, value_name = "FRONT,BACK", value_parser = cover::CoverSpec::parse_pages)]
+    cover_pages: Option<(u16, u16)>,
+    /// 封面封底的打印方式
+    #[arg(long, value_enum)]
+    cover_print: Option<cover::CoverPrint>,
+    /// 封面封底所在的单独文件，源文件全部为正文；不指定页数时封面封底各1页
+    #[arg(long, value_name = "FILE")]
+    cover_file: Option<PathBuf>,
+    /// 封面之后、封底之前不加空白页（源文件中有封二、封三时使用）
+    #[arg(long)]
+    no_cover_blank: bool,
+    /// 输出更详细的日志，可重复使用（-v 调试，-vv 跟踪）
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
+    /// 只输出警告和错误
+    #[arg(short, long, conflicts_with = "verbose", global = true)]
+    quiet: bool,
+    /// 日志格式
+    #[arg(long, value_enum, default_value_t = logger::LogFormat::Text, global = true)]
+    log_format: logger::LogFormat,
+    /// 只生成折叠后的阅读顺序预览图（PNG），不生成PDF
+    #[arg(long)]
+    preview: bool,
+    /// 预览图中每页缩略图的宽度(px)
+    #[arg(long, value_name = "PX", default_value_t = 160, requires = "preview")]
+    preview_width: u32,
+    /// pdfium动态链接库文件或所在目录，也可以用环境变量 PDFIUM_LIB_PATH 指定
+    #[cfg(feature = "pdfium")]
+    #[arg(long, value_name = "PATH", global = true)]
+    pdfium_path: Option<PathBuf>,
+    /// 按矢量排版：直接复制源页面内容，不渲染为图像
+    #[arg(long)]
+    vector: bool,
+    /// 源图像的分辨率，高于300时缩小到300
+    #[arg(long, value_name = "DPI", value_parser = clap::value_parser!(u32).range(1..))]
+    dpi: Option<u32>,
+    /// 位图排版的页面图像编码：flate 无损，jpeg[:质量] 有损（默认质量85），
+    /// mono[:阈值] 1位黑白并用Flate压缩（默认阈值128，适合纯文字扫描件；不是CCITT G4/JBIG2）
+    #[arg(long, value_name = "ENCODING", value_parser = encoding::ImageEncoding::parse)]
+    image_encoding: Option<encoding::ImageEncoding>,
+    /// 检测彩色页面：黑白页面按灰度渲染，并报告含有彩色页面的纸张
+    #[arg(long)]
+    detect_color: bool,
+    /// 按纸张属性分组，生成插回表 `{源文件名}_sheets.csv`，列出每张纸所在的组
+    #[arg(long, value_enum, value_name = "PROPERTY")]
+    group_sheets: Option<sheet_split::SheetGrouping>,
+    /// 为每组纸张生成单独的PDF文件
+    #[arg(long, requires = "group_sheets")]
+    split_groups: bool,
+    /// 文字水印，如 DRAFT、内部资料（中文需要用 --stamp-font 指定字体）
+    #[arg(long, value_name = "TEXT", group = "watermark_source")]
+    watermark: Option<String>,
+    /// 图像水印（PNG等，可以带透明度）
+    #[arg(long, value_name = "FILE", group = "watermark_source")]
+    watermark_image: Option<PathBuf>,
+    /// 水印的不透明度（0~1）
+    #[arg(long, value_name = "ALPHA", requires = "watermark_source")]
+    watermark_opacity: Option<f64>,
+    /// 水印逆时针旋转的角度(°)
+    #[arg(
+        long,
+        value_name = "DEG",
+        allow_negative_numbers = true,
+        requires = "watermark_source"
+    )]
+    watermark_rotation: Option<f64>,
+    /// 水印在页面上的位置
+    #[arg(long, value_enum, requires = "watermark_source")]
+    watermark_position: Option<stamp::StampPosition>,
+    /// 加水印的页面：all、body、odd、even 或页码范围（如 1-10,15）
+    #[arg(
+        long,
+        value_name = "PAGES",
+        value_parser = stamp::PageFilter::parse,
+        requires = "watermark_source"
+    )]
+    watermark_pages: Option<stamp::PageFilter>,
+    /// 页眉模板，占位符 {title}、{chapter}、{page}、{pages}、{booklet}
+    #[arg(long, value_name = "TEMPLATE")]
+    header: Option<String>,
+    /// 页脚模板，如 "{page}" 或 "{title} · {page}"
+    #[arg(long, value_name = "TEMPLATE")]
+    footer: Option<String>,
+    /// 页眉页脚中的页码为源页码加上这个值（可以为负数）
+    #[arg(long, value_name = "N", allow_negative_numbers = true)]
+    page_number_offset: Option<i32>,
+    /// 水印和页眉页脚使用的TrueType字体文件
+    #[arg(long, value_name = "FILE")]
+    stamp_font: Option<PathBuf>,
+    /// 输出文件已存在时的处理方式，rename 为自动在文件名后加序号；
+    /// 默认询问，无法弹窗询问时报错
+    #[arg(long, value_enum, value_name = "POLICY")]
+    overwrite: Option<output::OverwritePolicy>,
+    /// 不校验排版结果
+    #[arg(long)]
+    no_verify: bool,
+    /// 打开图形界面（不指定源文件时默认打开）
+    #[cfg(feature = "gui")]
+    #[arg(long, conflicts_with_all = ["preview", "replay"])]
+    gui: bool,
+    /// 按任务清单重新生成，不再询问参数；只能改用不影响分册的渲染和输出参数
+    #[arg(
+        long,
+        value_name = "MANIFEST",
+        conflicts_with_all = [
+            "input",
+            "output",
+            "preset",
+            "sheets",
+            "distribution",
+            "sheet_counts",
+            "binding",
+            "breaks",
+            "cover_pages",
+            "cover_print",
+            "cover_file",
+            "no_cover_blank",
+            "split_files"
+        ]
+    )]
+    replay: Option<PathBuf>,
+}
 
-    let out_path = DialogBuilder::file()
-        .set_title("选择输出目标文件夹")
-        .open_single_dir()
-        .show()
-        .unwrap();
+#[derive(Subcommand)]
+enum Command {
+    /// 管理预设
+    Presets {
+        #[command(subcommand)]
+        action: PresetsAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum PresetsAction {
+    /// 列出全部预设
+    List,
+    /// 显示预设与配置文件合并后的装订规则
+    Show {
+        /// 预设名称
+        name: String,
+    },
+}
+
+/// 装订方式
+#[derive(Clone, Copy, ValueEnum)]
+enum Binding {
+    /// 中间装订（骑马钉、线装）
+    Middle,
+    /// 两边装订（胶装，必须裁开）
+    Edge,
+}
+
+impl Cli {
+    fn log_level(&self) -> LevelFilter {
+        if self.quiet {
+            return LevelFilter::Warn;
+        }
+        match self.verbose {
+            0 => LevelFilter::Info,
+            1 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    logger::init(cli.log_level(), cli.log_format);
+    if let Some(Command::Presets { action }) = &cli.command {
+        return exit_code(run_presets_command(action, cli.config.as_deref()));
+    }
+    #[cfg(feature = "gui")]
+    if cli.gui || (cli.input.is_empty() && cli.replay.is_none() && !cli.preview) {
+        return exit_code(run_gui(&cli));
+    }
+    let manifest = match cli.replay.as_deref().map(load_manifest).transpose() {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            log::error!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let binding_rule = match &manifest {
+        Some(manifest) => Ok(manifest.binding_rule.clone()),
+        None => ask_binding_rule(&cli),
+    };
+    let mut binding_rule = match binding_rule {
+        Ok(binding_rule) => binding_rule,
+        Err(e) => {
+            log::error!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    apply_cli_overrides(&cli, &mut binding_rule);
+
+    // 图像直接读取，不需要pdfium
+    #[cfg(feature = "pdfium")]
+    let image_input = binding_rule
+        .source_paths()
+        .into_iter()
+        .all(image_source::is_image_input);
+    // 矢量排版不需要渲染，加载不了pdfium时改用不依赖pdfium的方式读取源文件
+    #[cfg(feature = "pdfium")]
+    let pdfium = if image_input {
+        None
+    } else {
+        match pdf_render::init_pdfium(cli.pdfium_path.as_deref()) {
+            Ok(pdfium) => Some(pdfium),
+            Err(e) if binding_rule.render_mode == booklet::RenderMode::Vector => {
+                log::warn!("{}", e);
+                log::warn!("矢量排版不需要pdfium，继续生成");
+                None
+            }
+            Err(e) => {
+                pdf_render::report_pdfium_error(&e);
+                return ExitCode::FAILURE;
+            }
+        }
+    };
+    let open_file = |path: &Path| {
+        if image_source::is_image_input(path) {
+            return image_source::ImageSource::open(path, binding_rule.image_dpi)
+                .map(|src_pdf| Box::new(src_pdf) as Box<dyn PageSource + '_>);
+        }
+        #[cfg(feature = "pdfium")]
+        if let Some(pdfium) = &pdfium {
+            return pdf_render::PdfDocumentHolder::open(pdfium, &path.to_path_buf(), None)
+                .map(|src_pdf| Box::new(src_pdf) as Box<dyn PageSource + '_>)
+                .map_err(|e| error::BookletError::Source(format!("{}: {}", path.display(), e)));
+        }
+        page_source::PdfFile::open(path).map(|src_pdf| Box::new(src_pdf) as Box<dyn PageSource>)
+    };
+    let src_pdf = composite_source::open_source(&binding_rule, open_file);
+    let src_pdf = match &src_pdf {
+        Ok(src_pdf) => src_pdf.as_ref(),
+        Err(e) => {
+            log::error!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    log::info!(page_count = src_pdf.get_page_count(); "源文件共{}页", src_pdf.get_page_count());
+    let binding_rule =
+        booklet::with_source_breaks(&binding_rule, &src_pdf.get_booklet_breaks()).into_owned();
+    if let Err(e) = booklet::check_sheet_counts(src_pdf.get_page_count(), &binding_rule) {
+        log::error!("{}", e);
+        return ExitCode::FAILURE;
+    }
+    if let Some((w, h)) = src_pdf.get_page_size(0) {
+        log::debug!(
+            "首页尺寸: {:.0}×{:.0} mm",
+            w / pdf_creator::MM_TO_PT,
+            h / pdf_creator::MM_TO_PT
+        );
+    }
+    if let Some(manifest) = &manifest {
+        let booklets = booklet::plan_booklets(src_pdf.get_page_count(), &binding_rule);
+        let changed = manifest.diff_plan(&booklets);
+        if !changed.is_empty() {
+            log::warn!(
+                version = manifest.version.as_str();
+                "排版结果与清单记录不同（清单由v{}生成），不同的册子: {:?}",
+                manifest.version,
+                changed
+            );
+        }
+    }
+    if cli.preview {
+        // 预览用于检查问题，校验失败时只给出警告
+        let report = verify::verify_layout(src_pdf.get_page_count(), &binding_rule);
+        if !report.is_ok() {
+            log::warn!("排版校验失败，{}", report);
+        }
+        let result = booklet::resolve_auto_crop(src_pdf, &binding_rule).and_then(|binding_rule| {
+            preview::create_preview(src_pdf, &binding_rule, cli.preview_width)
+        });
+        if let Err(e) = result {
+            log::error!("生成预览失败: {}", e);
+            return ExitCode::FAILURE;
+        }
+        return ExitCode::SUCCESS;
+    }
+    let cancel = progress::CancelToken::new();
+    // 按清单重新生成时保留原来的清单
+    let save_manifest = manifest.is_none();
+    let result = booklet::create_booklet(
+        src_pdf,
+        &binding_rule,
+        &progress::LogProgress,
+        &cancel,
+        save_manifest,
+    );
+    if let Err(e) = result {
+        log::error!("生成失败: {}", e);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+/// 出错时输出错误并返回失败的退出码，便于脚本判断是否成功
+fn exit_code(result: Result<(), error::BookletError>) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            log::error!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// 读取任务清单，并检查源文件是否与记录相同
+fn load_manifest(manifest_path: &Path) -> Result<manifest::Manifest, error::BookletError> {
+    let manifest = manifest::Manifest::load(manifest_path)?;
+    log::info!(
+        path:% = manifest_path.display(), version = manifest.version.as_str();
+        "按任务清单重新生成: {}（v{}，{}）",
+        manifest_path.display(),
+        manifest.version,
+        manifest.created
+    );
+    manifest.check_source()?;
+    Ok(manifest)
+}
+
+/// 列出或显示预设
+fn run_presets_command(
+    action: &PresetsAction,
+    config_path: Option<&Path>,
+) -> Result<(), error::BookletError> {
+    match action {
+        PresetsAction::List => {
+            let presets = config::list_presets()?;
+            if presets.is_empty() {
+                println!(
+                    "没有预设，在 {} 中添加 .toml 文件",
+                    config::presets_dir()
+                        .map(|dir| dir.display().to_string())
+                        .unwrap_or_default()
+                );
+            }
+            for preset in presets {
+                println!(
+                    "{:<20} {:<30} {}",
+                    preset.name,
+                    preset.description.unwrap_or_default(),
+                    preset.path.display()
+                );
+            }
+        }
+        PresetsAction::Show { name } => {
+            let (binding_rule, _) = config::load_binding_rule(config_path, Some(name))?;
+            print!("{}", config::to_toml(&binding_rule));
+        }
+    }
+    Ok(())
+}
+
+/// 打开图形界面，初始装订规则使用配置文件、预设和命令行参数，不弹窗询问
+#[cfg(feature = "gui")]
+fn run_gui(cli: &Cli) -> Result<(), error::BookletError> {
+    let (mut binding_rule, preset) =
+        config::load_binding_rule(cli.config.as_deref(), cli.preset.as_deref())?;
+    if let Some(input_path) = apply_cli_inputs(cli, &mut binding_rule) {
+        binding_rule.input_path = input_path.clone();
+        if binding_rule.output_dir.as_os_str().is_empty() {
+            binding_rule.output_dir = booklet::BindingRule::new(&input_path).output_dir;
+        }
+    }
+    binding_rule = binding_rule.set_output_path(&cli.output);
+    apply_cli_overrides(cli, &mut binding_rule);
+    gui::run(
+        binding_rule,
+        preset,
+        cli.config.clone(),
+        cli.pdfium_path.clone(),
+    )
+    .map_err(|e| error::BookletError::InvalidRule(format!("无法打开图形界面: {}", e)))
+}
+
+/// 命令行中的装订参数覆盖配置、对话框和任务清单的值
+fn apply_cli_overrides(cli: &Cli, binding_rule: &mut booklet::BindingRule) {
+    if let Some(sheets) = cli.sheets {
+        binding_rule.sheets_per_booklet = sheets;
+    }
+    if let Some(distribution) = cli.distribution {
+        binding_rule.distribution = distribution;
+    }
+    if !cli.sheet_counts.is_empty() {
+        binding_rule.booklet_sheet_counts = cli.sheet_counts.clone();
+    }
+    if let Some(binding) = cli.binding {
+        binding_rule.binding_at_middle = matches!(binding, Binding::Middle);
+    }
+    if !cli.breaks.is_empty() {
+        binding_rule.booklet_breaks = cli.breaks.clone();
+    }
+    apply_cli_cover(cli, &mut binding_rule.cover);
+    if cli.detect_color {
+        binding_rule.color_detection.enabled = true;
+    }
+    apply_cli_sheet_split(cli, &mut binding_rule.sheet_split);
+    apply_cli_stamps(cli, &mut binding_rule.stamps);
+    if let Some(overwrite) = cli.overwrite {
+        binding_rule.overwrite = overwrite;
+    }
+    if let Some(image_encoding) = cli.image_encoding {
+        binding_rule.image_encoding = image_encoding;
+    }
+    if cli.no_verify {
+        binding_rule.verify = false;
+    }
+    if cli.vector {
+        binding_rule.render_mode = booklet::RenderMode::Vector;
+    }
+    if cli.dpi.is_some() {
+        binding_rule.image_dpi = cli.dpi;
+    }
+}
+
+/// 命令行中的封面封底参数覆盖配置和对话框的值
+fn apply_cli_cover(cli: &Cli, cover: &mut cover::CoverSpec) {
+    if let Some((front, back)) = cli.cover_pages {
+        cover.front_pages = front;
+        cover.back_pages = back;
+    }
+    if let Some(print) = cli.cover_print {
+        cover.print = print;
+    }
+    if let Some(path) = &cli.cover_file {
+        cover.file = Some(path.clone());
+        if !cover.is_enabled() {
+            cover.front_pages = 1;
+            cover.back_pages = 1;
+        }
+    }
+    if cli.no_cover_blank {
+        cover.blank_inside = false;
+    }
+}
+
+/// 命令行中的纸张分组参数覆盖配置、对话框和任务清单的值
+fn apply_cli_sheet_split(cli: &Cli, sheet_split: &mut sheet_split::SheetSplit) {
+    if let Some(group_by) = cli.group_sheets {
+        sheet_split.group_by = group_by;
+    }
+    if cli.split_groups {
+        sheet_split.separate_files = true;
+    }
+}
+
+/// 命令行中的水印和页眉页脚参数，水印加在配置中已有的水印之后
+fn apply_cli_stamps(cli: &Cli, stamps: &mut stamp::Stamps) {
+    if cli.watermark.is_some() || cli.watermark_image.is_some() {
+        let mut watermark = stamp::Watermark {
+            text: cli.watermark.clone().unwrap_or_default(),
+            image: cli.watermark_image.clone(),
+            ..Default::default()
+        };
+        if let Some(opacity) = cli.watermark_opacity {
+            watermark.opacity = opacity.clamp(0.0, 1.0);
+        }
+        if let Some(rotation) = cli.watermark_rotation {
+            watermark.rotation = rotation;
+        }
+        if let Some(position) = cli.watermark_position {
+            watermark.position = position;
+        }
+        if let Some(pages) = &cli.watermark_pages {
+            watermark.pages = pages.clone();
+        }
+        stamps.watermarks.push(watermark);
+    }
+    if let Some(template) = &cli.header {
+        stamps.header.template = template.clone();
+    }
+    if let Some(template) = &cli.footer {
+        stamps.footer.template = template.clone();
+    }
+    if let Some(offset) = cli.page_number_offset {
+        stamps.page_number_offset = offset;
+    }
+    if let Some(path) = &cli.stamp_font {
+        stamps.font_file = Some(path.clone());
+    }
+}
+
+/// 命令行中的源文件覆盖配置中的源文件
+///
+/// 多个源文件或指定了页码范围时按顺序拼接，否则只使用一个源文件
+///
+/// # 返回
+/// 命令行中指定了源文件时返回第一个源文件的路径
+fn apply_cli_inputs(cli: &Cli, binding_rule: &mut booklet::BindingRule) -> Option<PathBuf> {
+    let mut parts: Vec<composite_source::SourcePart> = cli
+        .input
+        .iter()
+        .map(|arg| composite_source::SourcePart::parse(arg))
+        .collect();
+    let first = parts.first()?.path.clone();
+    for part in parts.iter_mut().skip(1) {
+        part.new_booklet = cli.split_files;
+    }
+    binding_rule.sources = if parts.len() == 1 && parts[0].pages.is_none() {
+        Vec::new()
+    } else {
+        parts
+    };
+    Some(first)
+}
+
+/// 确定源文件、输出目录和装订参数
+///
+/// 依次使用配置文件、预设和对话框，后面的覆盖前面的；命令行中的装订参数由调用方
+/// 用 `apply_cli_overrides` 覆盖。使用预设时不再询问装订参数。
+fn ask_binding_rule(cli: &Cli) -> Result<booklet::BindingRule, error::BookletError> {
+    let (mut binding_rule, preset) =
+        config::load_binding_rule(cli.config.as_deref(), cli.preset.as_deref())?;
+    if let Some(preset) = &preset {
+        log::info!(preset = preset.as_str(); "使用预设: {}", preset);
+    }
+    let path = match apply_cli_inputs(cli, &mut binding_rule) {
+        Some(path) => path,
+        // 配置中已经指定了要拼接的源文件
+        None if !binding_rule.sources.is_empty() => binding_rule.sources[0].path.clone(),
+        None => DialogBuilder::file()
+            // .set_location("~/Desktop")
+            .add_filter("PDF", ["pdf"])
+            .add_filter("图像", image_source::IMAGE_EXTENSIONS)
+            .set_title("选择源文件")
+            .open_single_file()
+            .show()
+            .unwrap()
+            .expect("必须选择一个文件"),
+    };
+    log::info!(path:% = path.display(); "源文件: {}", path.display());
+
+    let out_path = if cli.output.is_some() {
+        cli.output.clone()
+    } else if !binding_rule.output_dir.as_os_str().is_empty() {
+        Some(binding_rule.output_dir.clone())
+    } else {
+        DialogBuilder::file()
+            .set_title("选择输出目标文件夹")
+            .open_single_dir()
+            .show()
+            .unwrap()
+    };
+    if preset.is_none() {
+        ask_binding_params(&mut binding_rule);
+    }
+
+    let input_path = path;
+    binding_rule.input_path = input_path.clone();
+    binding_rule.output_dir = booklet::BindingRule::new(&input_path).output_dir;
+    Ok(binding_rule.set_output_path(&out_path))
+}
+
+/// 通过对话框询问封面、裁剪和扫描清理
+///
+/// 选择裁剪或清理时，配置中已有的参数保持不变，没有时使用默认参数
+fn ask_binding_params(binding_rule: &mut booklet::BindingRule) {
     let has_cover = DialogBuilder::message()
         .set_level(native_dialog::MessageLevel::Info)
         .set_title("装订参数")
@@ -37,20 +654,43 @@ fn main() {
             .show()
             .unwrap_or(false);
     }
+    let auto_crop = DialogBuilder::message()
+        .set_level(native_dialog::MessageLevel::Info)
+        .set_title("装订参数")
+        .set_text("是否自动裁剪页面空白边")
+        .confirm()
+        .show()
+        .unwrap_or(false);
+    let clean_scan = DialogBuilder::message()
+        .set_level(native_dialog::MessageLevel::Info)
+        .set_title("装订参数")
+        .set_text("是否清理扫描页面（纠偏、去噪点、背景变白）")
+        .confirm()
+        .show()
+        .unwrap_or(false);
 
-    // println!("{}", out_path.to_string_lossy());
-    let pdfium = pdf_render::init_pdfium();
-    let input_path = path;
-    let binding_rule = booklet::BindingRule::new(&input_path);
-    let binding_rule = booklet::BindingRule {
-        binding_at_middle: true,
-        sheets_per_booklet: 10,
-        has_cover,
-        keep_cover,
-        ..binding_rule
-    }
-    .set_output_path(&out_path);
-    let src_pdf = pdf_render::PdfDocumentHolder::new(&pdfium, &input_path, None);
-    dbg!(src_pdf.get_page_count());
-    booklet::create_booklet(&src_pdf, &binding_rule);
+    // 配置中已有封面封底页数时保持不变，只改变打印方式
+    let print = if keep_cover {
+        cover::CoverPrint::WithBody
+    } else {
+        cover::CoverPrint::Omit
+    };
+    binding_rule.cover = match (has_cover, binding_rule.cover.is_enabled()) {
+        (false, _) => cover::CoverSpec::default(),
+        (true, false) => cover::CoverSpec::single(print),
+        (true, true) => cover::CoverSpec {
+            print,
+            ..binding_rule.cover.clone()
+        },
+    };
+    binding_rule.crop = match (auto_crop, binding_rule.crop) {
+        (false, _) => crop::CropMode::None,
+        (true, crop::CropMode::None) => crop::CropMode::auto(),
+        (true, crop) => crop,
+    };
+    if !clean_scan {
+        binding_rule.scan_cleanup = scan_cleanup::ScanCleanup::default();
+    } else if !binding_rule.scan_cleanup.is_enabled() {
+        binding_rule.scan_cleanup = scan_cleanup::ScanCleanup::scanned_book();
+    }
 }
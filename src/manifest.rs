@@ -0,0 +1,381 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::{self, Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::booklet::{self, BindingRule, BookletConfig, BookletRange};
+use crate::color;
+use crate::cover::CoverSpec;
+use crate::distribution::Distribution;
+use crate::error::BookletError;
+use crate::image_source;
+use crate::output;
+use crate::pdf_creator::{self, BookletSummary, SheetSide};
+
+/// 任务清单，记录一次生成的全部参数和结果，可以用来重新生成相同的册子
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    /// 生成程序名称
+    pub tool: String,
+    /// 生成程序版本
+    pub version: String,
+    /// 生成时间（RFC 3339）
+    pub created: String,
+    /// 源文件信息
+    pub source: SourceInfo,
+    /// 实际使用的装订规则（自动裁剪已替换为检测出的裁剪框），其中的路径都是绝对路径
+    pub binding_rule: BindingRule,
+    /// `Distribution::Auto` 计算出的各册纸张数量，其他分配方式或指定了每册纸张数时为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub booklet_config: Option<BookletConfig>,
+    /// 每一册的排版结果
+    pub booklets: Vec<BookletRecord>,
+}
+
+/// 源文件信息
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SourceInfo {
+    /// 源文件的绝对路径
+    pub path: PathBuf,
+    /// 页数
+    pub page_count: u16,
+    /// 文件的SHA-256（十六进制）
+    pub sha256: String,
+}
+
+/// 一册的排版结果
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BookletRecord {
+    /// 册子编号（从1开始）
+    pub booklet_num: u16,
+    /// 输出文件名
+    pub file: String,
+    /// 文件已存在，按覆盖策略跳过
+    pub skipped: bool,
+    /// 文件大小（字节）
+    pub file_size: u64,
+    /// 开始页索引(包含)
+    pub start_page: u16,
+    /// 结束页索引(不包含)，包含末尾填充的空白页
+    pub end_page: u16,
+    /// 本册第一页的源页码（从1开始）
+    pub first_page: Option<u16>,
+    /// 本册最后一页的源页码（从1开始）
+    pub last_page: Option<u16>,
+    /// 纸张数
+    pub sheet_count: usize,
+    /// 每面纸上的页面，按输出PDF的页面顺序
+    pub sides: Vec<SideRecord>,
+    /// 空白页的位置
+    pub blanks: Vec<BlankPosition>,
+    /// 含有彩色页面的纸张编号（从1开始），没有检测彩色页面时为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color_sheets: Option<Vec<usize>>,
+}
+
+/// 一面纸上的两个页面
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SideRecord {
+    /// 纸张编号（从1开始）
+    pub sheet: usize,
+    /// 是否是背面
+    pub is_back: bool,
+    /// 上半部分的源页码，空白页为 `null`
+    pub top: Option<u16>,
+    /// 下半部分的源页码，空白页为 `null`
+    pub bottom: Option<u16>,
+}
+
+/// 空白页所在的位置
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BlankPosition {
+    /// 纸张编号（从1开始）
+    pub sheet: usize,
+    /// 是否是背面
+    pub is_back: bool,
+    /// 是否在上半部分
+    pub is_top: bool,
+}
+
+impl Manifest {
+    /// 根据生成结果创建清单
+    ///
+    /// # 参数
+    /// * `source_page_count` - 源文件页数
+    /// * `binding_rule` - 实际使用的装订规则
+    /// * `booklets` - 每一册的范围
+    /// * `summaries` - 每一册的生成结果
+    /// * `page_colors` - 每页是否是彩色，没有检测时为 `None`
+    pub fn new(
+        source_page_count: u16,
+        binding_rule: &BindingRule,
+        booklets: &[BookletRange],
+        summaries: &[BookletSummary],
+        page_colors: Option<&[bool]>,
+    ) -> io::Result<Self> {
+        let records = booklets
+            .iter()
+            .zip(summaries)
+            .map(|(booklet, summary)| {
+                let sides = pdf_creator::calc_sheet_sides(booklet, binding_rule);
+                let mut record =
+                    booklet_record(source_page_count, binding_rule, booklet, summary, &sides);
+                record.color_sheets =
+                    page_colors.map(|page_colors| color::color_sheets(&sides, page_colors));
+                record
+            })
+            .collect();
+        Ok(Self {
+            tool: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            created: chrono::Local::now().to_rfc3339(),
+            source: SourceInfo {
+                path: path::absolute(&binding_rule.input_path)?,
+                page_count: source_page_count,
+                sha256: source_sha256(binding_rule)?,
+            },
+            binding_rule: absolute_paths(binding_rule)?,
+            booklet_config: (binding_rule.booklet_sheet_counts.is_empty()
+                && binding_rule.distribution == Distribution::Auto)
+                .then(|| booklet::booklet_config(source_page_count, binding_rule)),
+            booklets: records,
+        })
+    }
+
+    /// 读取清单文件，旧版本清单中的封面设置转换为 `cover`
+    pub fn load(path: &Path) -> Result<Self, BookletError> {
+        let text = fs::read_to_string(path)?;
+        let invalid = |e: serde_json::Error| {
+            BookletError::InvalidRule(format!("无法读取清单 {}: {}", path.display(), e))
+        };
+        let mut value: serde_json::Value = serde_json::from_str(&text).map_err(invalid)?;
+        if let Some(rule) = value
+            .get_mut("binding_rule")
+            .and_then(serde_json::Value::as_object_mut)
+        {
+            let has_cover = rule.remove("has_cover").and_then(|v| v.as_bool());
+            let keep_cover = rule.remove("keep_cover").and_then(|v| v.as_bool());
+            if let Some(has_cover) = has_cover {
+                let cover = CoverSpec::from_legacy(has_cover, keep_cover.unwrap_or(false));
+                rule.entry("cover")
+                    .or_insert(serde_json::to_value(cover).map_err(invalid)?);
+            }
+        }
+        serde_json::from_value(value).map_err(invalid)
+    }
+
+    /// 写入清单文件（JSON）
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        output::write_atomic(path, json.as_bytes())
+    }
+
+    /// 检查源文件与清单记录的是否相同
+    pub fn check_source(&self) -> Result<(), BookletError> {
+        let sha256 = source_sha256(&self.binding_rule)?;
+        if sha256 != self.source.sha256 {
+            return Err(BookletError::InvalidRule(format!(
+                "源文件 {} 与清单记录的不同（SHA-256 {}，清单中为 {}）",
+                self.source.path.display(),
+                sha256,
+                self.source.sha256
+            )));
+        }
+        Ok(())
+    }
+
+    /// 检查按清单中的装订规则排版的结果是否与记录相同
+    ///
+    /// 程序版本不同时排版算法可能改变，返回不同的册子编号
+    pub fn diff_plan(&self, booklets: &[BookletRange]) -> Vec<u16> {
+        let mut changed: Vec<u16> = booklets
+            .iter()
+            .filter(|booklet| {
+                self.booklets
+                    .iter()
+                    .find(|record| record.booklet_num == booklet.booklet_num)
+                    .is_none_or(|record| {
+                        record.start_page != booklet.start_page
+                            || record.end_page != booklet.end_page
+                    })
+            })
+            .map(|booklet| booklet.booklet_num)
+            .collect();
+        changed.extend(
+            self.booklets
+                .iter()
+                .map(|record| record.booklet_num)
+                .filter(|&num| !booklets.iter().any(|booklet| booklet.booklet_num == num)),
+        );
+        changed
+    }
+}
+
+/// 清单文件路径：输出目录下的 `{源文件名}_manifest.json`
+pub fn manifest_path(binding_rule: &BindingRule) -> PathBuf {
+    let name = binding_rule
+        .input_path
+        .file_prefix()
+        .expect("没有文件名")
+        .to_string_lossy();
+    binding_rule
+        .output_dir
+        .join(format!("{}_manifest.json", name))
+}
+
+/// 把装订规则中的文件路径转为绝对路径，在其他工作目录中也能按清单重新生成
+fn absolute_paths(binding_rule: &BindingRule) -> io::Result<BindingRule> {
+    let absolute = |path: &mut PathBuf| -> io::Result<()> {
+        if !path.as_os_str().is_empty() {
+            *path = path::absolute(&*path)?;
+        }
+        Ok(())
+    };
+    let mut binding_rule = binding_rule.clone();
+    absolute(&mut binding_rule.input_path)?;
+    absolute(&mut binding_rule.output_dir)?;
+    for part in &mut binding_rule.sources {
+        absolute(&mut part.path)?;
+    }
+    let stamps = &mut binding_rule.stamps;
+    for path in binding_rule
+        .cover
+        .file
+        .iter_mut()
+        .chain(stamps.font_file.iter_mut())
+        .chain(
+            stamps
+                .watermarks
+                .iter_mut()
+                .flat_map(|w| w.image.iter_mut()),
+        )
+    {
+        absolute(path)?;
+    }
+    Ok(binding_rule)
+}
+
+/// 生成一册的记录
+fn booklet_record(
+    page_count: u16,
+    binding_rule: &BindingRule,
+    booklet: &BookletRange,
+    summary: &BookletSummary,
+    sides: &[SheetSide],
+) -> BookletRecord {
+    let page_number = |page_idx: u16| (page_idx < page_count).then(|| page_idx + 1);
+    let side_records: Vec<SideRecord> = sides
+        .iter()
+        .enumerate()
+        .map(|(i, side)| {
+            let (top, bottom) = side.top_bottom(binding_rule.binding_at_middle);
+            SideRecord {
+                sheet: i / 2 + 1,
+                is_back: side.is_back,
+                top: page_number(top),
+                bottom: page_number(bottom),
+            }
+        })
+        .collect();
+    let blanks = side_records
+        .iter()
+        .flat_map(|side| {
+            [(true, side.top), (false, side.bottom)]
+                .into_iter()
+                .filter(|(_, page)| page.is_none())
+                .map(|(is_top, _)| BlankPosition {
+                    sheet: side.sheet,
+                    is_back: side.is_back,
+                    is_top,
+                })
+        })
+        .collect();
+    let page_range = pdf_creator::source_page_range(sides, page_count);
+    BookletRecord {
+        booklet_num: booklet.booklet_num,
+        file: summary
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        skipped: summary.skipped,
+        file_size: summary.file_size,
+        start_page: booklet.start_page,
+        end_page: booklet.end_page,
+        first_page: page_range.map(|(first, _)| first),
+        last_page: page_range.map(|(_, last)| last),
+        sheet_count: sides.len().div_ceil(2),
+        sides: side_records,
+        blanks,
+        color_sheets: None,
+    }
+}
+
+/// 计算源文件的SHA-256
+///
+/// 拼接多个源文件时依次计算每个源文件的SHA-256和页码范围，
+/// 封面封底在单独的文件中时再加上封面文件
+pub fn source_sha256(binding_rule: &BindingRule) -> io::Result<String> {
+    let cover_file = binding_rule.cover.file.as_deref();
+    if binding_rule.sources.is_empty() && cover_file.is_none() {
+        return sha256_file(&binding_rule.input_path);
+    }
+    let mut hasher = Sha256::new();
+    if binding_rule.sources.is_empty() {
+        hasher.update(sha256_file(&binding_rule.input_path)?);
+        hasher.update([0]);
+    }
+    for part in &binding_rule.sources {
+        hasher.update(sha256_file(&part.path)?);
+        if let Some((first, last)) = part.pages {
+            hasher.update(format!(":{}-{}", first, last));
+        }
+        hasher.update([0]);
+    }
+    if let Some(path) = cover_file {
+        hasher.update("cover:");
+        hasher.update(sha256_file(path)?);
+        hasher.update([0]);
+    }
+    Ok(to_hex(hasher))
+}
+
+/// 计算文件的SHA-256
+///
+/// 图像文件夹按排版顺序依次计算其中每个图像的文件名和内容，
+/// 增删、改名或修改图像都会改变结果
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    if path.is_dir() {
+        for file in image_source::list_images(path)? {
+            hasher.update(file.file_name().unwrap_or_default().as_encoded_bytes());
+            hasher.update([0]);
+            hash_file(&mut hasher, &file)?;
+        }
+    } else {
+        hash_file(&mut hasher, path)?;
+    }
+    Ok(to_hex(hasher))
+}
+
+fn to_hex(hasher: Sha256) -> String {
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn hash_file(hasher: &mut Sha256, path: &Path) -> io::Result<()> {
+    let mut file = fs::File::open(path)?;
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        hasher.update(&buf[..n]);
+    }
+}
@@ -0,0 +1,370 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use native_dialog::{DialogBuilder, MessageLevel};
+use serde::{Deserialize, Serialize};
+
+use crate::error::BookletError;
+
+/// 输出文件已存在时的处理方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum OverwritePolicy {
+    /// 弹窗询问，无法弹窗时报错
+    #[default]
+    Ask,
+    /// 跳过，保留已有文件
+    Skip,
+    /// 覆盖
+    Overwrite,
+    /// 自动在文件名后加序号，如 `book_01 (1).pdf`
+    #[value(name = "rename")]
+    AutoSuffix,
+}
+
+impl fmt::Display for OverwritePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            OverwritePolicy::Ask => "询问",
+            OverwritePolicy::Skip => "跳过",
+            OverwritePolicy::Overwrite => "覆盖",
+            OverwritePolicy::AutoSuffix => "自动加序号",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// 按覆盖策略确定输出路径
+///
+/// # 返回
+/// 返回实际写入的路径，跳过时返回 `None`；需要询问但无法弹窗（如没有图形环境）时返回错误，
+/// 不默默跳过
+pub fn resolve_output_path(path: &Path, policy: OverwritePolicy) -> io::Result<Option<PathBuf>> {
+    if !path.exists() {
+        return Ok(Some(path.to_path_buf()));
+    }
+    match policy {
+        OverwritePolicy::Overwrite => Ok(Some(path.to_path_buf())),
+        OverwritePolicy::Skip => Ok(None),
+        OverwritePolicy::AutoSuffix => Ok(Some(next_free_path(path))),
+        OverwritePolicy::Ask => {
+            let answer = DialogBuilder::message()
+                .set_level(MessageLevel::Warning)
+                .set_title("文件已存在")
+                .set_text(format!("{}\n已存在，是否覆盖？", path.display()))
+                .confirm()
+                .show();
+            match answer {
+                Ok(true) => Ok(Some(path.to_path_buf())),
+                Ok(false) => Ok(None),
+                Err(e) => Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!(
+                        "{} 已存在，无法询问是否覆盖（{}），请用 --overwrite 或 overwrite 指定处理方式",
+                        path.display(),
+                        e
+                    ),
+                )),
+            }
+        }
+    }
+}
+
+/// 查找不存在的文件名：`name (1).ext`、`name (2).ext` ...
+fn next_free_path(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let ext = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|n| path.with_file_name(format!("{} ({}){}", stem, n, ext)))
+        .find(|candidate| !candidate.exists())
+        .unwrap()
+}
+
+/// 按覆盖策略写入文件，见 `resolve_output_path` 和 `write_atomic`
+///
+/// # 返回
+/// 返回实际写入的路径，跳过时返回 `None`
+pub fn write_resolved(
+    path: &Path,
+    data: &[u8],
+    policy: OverwritePolicy,
+) -> io::Result<Option<PathBuf>> {
+    let Some(path) = resolve_output_path(path, policy)? else {
+        return Ok(None);
+    };
+    write_atomic(&path, data)?;
+    Ok(Some(path))
+}
+
+/// 源文件名（不含扩展名），用于生成输出文件名
+pub fn source_name(input_path: &Path) -> Result<String, BookletError> {
+    input_path
+        .file_prefix()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| {
+            BookletError::Source(format!("源文件路径 {} 没有文件名", input_path.display()))
+        })
+}
+
+/// 先写入同目录下的临时文件，完成后重命名为目标文件
+///
+/// 写入失败时删除临时文件，目标文件不会出现不完整的内容。
+pub fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let tmp_path = path.with_file_name(format!(".{}.part", file_name));
+    let result = (|| {
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+/// 默认文件名模板，与 `input_01.pdf` 的命名方式相同
+pub const DEFAULT_FILE_NAME_TEMPLATE: &str = "{name}_{num}";
+
+/// 文件名模板中可以使用的值
+pub struct FileNameFields<'a> {
+    /// 源文件名（不含扩展名）
+    pub name: &'a str,
+    /// 册子编号（从1开始）
+    pub booklet_num: u16,
+    /// 册子总数
+    pub booklet_count: u16,
+    /// 本册第一页在源文件中的页码（从1开始）
+    pub first_page: u16,
+    /// 本册最后一页在源文件中的页码（从1开始）
+    pub last_page: u16,
+    /// 本册纸张数
+    pub sheet_count: usize,
+    /// 生成日期
+    pub date: chrono::NaiveDate,
+}
+
+/// 按模板生成文件名，没有扩展名时自动加上 `.pdf`
+///
+/// 占位符：
+/// * `{name}` - 源文件名
+/// * `{num}` - 册子编号，默认补零到册子总数的位数（至少2位）
+/// * `{count}` - 册子总数
+/// * `{first}` / `{last}` - 本册第一页/最后一页的源页码
+/// * `{sheets}` - 本册纸张数
+/// * `{date}` - 生成日期，默认格式 `%Y%m%d`
+///
+/// 数字占位符可以用 `{num:3}` 指定补零位数，日期可以用 `{date:%Y-%m-%d}` 指定格式，
+/// `{{` 和 `}}` 表示花括号本身。
+///
+/// # 返回
+/// 模板有误时返回错误说明
+pub fn render_file_name(template: &str, fields: &FileNameFields) -> Result<String, String> {
+    let mut file_name = expand_template(template, "文件名模板", |key, spec| {
+        render_placeholder(key, spec, fields)
+    })?;
+
+    if file_name.is_empty() {
+        return Err(format!("文件名模板 \"{}\" 生成的文件名为空", template));
+    }
+    if file_name.contains(['/', '\\']) {
+        return Err(format!("文件名 \"{}\" 不能包含路径分隔符", file_name));
+    }
+    if Path::new(&file_name).extension().is_none() {
+        file_name.push_str(".pdf");
+    }
+    Ok(file_name)
+}
+
+/// 展开模板中的 `{占位符}` 和 `{占位符:格式}`，`{{` 和 `}}` 表示花括号本身
+///
+/// # 参数
+/// * `template` - 模板
+/// * `label` - 模板的名称，用于错误说明
+/// * `render` - 按占位符名称和格式生成内容
+///
+/// # 返回
+/// 花括号不匹配或 `render` 返回错误时返回错误说明
+pub fn expand_template(
+    template: &str,
+    label: &str,
+    mut render: impl FnMut(&str, Option<&str>) -> Result<String, String>,
+) -> Result<String, String> {
+    let mut text = String::new();
+    let mut rest = template;
+    while let Some(pos) = rest.find(['{', '}']) {
+        text.push_str(&rest[..pos]);
+        let brace = &rest[pos..];
+        if brace.starts_with("{{") || brace.starts_with("}}") {
+            text.push_str(&brace[..1]);
+            rest = &brace[2..];
+            continue;
+        }
+        if brace.starts_with('}') {
+            return Err(format!("{} \"{}\" 中有多余的 '}}'", label, template));
+        }
+        let end = brace
+            .find('}')
+            .ok_or_else(|| format!("{} \"{}\" 中的 '{{' 没有闭合", label, template))?;
+        let (key, spec) = match brace[1..end].split_once(':') {
+            Some((key, spec)) => (key, Some(spec)),
+            None => (&brace[1..end], None),
+        };
+        text.push_str(&render(key, spec)?);
+        rest = &brace[end + 1..];
+    }
+    text.push_str(rest);
+    Ok(text)
+}
+
+/// 按 `{占位符:位数}` 的位数补零，没有指定位数时使用 `default_width`
+pub fn pad_number(
+    key: &str,
+    spec: Option<&str>,
+    number: usize,
+    default_width: usize,
+) -> Result<String, String> {
+    let width = match spec {
+        Some(spec) => spec
+            .parse::<usize>()
+            .map_err(|_| format!("占位符 {{{}:{}}} 的位数无效", key, spec))?,
+        None => default_width,
+    };
+    Ok(format!("{:0width$}", number, width = width))
+}
+
+/// 生成单个占位符的内容
+fn render_placeholder(
+    key: &str,
+    spec: Option<&str>,
+    fields: &FileNameFields,
+) -> Result<String, String> {
+    let number = match key {
+        "name" => return Ok(fields.name.to_string()),
+        "date" => {
+            let format = spec.unwrap_or("%Y%m%d");
+            let items: Vec<_> = chrono::format::StrftimeItems::new(format).collect();
+            if items.contains(&chrono::format::Item::Error) {
+                return Err(format!("日期格式 \"{}\" 无效", format));
+            }
+            return Ok(fields.date.format_with_items(items.into_iter()).to_string());
+        }
+        "num" => fields.booklet_num as usize,
+        "count" => fields.booklet_count as usize,
+        "first" => fields.first_page as usize,
+        "last" => fields.last_page as usize,
+        "sheets" => fields.sheet_count,
+        _ => return Err(format!("未知的文件名占位符 {{{}}}", key)),
+    };
+    let default_width = if key == "num" {
+        fields.booklet_count.to_string().len().max(2)
+    } else {
+        0
+    };
+    pad_number(key, spec, number, default_width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(booklet_num: u16, booklet_count: u16) -> FileNameFields<'static> {
+        FileNameFields {
+            name: "book",
+            booklet_num,
+            booklet_count,
+            first_page: 41,
+            last_page: 80,
+            sheet_count: 10,
+            date: chrono::NaiveDate::from_ymd_opt(2024, 3, 9).unwrap(),
+        }
+    }
+
+    fn render(template: &str) -> Result<String, String> {
+        render_file_name(template, &fields(2, 12))
+    }
+
+    #[test]
+    fn each_placeholder() {
+        assert_eq!(render(DEFAULT_FILE_NAME_TEMPLATE).unwrap(), "book_02.pdf");
+        assert_eq!(render("{name}").unwrap(), "book.pdf");
+        assert_eq!(render("{count}").unwrap(), "12.pdf");
+        assert_eq!(render("p{first}-{last}").unwrap(), "p41-80.pdf");
+        assert_eq!(render("{sheets}张").unwrap(), "10张.pdf");
+        assert_eq!(render("{date}").unwrap(), "20240309.pdf");
+        assert_eq!(render("{date:%Y-%m-%d}").unwrap(), "2024-03-09.pdf");
+        assert_eq!(render("{{{num}}}").unwrap(), "{02}.pdf");
+        // 有扩展名时不再加 .pdf
+        assert_eq!(render("{name}_{num}.PDF").unwrap(), "book_02.PDF");
+    }
+
+    #[test]
+    fn padding_width() {
+        // 默认补零到册子总数的位数，至少2位
+        assert_eq!(render_file_name("{num}", &fields(2, 9)).unwrap(), "02.pdf");
+        assert_eq!(
+            render_file_name("{num}", &fields(2, 120)).unwrap(),
+            "002.pdf"
+        );
+        assert_eq!(render("{num:4}").unwrap(), "0002.pdf");
+        assert_eq!(render("{num:0}").unwrap(), "2.pdf");
+        assert_eq!(render("{first:3}").unwrap(), "041.pdf");
+        // 位数小于数字的长度时不截断
+        assert_eq!(render("{last:1}").unwrap(), "80.pdf");
+        assert_eq!(pad_number("page", None, 7, 0).unwrap(), "7");
+        assert!(pad_number("page", Some("-1"), 7, 0).is_err());
+        assert!(render("{num:x}").is_err());
+    }
+
+    #[test]
+    fn unknown_placeholders_and_braces() {
+        assert!(render("{title}").is_err());
+        assert!(render("{}").is_err());
+        assert!(render("{name").is_err());
+        assert!(render("name}").is_err());
+        assert!(render("{date:%Q}").is_err());
+        assert!(render("").is_err());
+    }
+
+    #[test]
+    fn path_separators_are_rejected() {
+        assert!(render("{name}/{num}").is_err());
+        assert!(render("out\\{num}").is_err());
+        let fields = FileNameFields {
+            name: "a/b",
+            ..fields(1, 1)
+        };
+        assert!(render_file_name("{name}", &fields).is_err());
+    }
+
+    #[test]
+    fn expand_template_passes_specs() {
+        let mut keys = Vec::new();
+        let text = expand_template("a{x}b{y:2}c", "模板", |key, spec| {
+            keys.push((key.to_string(), spec.map(str::to_string)));
+            Ok(key.to_uppercase())
+        })
+        .unwrap();
+        assert_eq!(text, "aXbYc");
+        assert_eq!(
+            keys,
+            vec![
+                ("x".to_string(), None),
+                ("y".to_string(), Some("2".to_string()))
+            ]
+        );
+    }
+}
@@ -0,0 +1,223 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use oxidize_pdf::parser::{ParseOptions, PdfDocument, PdfReader};
+
+use crate::error::BookletError;
+
+/// 源页面的来源，排版只通过这个接口读取源文件
+///
+/// 位图排版需要渲染页面，矢量排版需要页面所在的PDF文件和页号。
+/// 不支持的操作返回 `BookletError::Source`。
+pub trait PageSource {
+    /// 获取总页数
+    fn get_page_count(&self) -> u16;
+
+    /// 获取文档信息
+    ///
+    /// # 参数
+    /// * `key` - 文档信息字典中的键，如 `Title`、`Author`、`Subject`、`Keywords`
+    ///
+    /// # 返回
+    /// 没有或为空时返回 `None`
+    fn get_info(&self, key: &str) -> Option<String>;
+
+    /// 获取指定页面旋转后的图像数据，用于位图排版
+    ///
+    /// # 参数
+    /// * `page_idx` - 页面索引（从0开始）
+    /// * `reverse_image` - 是否旋转270°，否则旋转90°
+    ///
+    /// # 返回
+    /// 返回 (width, height, rgba_bytes) 元组
+    fn get_page_image(
+        &self,
+        page_idx: u16,
+        reverse_image: bool,
+    ) -> Result<(u32, u32, Vec<u8>), BookletError>;
+
+    /// 获取指定页面不旋转的低分辨率图像，用于分析页面内容和预览
+    ///
+    /// # 参数
+    /// * `page_idx` - 页面索引（从0开始）
+    /// * `target_width` - 图像宽度（像素）
+    ///
+    /// # 返回
+    /// 返回 (width, height, rgba_bytes) 元组
+    fn get_page_preview(
+        &self,
+        page_idx: u16,
+        target_width: i32,
+    ) -> Result<(u32, u32, Vec<u8>), BookletError>;
+
+    /// 页面尺寸（pt），不知道时返回 `None`
+    ///
+    /// # 参数
+    /// * `page_idx` - 页面索引（从0开始）
+    fn get_page_size(&self, page_idx: u16) -> Option<(f64, f64)> {
+        let _ = page_idx;
+        None
+    }
+
+    /// 页面是否含有图像（照片、插图或扫描图像），用于按纸张属性分组
+    ///
+    /// 不知道时返回 `None`
+    ///
+    /// # 参数
+    /// * `page_idx` - 页面索引（从0开始）
+    fn has_images(&self, page_idx: u16) -> Option<bool> {
+        let _ = page_idx;
+        None
+    }
+
+    /// 章节：一级书签所在的页面索引（升序）和标题，用于页眉页脚
+    fn get_chapters(&self) -> Vec<(u16, String)> {
+        Vec::new()
+    }
+
+    /// 必须开始新的一册的页面索引（升序），如拼接源文件时要求单独成册的部分的第一页
+    fn get_booklet_breaks(&self) -> Vec<u16> {
+        Vec::new()
+    }
+
+    /// 页面所在的PDF文件和在该文件中的页面索引，用于矢量排版
+    ///
+    /// 页面不是来自PDF文件时返回 `None`
+    fn get_pdf_page(&self, page_idx: u16) -> Option<(&Path, u16)> {
+        let _ = page_idx;
+        None
+    }
+}
+
+/// 用oxidize-pdf读取的PDF源文件，不依赖pdfium
+///
+/// 只能按矢量排版，不能渲染页面，因此也不支持自动裁剪、扫描清理和预览
+pub struct PdfFile {
+    path: PathBuf,
+    page_count: u16,
+    /// 文档信息，键为PDF名称
+    info: Vec<(&'static str, String)>,
+}
+
+impl PdfFile {
+    /// 打开PDF文件，读取页数和文档信息
+    ///
+    /// # 参数
+    /// * `path` - PDF文件路径
+    pub fn open(path: &Path) -> Result<Self, BookletError> {
+        let (doc, page_count) = open_pdf(path)?;
+        let metadata = doc.metadata()?;
+        let info = [
+            ("Title", metadata.title),
+            ("Author", metadata.author),
+            ("Subject", metadata.subject),
+            ("Keywords", metadata.keywords),
+            ("Creator", metadata.creator),
+            ("Producer", metadata.producer),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| {
+            let value = value?.trim().to_string();
+            (!value.is_empty()).then_some((key, value))
+        })
+        .collect();
+        Ok(Self {
+            path: path.to_path_buf(),
+            page_count,
+            info,
+        })
+    }
+}
+
+impl PageSource for PdfFile {
+    fn get_page_count(&self) -> u16 {
+        self.page_count
+    }
+
+    fn get_info(&self, key: &str) -> Option<String> {
+        self.info
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, value)| value.clone())
+    }
+
+    fn get_page_image(
+        &self,
+        _page_idx: u16,
+        _reverse_image: bool,
+    ) -> Result<(u32, u32, Vec<u8>), BookletError> {
+        Err(not_renderable())
+    }
+
+    fn get_page_preview(
+        &self,
+        _page_idx: u16,
+        _target_width: i32,
+    ) -> Result<(u32, u32, Vec<u8>), BookletError> {
+        Err(not_renderable())
+    }
+
+    fn get_pdf_page(&self, page_idx: u16) -> Option<(&Path, u16)> {
+        (page_idx < self.page_count).then_some((self.path.as_path(), page_idx))
+    }
+}
+
+/// 用oxidize-pdf打开PDF文件，检查是否加密和能否读取页面
+///
+/// 交叉引用流损坏或无法解析时，宽松模式会重建交叉引用表，但对象流中的对象
+/// （PDF 1.5起常用于存放页面树）会全部丢失，读出0页或找不到页面树，这时明确拒绝
+///
+/// # 参数
+/// * `path` - PDF文件路径
+///
+/// # 返回
+/// 返回文档和页数
+pub fn open_pdf(path: &Path) -> Result<(PdfDocument<File>, u16), BookletError> {
+    let reader = PdfReader::open_with_options(path, ParseOptions::lenient())?;
+    if reader.is_encrypted() && !reader.is_unlocked() {
+        return Err(BookletError::Source(format!(
+            "{} 已加密，需要密码",
+            path.display()
+        )));
+    }
+    let doc = reader.into_document();
+    let page_count = doc.page_count().and_then(|count| {
+        // 页面树缺失时页数可能读出0或在读取页面时才报错
+        if count > 0 {
+            doc.get_page(0)?;
+        }
+        Ok(count)
+    });
+    match page_count {
+        Ok(count) if count > 0 => u16::try_from(count)
+            .map(|count| (doc, count))
+            .map_err(|_| BookletError::Source(format!("{} 页数太多", path.display()))),
+        result if uses_object_streams(path)? => Err(BookletError::Source(format!(
+            "{} 使用了对象流（PDF 1.5及以上），矢量排版无法读取其中的页面{}。\
+             请改用位图排版（需要pdfium），或用其他工具另存为不使用对象流的PDF",
+            path.display(),
+            result
+                .err()
+                .map(|e| format!("（{}）", e))
+                .unwrap_or_default()
+        ))),
+        Ok(_) => Err(BookletError::Source(format!("{} 没有页面", path.display()))),
+        Err(e) => Err(BookletError::Source(format!(
+            "{} 无法读取页面树: {}",
+            path.display(),
+            e
+        ))),
+    }
+}
+
+/// 文件是否使用交叉引用流或对象流
+fn uses_object_streams(path: &Path) -> Result<bool, BookletError> {
+    let data = std::fs::read(path)?;
+    Ok([b"/ObjStm".as_slice(), b"/XRef"]
+        .iter()
+        .any(|name| data.windows(name.len()).any(|window| window == *name)))
+}
+
+fn not_renderable() -> BookletError {
+    BookletError::Source("没有pdfium，不能渲染页面，只能按矢量排版".to_string())
+}
@@ -1,324 +1,801 @@
-use std::u16;
-
-use crate::booklet::BindingRule;
-use crate::pdf_render::PdfDocumentHolder;
+use crate::booklet::{BindingRule, BookletRange, CenterLineStyle, Margins, RenderMode};
+use crate::color;
+use crate::crop;
+use crate::encoding;
+use crate::error::BookletError;
+use crate::output;
+use crate::page_source::PageSource;
+use crate::pdf_info::{self, InfoEntry};
+use crate::pdf_vector;
+use crate::progress::ProgressTracker;
+use crate::stamp::Stamper;
+use crate::verify;
 use oxidize_pdf::Color;
 use oxidize_pdf::Document;
 use oxidize_pdf::Font;
+use oxidize_pdf::Image;
 use oxidize_pdf::Page;
 use oxidize_pdf::graphics::LineDashPattern;
-use pdfium_render::prelude::PdfDocumentMetadataTagType;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, mpsc};
+use std::thread;
+
+/// 1mm对应的pt数
+pub const MM_TO_PT: f64 = 72.0 / 25.4;
+
+/// 已生成册子的信息
+pub struct BookletSummary {
+    /// 册子编号
+    pub booklet_num: u16,
+    /// 输出文件路径
+    pub path: PathBuf,
+    /// A4纸页数（单面）
+    pub sheet_pages: usize,
+    /// 文件大小（字节）
+    pub file_size: u64,
+    /// 文件已存在，按覆盖策略跳过
+    pub skipped: bool,
+}
+
+/// A4纸一面上的两个页面
+#[derive(Clone, Copy, Debug)]
+pub struct SheetSide {
+    /// 低页索引，超出源文件页数表示空白页
+    pub low: u16,
+    /// 高页索引，超出源文件页数表示空白页
+    pub high: u16,
+    /// 是否是纸张背面
+    pub is_back: bool,
+}
+
+impl SheetSide {
+    /// 低页是否旋转270°，否则旋转90°
+    pub fn low_reverse(&self) -> bool {
+        self.is_back
+    }
+
+    /// 高页是否旋转270°，否则旋转90°
+    pub fn high_reverse(&self, binding_at_middle: bool) -> bool {
+        !(self.is_back ^ binding_at_middle)
+    }
+
+    /// 上半部分和下半部分的页面索引
+    ///
+    /// 中间装订时低页在下半部分，两边装订时低页在上半部分
+    pub fn top_bottom(&self, binding_at_middle: bool) -> (u16, u16) {
+        if binding_at_middle {
+            (self.high, self.low)
+        } else {
+            (self.low, self.high)
+        }
+    }
+}
+
+/// 页面渲染任务
+#[derive(Clone, Copy)]
+struct RenderTask {
+    /// 结果存放位置，第n面的低页为2n，高页为2n+1
+    slot: usize,
+    page_idx: u16,
+    reverse_image: bool,
+    /// 检测为黑白页面，按灰度保存
+    to_gray: bool,
+}
+
+impl RenderTask {
+    /// 所在纸张编号（从1开始），每张纸正反两面
+    fn sheet_num(&self) -> usize {
+        self.slot / 4 + 1
+    }
+}
 
 /// 创建册子
 ///
 /// # 参数
-/// * `src_pdf` - 源PDF文档容器
+/// * `src_pdf` - 源页面
 /// * `binding_rule` - 装订规则
-/// * `booklet_num` - 册子编号
-/// * `is_last_booklet` - 是否是最后一册
-/// * `booklet_start_page` - 小册子开始页索引(包含)
-/// * `booklet_end_page` - 小册子结束页索引(不包含)
+/// * `booklet` - 本册在源文件中的范围
+/// * `target_path` - 输出文件路径
+/// * `page_colors` - 每页是否是彩色，没有检测时为 `None`
+/// * `stamper` - 水印和页眉页脚，没有时为 `None`
+/// * `tracker` - 进度跟踪
+///
+/// # 返回
+/// 返回生成的册子信息，取消时不写入文件。
+/// 文件先写入临时文件再重命名，已存在时按覆盖策略处理
 pub fn create_booklet(
-    src_pdf: &PdfDocumentHolder,
+    src_pdf: &dyn PageSource,
     binding_rule: &BindingRule,
-    booklet_num: u16,
-    is_last_booklet: bool,
-    booklet_start_page: u16,
-    booklet_end_page: u16,
-) {
-    let mut doc = Document::new();
-    write_pdf_metadata(src_pdf, &mut doc);
-    let file_name = binding_rule
-        .input_path
-        .file_prefix()
-        .expect("没有文件名")
-        .to_string_lossy();
-    doc.set_title(format!("booklet #{}", booklet_num));
-    let mut page_idx = booklet_start_page;
-    while page_idx < booklet_end_page {
-        if let Some(page) = create_page(
-            src_pdf,
-            page_idx,
-            booklet_start_page,
-            booklet_end_page,
+    booklet: &BookletRange,
+    target_path: &Path,
+    page_colors: Option<&[bool]>,
+    stamper: Option<&Stamper>,
+    tracker: &ProgressTracker,
+) -> Result<BookletSummary, BookletError> {
+    let booklet_num = booklet.booklet_num;
+    let page_count = src_pdf.get_page_count();
+    let sides = calc_sheet_sides(booklet, binding_rule);
+    tracker.begin_booklet(booklet_num, sides.len().div_ceil(2));
+    // 渲染之前确定输出路径，跳过的册子不需要渲染
+    let Some(path) = output::resolve_output_path(target_path, binding_rule.overwrite)? else {
+        log::info!(booklet = booklet_num, path:% = target_path.display(); "文件已存在，跳过第{}册", booklet_num);
+        let skipped_pages = sides
+            .iter()
+            .flat_map(|side| [side.low, side.high])
+            .filter(|&page_idx| page_idx < page_count)
+            .count();
+        tracker.skip_pages(skipped_pages);
+        return Ok(BookletSummary {
             booklet_num,
-            is_last_booklet,
+            path: target_path.to_path_buf(),
+            sheet_pages: sides.len(),
+            file_size: 0,
+            skipped: true,
+        });
+    };
+
+    let mut blank_pages = 0;
+    for (i, side) in sides.iter().enumerate() {
+        let low_page = page_number(side.low, page_count);
+        let high_page = page_number(side.high, page_count);
+        blank_pages += [low_page, high_page].iter().filter(|p| p.is_none()).count();
+        log::debug!(
+            booklet = booklet_num,
+            sheet = i / 2 + 1,
+            side = if side.is_back { "back" } else { "front" },
+            low_page,
+            high_page;
+            "排版第{}册第{}面", booklet_num, i + 1
+        );
+    }
+    let sheet_pages = sides.len();
+    let info = booklet_info(src_pdf, binding_rule, booklet, &sides);
+    let data = match binding_rule.render_mode {
+        RenderMode::Raster => {
+            let mut data = create_raster_pdf(
+                src_pdf,
+                binding_rule,
+                booklet_num,
+                &sides,
+                page_colors,
+                stamper,
+                tracker,
+            )?;
+            pdf_info::append_info(&mut data, &info)?;
+            data
+        }
+        RenderMode::Vector => pdf_vector::create_vector_pdf(
+            src_pdf,
             binding_rule,
-        ) {
-            doc.add_page(page);
-        } else {
-            break;
+            booklet_num,
+            &sides,
+            &info,
+            tracker,
+        )?,
+    };
+    if binding_rule.verify {
+        let report = verify::verify_output(&data, booklet_num, &sides, page_count)?;
+        if !report.is_ok() {
+            return Err(BookletError::Verification(report));
         }
-        page_idx += 1;
     }
+    output::write_atomic(&path, &data)?;
+    let file_size = data.len() as u64;
 
-    doc.save(format!(
-        "{}/{}_{:02}.pdf",
-        binding_rule.output_dir.display(),
-        file_name,
-        booklet_num
-    ))
-    .unwrap();
-
-    println!(
-        "完成第{}册，共{}页, 开始页: {}, 结束页: {}",
+    log::info!(
+        booklet = booklet_num,
+        pages = booklet.end_page - booklet.start_page,
+        start_page = booklet.start_page,
+        end_page = booklet.end_page,
+        blank_pages,
+        file_size,
+        path:% = path.display();
+        "完成第{}册，共{}页，文件大小: {}",
         booklet_num,
-        booklet_end_page - booklet_start_page,
-        booklet_start_page,
-        booklet_end_page
+        booklet.end_page - booklet.start_page,
+        format_file_size(file_size)
     );
+    Ok(BookletSummary {
+        booklet_num,
+        path,
+        sheet_pages,
+        file_size,
+        skipped: false,
+    })
 }
 
-/// 设置PDF文档的元数据
+/// 把页面渲染为图像后排版一册
 ///
 /// # 参数
-/// * `src_pdf` - 源PDF文档容器
-/// * `doc` - 目标PDF文档对象
-fn write_pdf_metadata(src_pdf: &PdfDocumentHolder<'_>, doc: &mut Document) {
-    let creator = format!(
-        "{} v{} - {}",
-        env!("CARGO_PKG_NAME"),
-        env!("CARGO_PKG_VERSION"),
-        env!("CARGO_PKG_DESCRIPTION")
-    );
-    doc.set_creator(creator);
-    // doc.set_producer(pkg_name);
+/// * `src_pdf` - 源页面
+/// * `binding_rule` - 装订规则
+/// * `booklet_num` - 册子编号
+/// * `sides` - 本册每面纸上的页面
+/// * `page_colors` - 每页是否是彩色，黑白页面按灰度保存；没有检测时为 `None`
+/// * `stamper` - 水印和页眉页脚，没有时为 `None`
+/// * `tracker` - 进度跟踪
+///
+/// # 返回
+/// 返回PDF文件内容，不含文档信息
+fn create_raster_pdf(
+    src_pdf: &dyn PageSource,
+    binding_rule: &BindingRule,
+    booklet_num: u16,
+    sides: &[SheetSide],
+    page_colors: Option<&[bool]>,
+    stamper: Option<&Stamper>,
+    tracker: &ProgressTracker,
+) -> Result<Vec<u8>, BookletError> {
+    let page_count = src_pdf.get_page_count();
+    let binding_at_middle = binding_rule.binding_at_middle;
+    let to_gray = |page_idx: u16| {
+        page_colors.is_some_and(|colors| colors.get(page_idx as usize) == Some(&false))
+    };
+    let tasks: Vec<RenderTask> = sides
+        .iter()
+        .enumerate()
+        .flat_map(|(i, side)| {
+            [
+                RenderTask {
+                    slot: i * 2,
+                    page_idx: side.low,
+                    reverse_image: side.low_reverse(),
+                    to_gray: to_gray(side.low),
+                },
+                RenderTask {
+                    slot: i * 2 + 1,
+                    page_idx: side.high,
+                    reverse_image: side.high_reverse(binding_at_middle),
+                    to_gray: to_gray(side.high),
+                },
+            ]
+        })
+        .filter(|task| task.page_idx < page_count)
+        .collect();
+    let mut images = render_page_images(src_pdf, &tasks, sides.len() * 2, binding_rule, tracker)?;
+    let mut doc = Document::new();
+    if let Some(stamper) = stamper {
+        stamper.register_font(&mut doc)?;
+    }
+    for (i, side) in sides.iter().enumerate() {
+        let img_low = images[i * 2].take();
+        let img_high = images[i * 2 + 1].take();
+        doc.add_page(create_page(
+            side,
+            img_low,
+            img_high,
+            booklet_num,
+            binding_rule,
+            stamper,
+        )?);
+    }
+    encoding::mark_flate_images(&doc.to_bytes()?)
+}
+
+/// 页面索引转换为页码（从1开始），空白页返回 `None`
+fn page_number(page_idx: u16, page_count: u16) -> Option<u16> {
+    (page_idx < page_count).then(|| page_idx + 1)
+}
+
+/// 格式化文件大小
+pub fn format_file_size(size: u64) -> String {
+    const MB: f64 = 1024.0 * 1024.0;
+    if size as f64 >= MB {
+        format!("{:.1} MB", size as f64 / MB)
+    } else {
+        format!("{:.1} KB", size as f64 / 1024.0)
+    }
+}
 
-    if let Some(author) = src_pdf.metadata().get(PdfDocumentMetadataTagType::Author) {
-        let author_value = author.value();
-        if !author_value.is_empty() {
-            doc.set_author(author_value);
+/// 生成册子的文档信息
+///
+/// 标题为源文件标题（没有时为文件名）加上册号，保留源文件的作者、主题和关键词，
+/// 另外记录册号、源页码范围和装订参数，便于从打印出的文件找回对应的任务。
+///
+/// # 参数
+/// * `src_pdf` - 源页面
+/// * `binding_rule` - 装订规则
+/// * `booklet` - 本册在源文件中的范围
+/// * `sides` - 本册每面纸上的页面
+fn booklet_info(
+    src_pdf: &dyn PageSource,
+    binding_rule: &BindingRule,
+    booklet: &BookletRange,
+    sides: &[SheetSide],
+) -> Vec<InfoEntry> {
+    let source_name = binding_rule
+        .input_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let title = book_title(src_pdf, binding_rule);
+    let now = pdf_info::format_date(chrono::Local::now());
+    let mut info: Vec<InfoEntry> = vec![(
+        "Title",
+        format!(
+            "{} — booklet {}/{}",
+            title, booklet.booklet_num, booklet.booklet_count
+        ),
+    )];
+    for key in ["Author", "Subject", "Keywords"] {
+        if let Some(value) = src_pdf.get_info(key) {
+            info.push((key, value));
         }
     }
+    let page_count = src_pdf.get_page_count();
+    let source_pages = source_page_range(sides, page_count)
+        .map(|(first, last)| format!("{}-{}", first, last))
+        .unwrap_or_default();
+    info.extend([
+        (
+            "Creator",
+            format!(
+                "{} v{} - {}",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION"),
+                env!("CARGO_PKG_DESCRIPTION")
+            ),
+        ),
+        (
+            "Producer",
+            format!(
+                "{} v{} (oxidize-pdf)",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION")
+            ),
+        ),
+        ("CreationDate", now.clone()),
+        ("ModDate", now),
+        ("BookletNumber", booklet.booklet_num.to_string()),
+        ("BookletCount", booklet.booklet_count.to_string()),
+        ("SourceFile", source_name),
+        ("SourcePages", source_pages),
+        ("SheetCount", sides.len().div_ceil(2).to_string()),
+        (
+            "Binding",
+            if binding_rule.binding_at_middle {
+                "中间装订".to_string()
+            } else {
+                "两边装订".to_string()
+            },
+        ),
+        (
+            "SheetsPerBooklet",
+            binding_rule.sheets_per_booklet.to_string(),
+        ),
+        ("Cover", binding_rule.cover.to_string()),
+        ("RenderMode", binding_rule.render_mode.to_string()),
+    ]);
+    if binding_rule.render_mode == RenderMode::Raster {
+        info.push(("ImageEncoding", binding_rule.image_encoding.to_string()));
+    }
+    info
+}
 
-    if let Some(subject) = src_pdf.metadata().get(PdfDocumentMetadataTagType::Subject) {
-        let subject_value = subject.value();
-        if !subject_value.is_empty() {
-            doc.set_subject(subject_value);
+/// 书名：源文件的标题，没有时为源文件名（不含扩展名）
+pub fn book_title(src_pdf: &dyn PageSource, binding_rule: &BindingRule) -> String {
+    src_pdf.get_info("Title").unwrap_or_else(|| {
+        binding_rule
+            .input_path
+            .file_prefix()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    })
+}
+
+/// 本册包含的源页码范围（从1开始），全部为空白页时返回 `None`
+pub fn source_page_range(sides: &[SheetSide], page_count: u16) -> Option<(u16, u16)> {
+    let pages = sides
+        .iter()
+        .flat_map(|side| [side.low, side.high])
+        .filter(|&page_idx| page_idx < page_count);
+    Some((pages.clone().min()? + 1, pages.max()? + 1))
+}
+
+/// 计算一册中每面纸上的页面
+///
+/// 第k面的低页是本册第k页；中间装订时高页是倒数第k页，两边装订时高页是后半册的第k页
+///
+/// # 参数
+/// * `booklet` - 本册的页面
+/// * `binding_rule` - 装订规则
+pub fn calc_sheet_sides(booklet: &BookletRange, binding_rule: &BindingRule) -> Vec<SheetSide> {
+    let pages = &booklet.pages;
+    let half = pages.len() / 2;
+    let page_idx = |pos: usize| pages[pos].unwrap_or(u16::MAX);
+    (0..half)
+        .map(|k| {
+            let high = if binding_rule.binding_at_middle {
+                pages.len() - 1 - k
+            } else {
+                half + k
+            };
+            SheetSide {
+                low: page_idx(k),
+                high: page_idx(high),
+                // 每册从正面开始
+                is_back: k % 2 == 1,
+            }
+        })
+        .collect()
+}
+
+/// 渲染并处理页面图像
+///
+/// pdfium不能并发调用，页面在当前线程依次渲染；扫描清理、裁剪和编码交给工作线程并行处理。
+/// 结果按 `slot` 存放，与单线程处理得到的图像和顺序完全相同。
+/// 每渲染一页之前检查是否已取消。
+///
+/// # 返回
+/// 返回长度为 `slot_count` 的图像列表，空白页为 `None`
+fn render_page_images(
+    src_pdf: &dyn PageSource,
+    tasks: &[RenderTask],
+    slot_count: usize,
+    binding_rule: &BindingRule,
+    tracker: &ProgressTracker,
+) -> Result<Vec<Option<Image>>, BookletError> {
+    let page_count = src_pdf.get_page_count();
+    let mut images: Vec<Option<Image>> = (0..slot_count).map(|_| None).collect();
+    let threads = binding_rule.render_thread_count();
+    if threads <= 1 {
+        for task in tasks {
+            tracker.check_cancelled()?;
+            let (width, height, rgba) =
+                src_pdf.get_page_image(task.page_idx, task.reverse_image)?;
+            images[task.slot] = Some(process_page_image(
+                task,
+                width,
+                height,
+                rgba,
+                page_count,
+                binding_rule,
+            )?);
+            tracker.page_done(task.sheet_num(), task.page_idx);
         }
+        return Ok(images);
     }
 
-    if let Some(keywords) = src_pdf.metadata().get(PdfDocumentMetadataTagType::Keywords) {
-        let keywords_value = keywords.value();
-        if !keywords_value.is_empty() {
-            doc.set_keywords(keywords_value);
+    // 队列长度等于线程数，避免渲染过快占用太多内存（300DPI的页面约70MB）
+    let (task_tx, task_rx) = mpsc::sync_channel::<(RenderTask, u32, u32, Vec<u8>)>(threads);
+    let task_rx = Mutex::new(task_rx);
+    let (result_tx, result_rx) = mpsc::channel();
+    thread::scope(|scope| -> Result<(), BookletError> {
+        // 移入闭包，提前返回时也会关闭任务队列，工作线程随之退出
+        let task_tx = task_tx;
+        for _ in 0..threads {
+            let task_rx = &task_rx;
+            let result_tx = result_tx.clone();
+            scope.spawn(move || {
+                loop {
+                    let received = task_rx.lock().unwrap().recv();
+                    let Ok((task, width, height, rgba)) = received else {
+                        break;
+                    };
+                    let img =
+                        process_page_image(&task, width, height, rgba, page_count, binding_rule);
+                    result_tx.send((task.slot, img)).unwrap();
+                }
+            });
+        }
+        drop(result_tx);
+        for task in tasks {
+            tracker.check_cancelled()?;
+            let (width, height, rgba) =
+                src_pdf.get_page_image(task.page_idx, task.reverse_image)?;
+            task_tx.send((*task, width, height, rgba)).unwrap();
+            tracker.page_done(task.sheet_num(), task.page_idx);
+        }
+        drop(task_tx);
+        for (slot, img) in result_rx {
+            images[slot] = Some(img?);
         }
+        Ok(())
+    })?;
+    Ok(images)
+}
+
+/// 处理渲染好的页面图像：清理扫描页面、裁剪、黑白页面转为灰度并编码
+///
+/// # 参数
+/// * `task` - 渲染任务
+/// * `width`, `height`, `rgba` - 渲染结果
+/// * `page_count` - 源文件页数
+/// * `binding_rule` - 装订规则
+fn process_page_image(
+    task: &RenderTask,
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+    page_count: u16,
+    binding_rule: &BindingRule,
+) -> Result<Image, BookletError> {
+    let (width, height, rgba) = if binding_rule.scan_cleanup.is_enabled() {
+        binding_rule.scan_cleanup.process(width, height, rgba)
+    } else {
+        (width, height, rgba)
+    };
+    let (body_start, body_end) = binding_rule.body_page_range(page_count);
+    let is_body = task.page_idx >= body_start && task.page_idx < body_end;
+    let (width, height, mut rgba) = match binding_rule.crop.crop_box(task.page_idx) {
+        Some(crop_box) if is_body => {
+            crop::crop_rgba(width, height, rgba, &crop_box.rotated(task.reverse_image))
+        }
+        _ => (width, height, rgba),
+    };
+    if task.to_gray {
+        color::to_gray(&mut rgba);
     }
+    encoding::encode_image(width, height, &rgba, binding_rule.image_encoding)
 }
 
+/// 排版A4纸的一面
+///
+/// # 参数
+/// * `side` - 这一面上的页面
+/// * `img_low`, `img_high` - 低页和高页的图像，空白页为 `None`
+/// * `booklet_num` - 册子编号
+/// * `binding_rule` - 装订规则
+/// * `stamper` - 水印和页眉页脚，叠加在每个页面上
 fn create_page(
-    src_pdf: &PdfDocumentHolder,
-    page_idx: u16,
-    group_start_idx: u16,
-    group_end_idx: u16,
+    side: &SheetSide,
+    img_low: Option<Image>,
+    img_high: Option<Image>,
     booklet_num: u16,
-    // padded_page_count: &mut u16,
-    is_last_booklet: bool,
     binding_rule: &BindingRule,
-) -> Option<Page> {
-    let src_pdf_page_count = src_pdf.get_page_count();
-    let page_low_idx;
-    let page_high_idx;
-    let is_sheet_back;
-    if let Some((li, hi, is_back)) = calc_sheet_lh_page_idx(
-        src_pdf_page_count,
-        page_idx,
-        group_start_idx,
-        group_end_idx,
-        booklet_num == 1,
-        is_last_booklet,
-        binding_rule,
-    ) {
-        page_low_idx = li;
-        page_high_idx = hi;
-        is_sheet_back = is_back;
-    } else {
-        // 本册结束了
-        return None;
-    };
+    stamper: Option<&Stamper>,
+) -> Result<Page, BookletError> {
     let binding_at_middle = binding_rule.binding_at_middle;
-    let img_low = if page_low_idx >= src_pdf_page_count {
-        // 空白的情况，没有低页
-        None
-    } else {
-        // 获取低页的图像数据
-        let reverse_image = is_sheet_back;
-        let (page_low_width, page_low_height, page_low_rgba) =
-            src_pdf.get_page_image(page_low_idx, reverse_image);
-        Some(
-            oxidize_pdf::Image::from_rgba_data(page_low_rgba, page_low_width, page_low_height)
-                .unwrap(),
-        )
-    };
-    println!("{}, {}, {}", page_low_idx, page_high_idx, is_sheet_back);
-    let img_high = if page_high_idx >= src_pdf_page_count {
-        // 空白的情况，没有高页
-        None
-    } else {
-        // 获取高页的图像数据
-        let reverse_image = !(is_sheet_back ^ binding_at_middle);
-        let (page_high_width, page_high_height, page_high_rgba) =
-            src_pdf.get_page_image(page_high_idx, reverse_image);
-        let img_high =
-            oxidize_pdf::Image::from_rgba_data(page_high_rgba, page_high_width, page_high_height)
-                .unwrap();
-        Some(img_high)
-    };
-    let v_1mm_to_pt = 72.0 / 25.4;
-    // 3mm
-    let margin = 3.0 * v_1mm_to_pt;
     let mut new_page = Page::a4();
     let (w, h) = (new_page.width(), new_page.height());
-    let half_h = h / 2.0;
-    let half_w = w / 2.0;
-    // 等比缩放
-    // let margin_tb = margin * h / w / 2.0; ==> margin * h / 2.0 / w; ==> margin * (h / 2.0) / w;
-    let margin_tb = margin * half_h / w;
-    let margin_tb2 = 2.0 * margin_tb;
-    // let v_1d5mm = 1.5*v_1mm_to_pt;
-    let small_margin_tb = 0.6 * margin_tb;
-    let ((img_bottom, img_bottom_idx, bottom_y), (img_top, img_top_idx, top_y)) =
-        if binding_rule.binding_at_middle {
-            (
-                (img_low, page_low_idx, small_margin_tb),
-                (
-                    img_high,
-                    page_high_idx,
-                    half_h + margin_tb2 - small_margin_tb,
-                ),
-            )
-        } else {
-            (
-                (img_high, page_high_idx, margin_tb2 - small_margin_tb),
-                (img_low, page_low_idx, half_h + small_margin_tb),
-            )
-        };
-    let img_width = w - 2.0 * margin;
-    let img_height = half_h - margin_tb2;
-    let v_12mm = 12.0 * v_1mm_to_pt;
-    if let Some(img) = img_bottom {
-        new_page.add_image(format!("{}", img_bottom_idx), img);
-        new_page
-            .draw_image(
-                format!("{}", img_bottom_idx).as_str(),
-                margin,
-                bottom_y,
-                img_width,
-                img_height,
-            )
-            .unwrap();
+    let margins = &binding_rule.margins;
+    // 中间装订时低页在下半部分，两边装订时低页在上半部分
+    let low_at_top = !binding_at_middle;
+    for (img, img_idx, at_top, reverse_image) in [
+        (img_low, side.low, low_at_top, side.low_reverse()),
+        (
+            img_high,
+            side.high,
+            !low_at_top,
+            side.high_reverse(binding_at_middle),
+        ),
+    ] {
+        if let Some(img) = img {
+            let slot = calc_slot_rect(margins, binding_at_middle, at_top, reverse_image, w, h);
+            let anchor = slot_anchor(binding_at_middle, at_top, reverse_image);
+            let (x, y, img_width, img_height) =
+                fit_rect(slot, anchor, img.width() as f64, img.height() as f64);
+            let name = page_xobject_name(img_idx);
+            new_page.add_image(&name, img);
+            new_page.draw_image(&name, x, y, img_width, img_height)?;
+            if let Some(stamper) = stamper {
+                stamper.draw(
+                    &mut new_page,
+                    img_idx,
+                    (x, y, img_width, img_height),
+                    reverse_image,
+                    booklet_num,
+                )?;
+            }
+        }
     }
-    if let Some(img) = img_top {
-        new_page.add_image(format!("{}", img_top_idx), img);
+
+    draw_center_line(&mut new_page, margins.center_line, side.is_back);
+    if !side.is_back {
+        let (x, y) = booklet_marker_position(w, h);
         new_page
-            .draw_image(
-                format!("{}", img_top_idx).as_str(),
-                margin,
-                top_y,
-                img_width,
-                img_height,
-            )
-            .unwrap();
+            .text()
+            .set_font(Font::TimesRoman, 6.0)
+            .at(x, y)
+            .write(&booklet_marker(booklet_num))?;
     }
+    Ok(new_page)
+}
+
+/// 在放置区域内等比缩放图像，并按 `anchor` 对齐
+///
+/// # 参数
+/// * `slot` - 放置区域 (x, y, width, height)
+/// * `anchor` - 宽高比与放置区域不同时剩余空白的分配，见 `slot_anchor`
+/// * `img_width`, `img_height` - 图像尺寸（像素或pt，只使用宽高比）
+pub fn fit_rect(
+    slot: (f64, f64, f64, f64),
+    anchor: (f64, f64),
+    img_width: f64,
+    img_height: f64,
+) -> (f64, f64, f64, f64) {
+    let (x, y, w, h) = slot;
+    let scale = (w / img_width).min(h / img_height);
+    let (fit_w, fit_h) = (img_width * scale, img_height * scale);
+    (
+        x + (w - fit_w) * anchor.0,
+        y + (h - fit_h) * anchor.1,
+        fit_w,
+        fit_h,
+    )
+}
+
+/// 图像在放置区域内的对齐方式：靠装订侧和天头
+///
+/// 源页面与放置区域宽高比不同时，装订侧和天头的页边距与设置完全一致，
+/// 多出的空白留在切口侧和地脚，对页上两页的文字靠近中缝、顶端对齐。
+///
+/// # 参数
+/// 与 `calc_slot_rect` 相同
+///
+/// # 返回
+/// 返回 (x, y) 方向的对齐比例：0为靠左/下，1为靠右/上
+pub fn slot_anchor(binding_at_middle: bool, at_top: bool, reverse_image: bool) -> (f64, f64) {
+    // 天头朝左时靠左，朝右时靠右
+    let x = if reverse_image { 0.0 } else { 1.0 };
+    // 装订侧在放置区域的下边或上边，见 `calc_slot_rect`
+    let y = if at_top == binding_at_middle {
+        0.0
+    } else {
+        1.0
+    };
+    (x, y)
+}
+
+/// 计算单页图像在A4纸上的放置区域
+///
+/// # 参数
+/// * `margins` - 页边距
+/// * `binding_at_middle` - 是否在中间装订
+/// * `at_top` - 是否放在纸张上半部分
+/// * `reverse_image` - 图像是否旋转了270°（天头朝左），否则为90°（天头朝右）
+/// * `w`, `h` - 纸张宽高(pt)
+///
+/// # 返回
+/// 返回 (x, y, width, height) 元组，单位pt
+pub fn calc_slot_rect(
+    margins: &Margins,
+    binding_at_middle: bool,
+    at_top: bool,
+    reverse_image: bool,
+    w: f64,
+    h: f64,
+) -> (f64, f64, f64, f64) {
+    let half_h = h / 2.0;
+    let outer = margins.outer * MM_TO_PT;
+    let inner = margins.inner * MM_TO_PT;
+    let head = margins.head * MM_TO_PT;
+    let foot = margins.foot * MM_TO_PT;
+    // 中间装订时装订侧靠近纸张中线，两边装订时装订侧在纸张上下边缘
+    let (near_center, near_edge) = if binding_at_middle {
+        (inner, outer)
+    } else {
+        (outer, inner)
+    };
+    let (y0, y1) = if at_top {
+        (half_h + near_center, h - near_edge)
+    } else {
+        (near_edge, half_h - near_center)
+    };
+    let (x0, x1) = if reverse_image {
+        (head, w - foot)
+    } else {
+        (foot, w - head)
+    };
+    (x0, y0, x1 - x0, y1 - y0)
+}
+
+/// 纸张正面中缝处标记册号的文字
+pub fn booklet_marker(booklet_num: u16) -> String {
+    format!("^- {} -^", booklet_num)
+}
+
+/// 纸面上源页面的图像或表单对象的资源名称，校验生成的文件时据此还原每面放置的源页面
+pub fn page_xobject_name(page_idx: u16) -> String {
+    format!("P{}", page_idx)
+}
+
+/// 从资源名称解析源页面索引，不是源页面时返回 `None`
+pub fn parse_page_xobject_name(name: &str) -> Option<u16> {
+    name.strip_prefix('P')?.parse().ok()
+}
 
-    // 间隔12mm
-    let dot_space = v_12mm;
-    let padding = 6.0 * v_1mm_to_pt;
-    let ((start_x, start_y), (to_x, to_y)) = if is_sheet_back {
+/// 册号标记文字的起点
+///
+/// # 参数
+/// * `w`, `h` - 纸张宽高(pt)
+pub fn booklet_marker_position(w: f64, h: f64) -> (f64, f64) {
+    (w / 2.0 - 9.0 * MM_TO_PT, h / 2.0)
+}
+
+/// 中缝线的线型，不画中缝线时返回 `None`
+pub fn center_line_pattern(style: CenterLineStyle) -> Option<LineDashPattern> {
+    Some(match style {
+        CenterLineStyle::Hidden => return None,
+        CenterLineStyle::Dotted(space) => LineDashPattern::dotted(1.0, space * MM_TO_PT),
+        CenterLineStyle::Dashed(dash, gap) => {
+            LineDashPattern::dashed(dash * MM_TO_PT, gap * MM_TO_PT)
+        }
+        CenterLineStyle::Solid => LineDashPattern::solid(),
+    })
+}
+
+/// 中缝线的起点和终点
+///
+/// 线的起点留出6mm，正反面起点相对，避免透印重叠
+///
+/// # 参数
+/// * `w`, `h` - 纸张宽高(pt)
+/// * `is_sheet_back` - 是否是纸张背面
+pub fn center_line_points(w: f64, h: f64, is_sheet_back: bool) -> ((f64, f64), (f64, f64)) {
+    let half_h = h / 2.0;
+    let padding = 6.0 * MM_TO_PT;
+    if is_sheet_back {
         ((padding, half_h), (w, half_h))
     } else {
         ((w - padding, half_h), (0.0, half_h))
+    }
+}
+
+/// 画中缝线
+///
+/// # 参数
+/// * `page` - A4纸页面
+/// * `style` - 中缝线样式
+/// * `is_sheet_back` - 是否是纸张背面
+fn draw_center_line(page: &mut Page, style: CenterLineStyle, is_sheet_back: bool) {
+    let Some(pattern) = center_line_pattern(style) else {
+        return;
     };
-    new_page
-        .graphics()
+    let ((start_x, start_y), (to_x, to_y)) =
+        center_line_points(page.width(), page.height(), is_sheet_back);
+    page.graphics()
         .set_stroke_color(Color::Gray(0.3))
+        .set_line_dash_pattern(pattern)
         .move_to(start_x, start_y)
         .line_to(to_x, to_y)
-        .set_line_dash_pattern(LineDashPattern::dotted(1.0, dot_space))
         .stroke();
-    if !is_sheet_back {
-        let _ = new_page
-            .text()
-            .set_font(Font::TimesRoman, 6.0)
-            .at(half_w - 9.0 * v_1mm_to_pt, half_h)
-            .write(format!("^- {} -^", booklet_num).as_str());
-    }
-    return Some(new_page);
 }
 
-fn calc_sheet_lh_page_idx(
-    page_count: u16,
-    page_idx: u16,
-    group_start_idx: u16,
-    group_end_idx: u16,
-    // booklet_num: u16,
-    is_first_booklet: bool,
-    is_last_booklet: bool,
-    binding_rule: &BindingRule,
-) -> Option<(u16, u16, bool)> {
-    let has_cover = binding_rule.has_cover;
-    let keep_cover = binding_rule.keep_cover;
-    let mut page_low_idx = page_idx;
-    // let mut page_idx = page_idx;
-    let binding_at_middle = binding_rule.binding_at_middle;
-    let mut page_high_idx = group_end_idx - page_idx + group_start_idx - 1;
-    let mut is_sheet_back = page_idx % 2 != 0;
-    // 第一册
-    if is_first_booklet {
-        if has_cover && keep_cover {
-            if page_idx == 1 {
-                page_low_idx = u16::MAX;
-                // is_sheet_back = true;
-            } else if page_idx > 1 {
-                // page_idx -= 1;
-                page_low_idx = page_idx - 1;
-            } else {
-                // == 0
-                // is_sheet_back = false;
-            }
-        } else if has_cover && !keep_cover {
-            if page_idx == 0 {
-                // group_start_idx = 1;
-            }
-            is_sheet_back = !is_sheet_back;
-        }
-    } else {
-        if has_cover {
-            is_sheet_back = !is_sheet_back;
-        }
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if page_low_idx < u16::MAX && page_low_idx >= page_high_idx {
-        // 本册结束了
-        return None;
-    }
-    // 边缘装订
-    if !binding_at_middle {
-        if has_cover && keep_cover {}
-        if is_first_booklet && is_last_booklet {
-            page_high_idx = (group_end_idx - group_start_idx - 1) / 2 + page_idx;
-        } else if is_first_booklet || is_last_booklet {
-            page_high_idx = (group_end_idx - group_start_idx) / 2 + page_idx;
-        } else {
-            page_high_idx = (group_end_idx - group_start_idx + 1) / 2 + page_idx;
-        }
-    }
-    if is_last_booklet {
-        if has_cover && keep_cover {
-            if page_high_idx == page_count - 1 {
-                page_high_idx = u16::MAX;
-            } else if page_high_idx == group_end_idx - 1 {
-                page_high_idx = page_count - 1;
-                is_sheet_back = false;
-            } else if page_high_idx == group_end_idx - 2 {
-                is_sheet_back = true;
-            }
-        } else if has_cover && !keep_cover {
-            if page_high_idx >= page_count {
-                page_high_idx = u16::MAX;
-                is_sheet_back = page_idx % 2 == 0;
+    #[test]
+    fn fit_keeps_inner_and_head_margins() {
+        let margins = Margins {
+            outer: 5.0,
+            inner: 10.0,
+            head: 7.0,
+            foot: 4.0,
+            ..Margins::default()
+        };
+        let a4 = Page::a4();
+        let (w, h) = (a4.width(), a4.height());
+        let half_h = h / 2.0;
+        // 比放置区域宽和比放置区域高的图像
+        for (img_w, img_h) in [(400.0, 100.0), (100.0, 400.0)] {
+            for binding_at_middle in [true, false] {
+                for at_top in [true, false] {
+                    for reverse_image in [true, false] {
+                        let slot = calc_slot_rect(
+                            &margins,
+                            binding_at_middle,
+                            at_top,
+                            reverse_image,
+                            w,
+                            h,
+                        );
+                        let anchor = slot_anchor(binding_at_middle, at_top, reverse_image);
+                        let (x, y, fit_w, fit_h) = fit_rect(slot, anchor, img_w, img_h);
+                        let inner = match (binding_at_middle, at_top) {
+                            (true, true) => y - half_h,
+                            (true, false) => half_h - (y + fit_h),
+                            (false, true) => h - (y + fit_h),
+                            (false, false) => y,
+                        };
+                        let head = if reverse_image { x } else { w - (x + fit_w) };
+                        assert!((inner - margins.inner * MM_TO_PT).abs() < 1e-9);
+                        assert!((head - margins.head * MM_TO_PT).abs() < 1e-9);
+                        assert!((fit_w / fit_h - img_w / img_h).abs() < 1e-9);
+                    }
+                }
             }
         }
     }
-    Some((page_low_idx, page_high_idx, is_sheet_back))
 }
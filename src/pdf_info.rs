@@ -0,0 +1,103 @@
+use std::fmt::Write;
+use std::io;
+
+use chrono::{DateTime, Local};
+
+/// 文档信息字典（Info）中的一项，键为PDF名称（不含 `/`）
+pub type InfoEntry = (&'static str, String);
+
+/// 以增量更新的方式替换文档信息字典
+///
+/// oxidize-pdf 只能写入标准的文档信息，不能添加自定义项。这里在文件末尾追加一个
+/// 同编号的信息字典对象、只含这一项的交叉引用表和新的 trailer，阅读器会使用追加的版本。
+/// 只支持 oxidize-pdf 写出的传统交叉引用表格式。
+///
+/// # 参数
+/// * `data` - 完整的PDF文件内容
+/// * `entries` - 新的文档信息，会完全替换原有的信息字典
+pub fn append_info(data: &mut Vec<u8>, entries: &[InfoEntry]) -> io::Result<()> {
+    let trailer_pos = data
+        .windows(b"trailer".len())
+        .rposition(|w| w == b"trailer")
+        .ok_or_else(|| invalid_data("找不到trailer"))?;
+    let trailer = String::from_utf8_lossy(&data[trailer_pos..]).into_owned();
+    let info_num = number_after(&trailer, "/Info")?;
+    let root_num = number_after(&trailer, "/Root")?;
+    let size = number_after(&trailer, "/Size")?;
+    let prev_xref = number_after(&trailer, "startxref")?;
+
+    let mut update = String::new();
+    if !data.ends_with(b"\n") {
+        update.push('\n');
+    }
+    let info_pos = data.len() + update.len();
+    let _ = write!(update, "{} 0 obj\n<<", info_num);
+    for (key, value) in entries {
+        let _ = write!(update, " /{} {}", key, encode_text(value));
+    }
+    update.push_str(" >>\nendobj\n");
+    let xref_pos = data.len() + update.len();
+    // 交叉引用表每项固定20字节
+    let _ = write!(
+        update,
+        "xref\n{} 1\n{:010} 00000 n \ntrailer\n<< /Size {} /Root {} 0 R /Info {} 0 R /Prev {} >>\nstartxref\n{}\n%%EOF\n",
+        info_num, info_pos, size, root_num, info_num, prev_xref, xref_pos
+    );
+    data.extend_from_slice(update.as_bytes());
+    Ok(())
+}
+
+/// PDF日期格式：`D:YYYYMMDDHHmmSS+HH'mm'`
+pub fn format_date(date: DateTime<Local>) -> String {
+    let offset = date.offset().local_minus_utc();
+    let sign = if offset < 0 { '-' } else { '+' };
+    let offset = offset.abs() / 60;
+    format!(
+        "D:{}{}{:02}'{:02}'",
+        date.format("%Y%m%d%H%M%S"),
+        sign,
+        offset / 60,
+        offset % 60
+    )
+}
+
+/// 读取关键字后面的整数，如 `/Info 3 0 R` 中的 `3`
+fn number_after(text: &str, key: &str) -> io::Result<u64> {
+    let pos = text
+        .rfind(key)
+        .ok_or_else(|| invalid_data(&format!("trailer中没有{}", key)))?;
+    text[pos + key.len()..]
+        .split_whitespace()
+        .next()
+        .and_then(|token| token.parse().ok())
+        .ok_or_else(|| invalid_data(&format!("trailer中的{}无效", key)))
+}
+
+/// 编码PDF文本字符串，ASCII文本使用字面字符串，其他文本使用UTF-16BE十六进制字符串
+pub fn encode_text(text: &str) -> String {
+    if text.chars().all(|c| c.is_ascii() && !c.is_ascii_control()) {
+        let mut literal = String::from("(");
+        for c in text.chars() {
+            if matches!(c, '(' | ')' | '\\') {
+                literal.push('\\');
+            }
+            literal.push(c);
+        }
+        literal.push(')');
+        literal
+    } else {
+        let mut hex = String::from("<FEFF");
+        for unit in text.encode_utf16() {
+            let _ = write!(hex, "{:04X}", unit);
+        }
+        hex.push('>');
+        hex
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("无法更新文档信息: {}", msg),
+    )
+}
@@ -1,31 +1,38 @@
 use native_dialog::{DialogBuilder, MessageLevel};
 use pdfium_render::prelude::*;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use crate::error::BookletError;
+use crate::page_source::PageSource;
 
 /// PDF文档持有者，同时保存Pdfium和PdfDocument以确保生命周期
 pub struct PdfDocumentHolder<'a> {
     document: PdfDocument<'a>,
+    /// 源文件路径，矢量排版时从这个文件复制页面
+    path: PathBuf,
 }
 
 impl<'a> PdfDocumentHolder<'a> {
-    /// 创建新的PDF文档持有者
+    /// 打开PDF文件，无法读取时返回错误
     ///
     /// # 参数
     /// * `path` - PDF文件路径
     /// * `password` - 可选的密码
-    ///
-    /// # Panics
-    /// 如果无法绑定到pdfium库或无法读取PDF文件，会触发panic
-    pub fn new(pdfium: &'a Pdfium, path: &PathBuf, password: Option<&'a str>) -> Self {
+    pub fn open(
+        pdfium: &'a Pdfium,
+        path: &PathBuf,
+        password: Option<&'a str>,
+    ) -> Result<Self, PdfiumError> {
         // 先加载文档
-        let document = pdfium
-            .load_pdf_from_file(path, password)
-            .expect("无法读取PDF文件");
+        let document = pdfium.load_pdf_from_file(path, password)?;
 
         // 将document转换为'static生命周期
         // let document: PdfDocument<'static> = unsafe { std::mem::transmute(document) };
 
-        Self { document }
+        Ok(Self {
+            document,
+            path: path.clone(),
+        })
     }
 
     /// 获取页面对象的引用
@@ -36,14 +43,32 @@ impl<'a> PdfDocumentHolder<'a> {
     pub fn metadata(&self) -> &PdfMetadata<'_> {
         self.document.metadata()
     }
-    /// 获取指定页面的图像数据
-    ///
-    /// # 参数
-    /// * `page_idx` - 页面索引（从0开始）
-    ///
-    /// # 返回
-    /// 返回 (width, height, rgba_bytes) 元组
-    pub fn get_page_image(&self, page_idx: u16, reverse_image: bool) -> (u32, u32, Vec<u8>) {
+}
+
+impl PageSource for PdfDocumentHolder<'_> {
+    fn get_page_count(&self) -> u16 {
+        self.pages().len()
+    }
+
+    fn get_info(&self, key: &str) -> Option<String> {
+        let tag = match key {
+            "Title" => PdfDocumentMetadataTagType::Title,
+            "Author" => PdfDocumentMetadataTagType::Author,
+            "Subject" => PdfDocumentMetadataTagType::Subject,
+            "Keywords" => PdfDocumentMetadataTagType::Keywords,
+            "Creator" => PdfDocumentMetadataTagType::Creator,
+            "Producer" => PdfDocumentMetadataTagType::Producer,
+            _ => return None,
+        };
+        let value = self.metadata().get(tag)?.value().trim().to_string();
+        (!value.is_empty()).then_some(value)
+    }
+
+    fn get_page_image(
+        &self,
+        page_idx: u16,
+        reverse_image: bool,
+    ) -> Result<(u32, u32, Vec<u8>), BookletError> {
         let rotate = if reverse_image {
             //旋转270°
             PdfPageRenderRotation::Degrees270
@@ -51,7 +76,6 @@ impl<'a> PdfDocumentHolder<'a> {
             // 旋转90°
             PdfPageRenderRotation::Degrees90
         };
-        let page = self.pages().get(page_idx).unwrap();
         // 72 DPI: 595 x 842 像素
         // 150 DPI: 1240 x 1754 像素
         // 300 DPI: 2480 x 3508 像素
@@ -60,48 +84,208 @@ impl<'a> PdfDocumentHolder<'a> {
             .set_target_height(target_height)
             .set_maximum_height(target_height)
             .rotate(rotate, true);
-        let bitmap = page.render_with_config(&render_config).unwrap();
+        self.render(page_idx, &render_config)
+    }
+
+    fn get_page_preview(
+        &self,
+        page_idx: u16,
+        target_width: i32,
+    ) -> Result<(u32, u32, Vec<u8>), BookletError> {
+        let render_config = PdfRenderConfig::new()
+            .set_target_width(target_width)
+            .set_maximum_width(target_width);
+        self.render(page_idx, &render_config)
+    }
+
+    fn get_page_size(&self, page_idx: u16) -> Option<(f64, f64)> {
+        let page = self.pages().get(page_idx).ok()?;
+        Some((page.width().value as f64, page.height().value as f64))
+    }
+
+    fn get_pdf_page(&self, page_idx: u16) -> Option<(&Path, u16)> {
+        (page_idx < self.pages().len()).then_some((self.path.as_path(), page_idx))
+    }
+
+    fn get_chapters(&self) -> Vec<(u16, String)> {
+        let mut chapters = Vec::new();
+        let mut bookmark = self.document.bookmarks().root();
+        while let Some(current) = bookmark {
+            let page_idx = current
+                .destination()
+                .and_then(|destination| destination.page_index().ok());
+            if let (Some(page_idx), Some(title)) = (page_idx, current.title()) {
+                chapters.push((page_idx, title.trim().to_string()));
+            }
+            bookmark = current.next_sibling();
+        }
+        chapters.sort_by_key(|(page_idx, _)| *page_idx);
+        chapters
+    }
+
+    fn has_images(&self, page_idx: u16) -> Option<bool> {
+        let page = self.pages().get(page_idx).ok()?;
+        Some(page.objects().iter().any(|object| contains_image(&object)))
+    }
+}
+
+/// 页面对象是否是图像，或是含有图像的表单对象
+fn contains_image(object: &PdfPageObject) -> bool {
+    match object.object_type() {
+        PdfPageObjectType::Image => true,
+        PdfPageObjectType::XObjectForm => object
+            .as_x_object_form_object()
+            .is_some_and(|form| form.iter().any(|child| contains_image(&child))),
+        _ => false,
+    }
+}
+
+impl PdfDocumentHolder<'_> {
+    /// 按渲染配置渲染页面
+    ///
+    /// # 返回
+    /// 返回 (width, height, rgba_bytes) 元组
+    fn render(
+        &self,
+        page_idx: u16,
+        render_config: &PdfRenderConfig,
+    ) -> Result<(u32, u32, Vec<u8>), BookletError> {
+        let render_error =
+            |e| BookletError::Source(format!("无法渲染第{}页: {:?}", page_idx + 1, e));
+        let page = self.pages().get(page_idx).map_err(render_error)?;
+        let bitmap = page
+            .render_with_config(render_config)
+            .map_err(render_error)?;
         let width = bitmap.width() as u32;
         let height = bitmap.height() as u32;
         let rgba = bitmap.as_rgba_bytes();
-        (width, height, rgba)
+        Ok((width, height, rgba))
     }
+}
 
-    /// 获取PDF总页数
-    pub fn get_page_count(&self) -> u16 {
-        self.pages().len()
+/// 指定pdfium库位置的环境变量，可以是库文件或所在目录
+pub const PDFIUM_LIB_PATH_ENV: &str = "PDFIUM_LIB_PATH";
+/// pdfium库下载地址
+const PDFIUM_DOWNLOAD_URL: &str = "https://github.com/bblanchon/pdfium-binaries/releases";
+
+/// 查找并加载pdfium库
+///
+/// 依次尝试，使用第一个能加载的：
+/// 1. `lib_path` 参数（命令行 `--pdfium-path`）
+/// 2. 环境变量 `PDFIUM_LIB_PATH`
+/// 3. 程序所在目录
+/// 4. 系统库
+/// 5. 当前目录下的 `lib` 文件夹
+///
+/// 路径可以是库文件，也可以是所在目录（使用平台的库文件名，如 `libpdfium.so`）。
+///
+/// # 参数
+/// * `lib_path` - 命令行指定的库文件或目录
+///
+/// # 返回
+/// 全部失败时返回 `BookletError::PdfiumNotFound`，包含每个位置的失败原因
+pub fn init_pdfium(lib_path: Option<&Path>) -> Result<Pdfium, BookletError> {
+    let mut candidates: Vec<(&str, PathBuf)> = Vec::new();
+    if let Some(path) = lib_path {
+        candidates.push(("--pdfium-path", path.to_path_buf()));
+    }
+    if let Some(path) = std::env::var_os(PDFIUM_LIB_PATH_ENV).filter(|path| !path.is_empty()) {
+        candidates.push((PDFIUM_LIB_PATH_ENV, PathBuf::from(path)));
+    }
+    if let Some(dir) = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+    {
+        candidates.push(("程序所在目录", dir));
     }
-}
 
-pub fn init_pdfium() -> Pdfium {
-    let lib_path = std::env::current_dir().unwrap().join("lib");
-    let lib = Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path(&lib_path));
-    if lib.is_err() {
-        eprintln!("无法绑定到pdfium库");
-        eprintln!(
-            "请前往下载适合的版本，解压后将动态链接库文件放入文件夹 {}",
-            lib_path.to_string_lossy()
-        );
-        let url = "https://github.com/bblanchon/pdfium-binaries/releases";
-        eprintln!("下载地址 {}", url);
-        let yes = DialogBuilder::message()
-            .set_level(MessageLevel::Error)
-            .set_title("出错啦!")
-            .set_text(format!(
-                "请下载适合的版本，解压后放入文件夹 {}\n下载地址 {}",
-                lib_path.to_string_lossy(),
-                url
-            ))
-            .confirm()
-            .show()
-            .unwrap();
-
-        if yes {
-            let _ = webbrowser::open(url);
+    let mut attempts = Vec::new();
+    for (source, path) in candidates {
+        let lib_file = library_file(&path);
+        match Pdfium::bind_to_library(&lib_file) {
+            Ok(bindings) => {
+                log::debug!(path:% = lib_file.display(); "pdfium库: {}", lib_file.display());
+                return Ok(Pdfium::new(bindings));
+            }
+            Err(e) => attempts.push(format!("{}: {}", source, describe_bind_error(e))),
         }
-        // sleep(Duration::from_secs(5));
-        // panic!("请按上述提示操作后重新运行")
-        std::process::exit(0)
     }
-    Pdfium::new(lib.unwrap())
+    match Pdfium::bind_to_system_library() {
+        Ok(bindings) => {
+            log::debug!("pdfium库: 系统库");
+            return Ok(Pdfium::new(bindings));
+        }
+        Err(e) => attempts.push(format!("系统库: {}", describe_bind_error(e))),
+    }
+    if let Ok(dir) = std::env::current_dir() {
+        let lib_file = Pdfium::pdfium_platform_library_name_at_path(&dir.join("lib"));
+        match Pdfium::bind_to_library(&lib_file) {
+            Ok(bindings) => {
+                log::debug!(path:% = lib_file.display(); "pdfium库: {}", lib_file.display());
+                return Ok(Pdfium::new(bindings));
+            }
+            Err(e) => attempts.push(format!("当前目录下的lib: {}", describe_bind_error(e))),
+        }
+    }
+    Err(BookletError::PdfiumNotFound(attempts))
+}
+
+/// 加载库失败的原因，动态链接库的错误只保留系统给出的说明（其中包含库文件路径）
+fn describe_bind_error(e: PdfiumError) -> String {
+    match e {
+        PdfiumError::LoadLibraryError(e) => match std::error::Error::source(&e) {
+            Some(source) => source.to_string(),
+            None => e.to_string(),
+        },
+        e => format!("{:?}", e),
+    }
+}
+
+/// 目录下的平台库文件名，不是目录时原样返回
+fn library_file(path: &Path) -> PathBuf {
+    if path.is_dir() {
+        Pdfium::pdfium_platform_library_name_at_path(path)
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// 报告无法加载pdfium库，提示下载位置
+///
+/// 总是写入日志；有图形环境时再弹窗，确认后打开下载页面
+pub fn report_pdfium_error(error: &BookletError) {
+    log::error!("{}", error);
+    log::error!(
+        "请前往 {} 下载适合的版本，解压后把动态链接库放在程序所在目录，或用 --pdfium-path / {} 指定位置",
+        PDFIUM_DOWNLOAD_URL,
+        PDFIUM_LIB_PATH_ENV
+    );
+    if !has_display() {
+        return;
+    }
+    let yes = DialogBuilder::message()
+        .set_level(MessageLevel::Error)
+        .set_title("出错啦!")
+        .set_text(format!(
+            "无法加载pdfium库\n请下载适合的版本，解压后把动态链接库放在程序所在目录\n是否打开下载页面 {}",
+            PDFIUM_DOWNLOAD_URL
+        ))
+        .confirm()
+        .show()
+        .unwrap_or(false);
+    if yes {
+        let _ = webbrowser::open(PDFIUM_DOWNLOAD_URL);
+    }
+}
+
+/// 是否有图形环境可以弹窗
+///
+/// Linux等系统上没有 `DISPLAY` 和 `WAYLAND_DISPLAY` 时视为无图形环境（如服务器、SSH）
+fn has_display() -> bool {
+    if cfg!(any(target_os = "windows", target_os = "macos")) {
+        return true;
+    }
+    ["DISPLAY", "WAYLAND_DISPLAY"]
+        .iter()
+        .any(|var| std::env::var_os(var).is_some_and(|value| !value.is_empty()))
 }
@@ -0,0 +1,623 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use oxidize_pdf::Page;
+use oxidize_pdf::parser::{PdfDictionary, PdfDocument, PdfObject};
+
+use crate::booklet::BindingRule;
+use crate::crop::CropBox;
+use crate::error::BookletError;
+use crate::page_source::{self, PageSource};
+use crate::pdf_creator::{self, SheetSide};
+use crate::pdf_info::{self, InfoEntry};
+use crate::progress::ProgressTracker;
+
+/// PDF仿射变换矩阵 `[a b c d e f]`：x' = a·x + c·y + e，y' = b·x + d·y + f
+type Matrix = [f64; 6];
+
+/// 检查矢量排版不支持的设置
+///
+/// 明确开启的功能做不到时返回错误，不默默忽略；只有部分效果做不到时给出警告
+pub fn check_rule(binding_rule: &BindingRule) -> Result<(), BookletError> {
+    if binding_rule.scan_cleanup.is_enabled() {
+        return Err(BookletError::InvalidRule(
+            "矢量排版不支持扫描清理，请关闭扫描清理或改用位图排版".to_string(),
+        ));
+    }
+    if binding_rule.stamps.is_enabled() {
+        return Err(BookletError::InvalidRule(
+            "矢量排版不支持水印和页眉页脚，请去掉水印和页眉页脚或改用位图排版".to_string(),
+        ));
+    }
+    if binding_rule.color_detection.enabled {
+        // 检测结果仍用于报告彩色纸张和分组，只是不改变页面内容
+        log::warn!("矢量排版不会把黑白页面转为灰度，彩色页面检测只用于报告彩色纸张和分组");
+    }
+    Ok(())
+}
+
+/// 直接复制源页面的内容排版一册
+///
+/// 每个源页面作为一个表单对象（Form XObject）放到A4纸上，页面内容、字体和图像
+/// 原样复制，不经过渲染。页面的 `/Rotate`、裁剪框（CropBox）和手动裁剪都会保留。
+/// 注释（链接、批注等）不复制。
+///
+/// # 参数
+/// * `src_pdf` - 源页面，每页都要能对应到PDF文件
+/// * `binding_rule` - 装订规则
+/// * `booklet_num` - 册子编号
+/// * `sides` - 本册每面纸上的页面
+/// * `info` - 文档信息
+/// * `tracker` - 进度跟踪
+///
+/// # 返回
+/// 返回完整的PDF文件内容
+pub fn create_vector_pdf(
+    src_pdf: &dyn PageSource,
+    binding_rule: &BindingRule,
+    booklet_num: u16,
+    sides: &[SheetSide],
+    info: &[InfoEntry],
+    tracker: &ProgressTracker,
+) -> Result<Vec<u8>, BookletError> {
+    let page_count = src_pdf.get_page_count();
+    let (body_start, body_end) = binding_rule.body_page_range(page_count);
+    let binding_at_middle = binding_rule.binding_at_middle;
+    let a4 = Page::a4();
+    let (w, h) = (a4.width(), a4.height());
+
+    let mut writer = PdfWriter::new();
+    let catalog_id = writer.alloc();
+    let pages_id = writer.alloc();
+    let info_id = writer.alloc();
+    let font_id = writer.alloc();
+    writer.write_object(
+        font_id,
+        b"<< /Type /Font /Subtype /Type1 /BaseFont /Times-Roman >>",
+    );
+
+    let mut sources: HashMap<PathBuf, SourceFile> = HashMap::new();
+    let mut page_ids = Vec::with_capacity(sides.len());
+    // 中间装订时低页在下半部分，两边装订时低页在上半部分
+    let low_at_top = !binding_at_middle;
+    for (i, side) in sides.iter().enumerate() {
+        let mut content = String::new();
+        let mut xobjects = String::new();
+        for (page_idx, at_top, reverse_image) in [
+            (side.low, low_at_top, side.low_reverse()),
+            (side.high, !low_at_top, side.high_reverse(binding_at_middle)),
+        ] {
+            if page_idx >= page_count {
+                continue;
+            }
+            tracker.check_cancelled()?;
+            let (path, src_idx) = src_pdf.get_pdf_page(page_idx).ok_or_else(|| {
+                BookletError::Source(format!("第{}页不是PDF页面，不能按矢量排版", page_idx + 1))
+            })?;
+            let source = match sources.entry(path.to_path_buf()) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(SourceFile::open(path)?),
+            };
+            let form = source.page_form(&mut writer, src_idx)?;
+            let is_body = page_idx >= body_start && page_idx < body_end;
+            let crop_box = match binding_rule.crop.crop_box(page_idx) {
+                Some(crop_box) if is_body => *crop_box,
+                _ => CropBox::default(),
+            };
+            let slot = pdf_creator::calc_slot_rect(
+                &binding_rule.margins,
+                binding_at_middle,
+                at_top,
+                reverse_image,
+                w,
+                h,
+            );
+            let anchor = pdf_creator::slot_anchor(binding_at_middle, at_top, reverse_image);
+            let (matrix, clip) = place_form(&form, &crop_box, reverse_image, slot, anchor);
+            let name = pdf_creator::page_xobject_name(page_idx);
+            let _ = write!(
+                content,
+                "q\n{} {} {} {} re W n\n{} cm\n/{} Do\nQ\n",
+                num(clip.0),
+                num(clip.1),
+                num(clip.2),
+                num(clip.3),
+                matrix.map(num).join(" "),
+                name
+            );
+            let _ = write!(xobjects, " /{} {} 0 R", name, form.id);
+            tracker.page_done(i / 2 + 1, page_idx);
+        }
+
+        if let Some(pattern) = pdf_creator::center_line_pattern(binding_rule.margins.center_line) {
+            let ((start_x, start_y), (to_x, to_y)) =
+                pdf_creator::center_line_points(w, h, side.is_back);
+            let _ = write!(
+                content,
+                "q\n0.3 G\n{} d\n{} {} m\n{} {} l\nS\nQ\n",
+                pattern.to_pdf_string(),
+                num(start_x),
+                num(start_y),
+                num(to_x),
+                num(to_y)
+            );
+        }
+        if !side.is_back {
+            let (x, y) = pdf_creator::booklet_marker_position(w, h);
+            let _ = write!(
+                content,
+                "BT\n/F1 6 Tf\n{} {} Td\n{} Tj\nET\n",
+                num(x),
+                num(y),
+                pdf_info::encode_text(&pdf_creator::booklet_marker(booklet_num))
+            );
+        }
+
+        let content_id = writer.alloc();
+        writer.write_stream(content_id, "", &deflate(content.as_bytes())?);
+        let page_id = writer.alloc();
+        writer.write_object(
+            page_id,
+            format!(
+                "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {} {}] /Resources << /XObject <<{} >> /Font << /F1 {} 0 R >> >> /Contents {} 0 R >>",
+                pages_id,
+                num(w),
+                num(h),
+                xobjects,
+                font_id,
+                content_id
+            )
+            .as_bytes(),
+        );
+        page_ids.push(page_id);
+    }
+
+    finish_document(writer, catalog_id, pages_id, info_id, &page_ids, info)
+}
+
+/// 写出页面树、目录和文档信息，完成整个文件
+fn finish_document(
+    mut writer: PdfWriter,
+    catalog_id: u32,
+    pages_id: u32,
+    info_id: u32,
+    page_ids: &[u32],
+    info: &[InfoEntry],
+) -> Result<Vec<u8>, BookletError> {
+    let kids: Vec<String> = page_ids.iter().map(|id| format!("{} 0 R", id)).collect();
+    writer.write_object(
+        pages_id,
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "),
+            page_ids.len()
+        )
+        .as_bytes(),
+    );
+    writer.write_object(
+        catalog_id,
+        format!("<< /Type /Catalog /Pages {} 0 R >>", pages_id).as_bytes(),
+    );
+    let mut info_dict = String::from("<<");
+    for (key, value) in info {
+        let _ = write!(info_dict, " /{} {}", key, pdf_info::encode_text(value));
+    }
+    info_dict.push_str(" >>");
+    writer.write_object(info_id, info_dict.as_bytes());
+    Ok(writer.finish(catalog_id, info_id))
+}
+
+/// 把多个PDF文件中的页面原样复制到一个新文件中
+///
+/// 每页作为一个表单对象，页面大小和 `/Rotate` 与源页面相同，用于把已生成的册子中的
+/// 纸张重新组合
+///
+/// # 参数
+/// * `pages` - 依次复制的页面：所在的PDF文件和页面索引
+/// * `info` - 文档信息
+///
+/// # 返回
+/// 返回完整的PDF文件内容
+pub fn copy_pages(pages: &[(&Path, u16)], info: &[InfoEntry]) -> Result<Vec<u8>, BookletError> {
+    let mut writer = PdfWriter::new();
+    let catalog_id = writer.alloc();
+    let pages_id = writer.alloc();
+    let info_id = writer.alloc();
+    let mut sources: HashMap<PathBuf, SourceFile> = HashMap::new();
+    let mut page_ids = Vec::with_capacity(pages.len());
+    for (path, page_idx) in pages {
+        let source = match sources.entry(path.to_path_buf()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(SourceFile::open(path)?),
+        };
+        let form = source.page_form(&mut writer, *page_idx)?;
+        let content_id = writer.alloc();
+        writer.write_stream(content_id, "", b"/P0 Do");
+        let page_id = writer.alloc();
+        writer.write_object(
+            page_id,
+            format!(
+                "<< /Type /Page /Parent {} 0 R /MediaBox [{}] /Rotate {} /Resources << /XObject << /P0 {} 0 R >> >> /Contents {} 0 R >>",
+                pages_id,
+                form.bbox.map(num).join(" "),
+                form.rotation,
+                form.id,
+                content_id
+            )
+            .as_bytes(),
+        );
+        page_ids.push(page_id);
+    }
+    finish_document(writer, catalog_id, pages_id, info_id, &page_ids, info)
+}
+
+/// 计算表单对象放到纸上的变换矩阵和裁剪区域
+///
+/// 依次：按 `/Rotate` 转为页面显示的方向，按裁剪框截取，与位图排版一样旋转90°或270°，
+/// 最后在放置区域内等比缩放，按 `anchor` 靠装订侧和天头对齐。
+///
+/// # 参数
+/// * `form` - 源页面对应的表单对象
+/// * `crop_box` - 裁剪框（以显示方向的左上角为原点）
+/// * `reverse_image` - 是否旋转270°（天头朝左），否则旋转90°（天头朝右）
+/// * `slot` - 放置区域 (x, y, width, height)
+/// * `anchor` - 对齐方式，见 `pdf_creator::slot_anchor`
+///
+/// # 返回
+/// 返回 (变换矩阵, 纸上的裁剪区域 (x, y, width, height))
+fn place_form(
+    form: &PageForm,
+    crop_box: &CropBox,
+    reverse_image: bool,
+    slot: (f64, f64, f64, f64),
+    anchor: (f64, f64),
+) -> (Matrix, (f64, f64, f64, f64)) {
+    let [x0, y0, x1, y1] = form.bbox;
+    let (w0, h0) = (x1 - x0, y1 - y0);
+    let mut m = [1.0, 0.0, 0.0, 1.0, -x0, -y0];
+    // 页面按顺时针旋转显示
+    let (disp_w, disp_h) = match form.rotation {
+        90 => {
+            m = concat(m, [0.0, -1.0, 1.0, 0.0, 0.0, w0]);
+            (h0, w0)
+        }
+        180 => {
+            m = concat(m, [-1.0, 0.0, 0.0, -1.0, w0, h0]);
+            (w0, h0)
+        }
+        270 => {
+            m = concat(m, [0.0, 1.0, -1.0, 0.0, h0, 0.0]);
+            (h0, w0)
+        }
+        _ => (w0, h0),
+    };
+    // 裁剪框以左上角为原点，PDF坐标以左下角为原点
+    let crop_x = crop_box.left * disp_w;
+    let crop_y = (1.0 - crop_box.bottom) * disp_h;
+    let crop_w = (crop_box.right - crop_box.left) * disp_w;
+    let crop_h = (crop_box.bottom - crop_box.top) * disp_h;
+    m = concat(m, [1.0, 0.0, 0.0, 1.0, -crop_x, -crop_y]);
+    m = if reverse_image {
+        // 逆时针90°，天头朝左
+        concat(m, [0.0, 1.0, -1.0, 0.0, crop_h, 0.0])
+    } else {
+        // 顺时针90°，天头朝右
+        concat(m, [0.0, -1.0, 1.0, 0.0, 0.0, crop_w])
+    };
+    let (rot_w, rot_h) = (crop_h, crop_w);
+    let clip = pdf_creator::fit_rect(slot, anchor, rot_w, rot_h);
+    let scale = clip.2 / rot_w;
+    m = concat(m, [scale, 0.0, 0.0, scale, clip.0, clip.1]);
+    (m, clip)
+}
+
+/// 矩阵相乘，先做 `a` 变换再做 `b` 变换
+fn concat(a: Matrix, b: Matrix) -> Matrix {
+    [
+        a[0] * b[0] + a[1] * b[2],
+        a[0] * b[1] + a[1] * b[3],
+        a[2] * b[0] + a[3] * b[2],
+        a[2] * b[1] + a[3] * b[3],
+        a[4] * b[0] + a[5] * b[2] + b[4],
+        a[4] * b[1] + a[5] * b[3] + b[5],
+    ]
+}
+
+/// 格式化PDF数值，最多保留4位小数
+fn num(value: f64) -> String {
+    let text = format!("{:.4}", value);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    if text == "-0" {
+        "0".to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+/// 用zlib压缩流数据
+fn deflate(data: &[u8]) -> Result<Vec<u8>, BookletError> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+/// 源页面对应的表单对象
+#[derive(Clone, Copy)]
+struct PageForm {
+    /// 输出文件中的对象编号
+    id: u32,
+    /// 表单的边界，即源页面的CropBox（没有时为MediaBox）
+    bbox: [f64; 4],
+    /// 源页面的 `/Rotate`，顺时针，取值0、90、180、270
+    rotation: i32,
+}
+
+/// 待复制的对象
+enum Pending {
+    /// 源文件中的间接对象
+    Source(u32, u16),
+    /// 错误地直接嵌套在其他对象中的流，复制为单独的对象
+    Inline(Box<PdfObject>),
+}
+
+/// 一个源PDF文件，复制其中的页面和页面引用的对象
+struct SourceFile {
+    doc: PdfDocument<File>,
+    /// 源文件对象编号 → 输出文件对象编号
+    copied: HashMap<(u32, u16), u32>,
+    /// 源页面索引 → 已生成的表单对象
+    forms: HashMap<u16, PageForm>,
+}
+
+impl SourceFile {
+    fn open(path: &Path) -> Result<Self, BookletError> {
+        let (doc, _) = page_source::open_pdf(path)?;
+        Ok(Self {
+            doc,
+            copied: HashMap::new(),
+            forms: HashMap::new(),
+        })
+    }
+
+    /// 把源页面复制为表单对象，同一页只复制一次
+    ///
+    /// 页面的多个内容流合并后重新压缩，资源字典及其引用的字体、图像等对象原样复制
+    fn page_form(
+        &mut self,
+        writer: &mut PdfWriter,
+        page_idx: u16,
+    ) -> Result<PageForm, BookletError> {
+        if let Some(form) = self.forms.get(&page_idx) {
+            return Ok(*form);
+        }
+        let page = self.doc.get_page(page_idx as u32)?;
+        let bbox = page.crop_box.unwrap_or(page.media_box);
+        let content = self.doc.get_page_content_streams(&page)?.join(&b'\n');
+        let mut pending = Vec::new();
+        let mut dict = String::new();
+        let _ = write!(
+            dict,
+            " /Type /XObject /Subtype /Form /BBox [{}] /Resources ",
+            bbox.map(num).join(" ")
+        );
+        match page.dict.get("Resources") {
+            Some(resources) => self.serialize(writer, resources, &mut dict, &mut pending),
+            None => match &page.inherited_resources {
+                Some(resources) => self.serialize_dict(writer, resources, &mut dict, &mut pending),
+                None => dict.push_str("<< >>"),
+            },
+        }
+        if let Some(group) = page.dict.get("Group") {
+            dict.push_str(" /Group ");
+            self.serialize(writer, group, &mut dict, &mut pending);
+        }
+        dict.push_str(" /Filter /FlateDecode");
+        let id = writer.alloc();
+        writer.write_stream(id, &dict, &deflate(&content)?);
+        self.copy_pending(writer, pending)?;
+        let form = PageForm {
+            id,
+            bbox,
+            rotation: page.rotation.rem_euclid(360),
+        };
+        self.forms.insert(page_idx, form);
+        Ok(form)
+    }
+
+    /// 依次复制待复制的对象，复制时遇到的新引用加入队列
+    fn copy_pending(
+        &mut self,
+        writer: &mut PdfWriter,
+        mut pending: Vec<(u32, Pending)>,
+    ) -> Result<(), BookletError> {
+        while let Some((id, item)) = pending.pop() {
+            let obj = match item {
+                Pending::Source(num, gen_num) => self.doc.get_object(num, gen_num)?,
+                Pending::Inline(obj) => *obj,
+            };
+            match &obj {
+                PdfObject::Stream(stream) => {
+                    let mut dict = String::new();
+                    for (key, value) in &stream.dict.0 {
+                        // 长度按实际数据重新写，原来的长度可能是间接对象
+                        if key.0 == "Length" {
+                            continue;
+                        }
+                        dict.push(' ');
+                        write_name(&key.0, &mut dict);
+                        dict.push(' ');
+                        self.serialize(writer, value, &mut dict, &mut pending);
+                    }
+                    writer.write_stream(id, &dict, &stream.data);
+                }
+                obj => {
+                    let mut body = String::new();
+                    self.serialize(writer, obj, &mut body, &mut pending);
+                    writer.write_object(id, body.as_bytes());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 把对象写为PDF语法，引用改为输出文件中的对象编号
+    fn serialize(
+        &mut self,
+        writer: &mut PdfWriter,
+        obj: &PdfObject,
+        out: &mut String,
+        pending: &mut Vec<(u32, Pending)>,
+    ) {
+        match obj {
+            PdfObject::Null => out.push_str("null"),
+            PdfObject::Boolean(value) => out.push_str(if *value { "true" } else { "false" }),
+            PdfObject::Integer(value) => {
+                let _ = write!(out, "{}", value);
+            }
+            PdfObject::Real(value) => out.push_str(&num(*value)),
+            PdfObject::String(value) => {
+                out.push('<');
+                for byte in &value.0 {
+                    let _ = write!(out, "{:02X}", byte);
+                }
+                out.push('>');
+            }
+            PdfObject::Name(name) => write_name(&name.0, out),
+            PdfObject::Array(array) => {
+                out.push('[');
+                for (i, item) in array.0.iter().enumerate() {
+                    if i > 0 {
+                        out.push(' ');
+                    }
+                    self.serialize(writer, item, out, pending);
+                }
+                out.push(']');
+            }
+            PdfObject::Dictionary(dict) => self.serialize_dict(writer, dict, out, pending),
+            PdfObject::Stream(_) => {
+                let id = writer.alloc();
+                pending.push((id, Pending::Inline(Box::new(obj.clone()))));
+                let _ = write!(out, "{} 0 R", id);
+            }
+            PdfObject::Reference(num, gen_num) => {
+                let id = match self.copied.get(&(*num, *gen_num)) {
+                    Some(id) => *id,
+                    None => {
+                        let id = writer.alloc();
+                        self.copied.insert((*num, *gen_num), id);
+                        pending.push((id, Pending::Source(*num, *gen_num)));
+                        id
+                    }
+                };
+                let _ = write!(out, "{} 0 R", id);
+            }
+        }
+    }
+
+    fn serialize_dict(
+        &mut self,
+        writer: &mut PdfWriter,
+        dict: &PdfDictionary,
+        out: &mut String,
+        pending: &mut Vec<(u32, Pending)>,
+    ) {
+        // 页面树节点不复制指向上级的引用，否则会把整个源文件复制过来；
+        // 注释、书签等其他字典的 `/Parent` 照常复制
+        let page_node = matches!(
+            dict.get("Type"),
+            Some(PdfObject::Name(name)) if name.0 == "Page" || name.0 == "Pages"
+        );
+        out.push_str("<<");
+        for (key, value) in &dict.0 {
+            if page_node && key.0 == "Parent" {
+                continue;
+            }
+            out.push(' ');
+            write_name(&key.0, out);
+            out.push(' ');
+            self.serialize(writer, value, out, pending);
+        }
+        out.push_str(" >>");
+    }
+}
+
+/// 写PDF名称，特殊字符和非ASCII字符写为 `#XX`
+fn write_name(name: &str, out: &mut String) {
+    out.push('/');
+    for byte in name.bytes() {
+        if byte.is_ascii_graphic() && !b"()<>[]{}/%#".contains(&byte) {
+            out.push(byte as char);
+        } else {
+            let _ = write!(out, "#{:02X}", byte);
+        }
+    }
+}
+
+/// 依次写出PDF对象，最后生成交叉引用表
+struct PdfWriter {
+    data: Vec<u8>,
+    /// 第n个元素为n+1号对象在文件中的位置，还没写出时为 `None`
+    offsets: Vec<Option<usize>>,
+}
+
+impl PdfWriter {
+    fn new() -> Self {
+        Self {
+            data: b"%PDF-1.7\n%\xE2\xE3\xCF\xD3\n".to_vec(),
+            offsets: Vec::new(),
+        }
+    }
+
+    /// 分配一个对象编号，之后用 `write_object` 或 `write_stream` 写出
+    fn alloc(&mut self) -> u32 {
+        self.offsets.push(None);
+        self.offsets.len() as u32
+    }
+
+    fn write_object(&mut self, id: u32, body: &[u8]) {
+        self.offsets[id as usize - 1] = Some(self.data.len());
+        let _ = writeln!(self.data, "{} 0 obj", id);
+        self.data.extend_from_slice(body);
+        self.data.extend_from_slice(b"\nendobj\n");
+    }
+
+    /// 写出流对象
+    ///
+    /// # 参数
+    /// * `dict` - 流字典的内容（不含 `<<`、`>>` 和 `/Length`）
+    /// * `data` - 流数据，按字典中的 `/Filter` 编码
+    fn write_stream(&mut self, id: u32, dict: &str, data: &[u8]) {
+        let mut body = format!("<<{} /Length {} >>\nstream\n", dict, data.len()).into_bytes();
+        body.extend_from_slice(data);
+        body.extend_from_slice(b"\nendstream");
+        self.write_object(id, &body);
+    }
+
+    /// 写出交叉引用表和trailer，返回完整的文件内容
+    fn finish(mut self, root_id: u32, info_id: u32) -> Vec<u8> {
+        let xref_pos = self.data.len();
+        let size = self.offsets.len() + 1;
+        let _ = write!(self.data, "xref\n0 {}\n0000000000 65535 f \n", size);
+        for offset in &self.offsets {
+            // 每个分配的编号都会写出，这里只是防止生成无效的交叉引用表
+            match offset {
+                Some(offset) => {
+                    let _ = writeln!(self.data, "{:010} 00000 n ", offset);
+                }
+                None => self.data.extend_from_slice(b"0000000000 00001 f \n"),
+            }
+        }
+        let _ = write!(
+            self.data,
+            "trailer\n<< /Size {} /Root {} 0 R /Info {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+            size, root_id, info_id, xref_pos
+        );
+        self.data
+    }
+}
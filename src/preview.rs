@@ -0,0 +1,273 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use image::codecs::png::PngEncoder;
+use image::imageops::{self, FilterType};
+use image::{ImageEncoder, Rgba, RgbaImage};
+
+use crate::booklet::{self, BindingRule};
+use crate::crop;
+use crate::error::BookletError;
+use crate::output;
+use crate::page_source::PageSource;
+use crate::pdf_creator::{self, SheetSide};
+
+/// 每行的跨页数
+const SPREADS_PER_ROW: u32 = 4;
+/// 跨页之间和四周的间距(px)
+const GAP: u32 = 24;
+/// 页面上方标记所属册子的色条高度(px)
+const STRIPE: u32 = 4;
+const BACKGROUND: Rgba<u8> = Rgba([200, 200, 200, 255]);
+const PAPER: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const BLANK: Rgba<u8> = Rgba([235, 235, 235, 255]);
+const LINE: Rgba<u8> = Rgba([120, 120, 120, 255]);
+/// 相邻的册子交替使用两种颜色
+const STRIPE_COLORS: [Rgba<u8>; 2] = [Rgba([40, 110, 220, 255]), Rgba([230, 130, 30, 255])];
+
+/// 阅读顺序中的一页及其在纸上的位置
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReadingPage {
+    /// 所属册子编号
+    pub booklet_num: u16,
+    /// 纸张编号（从1开始）
+    pub sheet: usize,
+    /// 是否在纸张背面
+    pub is_back: bool,
+    /// 是否在纸张上半部分
+    pub is_top: bool,
+    /// 源页面索引，空白页为 `None`
+    pub page_idx: Option<u16>,
+}
+
+impl fmt::Display for ReadingPage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "第{}册第{}张{}{}半部分",
+            self.booklet_num,
+            self.sheet,
+            if self.is_back { "背面" } else { "正面" },
+            if self.is_top { "上" } else { "下" }
+        )
+    }
+}
+
+/// 模拟折叠一册的纸张，得到读者看到的页面顺序
+///
+/// 中间装订：纸张按顺序叠放后对折，先是每张纸下半部分的正面、背面（由外到内），
+/// 再是上半部分的背面、正面（由内到外）。
+/// 两边装订：纸张叠放后从中间裁开，上半部分叠在下半部分之前，每张先正面后背面。
+///
+/// # 参数
+/// * `booklet_num` - 册子编号
+/// * `sides` - 本册每面纸上的页面，每张纸正面在前、背面在后
+/// * `page_count` - 源文件页数
+/// * `binding_at_middle` - 是否在中间装订
+pub fn booklet_reading_order(
+    booklet_num: u16,
+    sides: &[SheetSide],
+    page_count: u16,
+    binding_at_middle: bool,
+) -> Vec<ReadingPage> {
+    let slot = |side_idx: usize, is_top: bool| {
+        let page_idx = sides.get(side_idx).map_or(u16::MAX, |side| {
+            let (top, bottom) = side.top_bottom(binding_at_middle);
+            if is_top { top } else { bottom }
+        });
+        ReadingPage {
+            booklet_num,
+            sheet: side_idx / 2 + 1,
+            is_back: side_idx % 2 == 1,
+            is_top,
+            page_idx: (page_idx < page_count).then_some(page_idx),
+        }
+    };
+    let sheet_count = sides.len().div_ceil(2);
+    let (first_half, second_half) = if binding_at_middle {
+        (false, true)
+    } else {
+        (true, false)
+    };
+    let first =
+        (0..sheet_count).flat_map(|s| [slot(s * 2, first_half), slot(s * 2 + 1, first_half)]);
+    if binding_at_middle {
+        // 上半部分折到内侧，由内到外先背面后正面
+        let second = (0..sheet_count)
+            .rev()
+            .flat_map(|s| [slot(s * 2 + 1, second_half), slot(s * 2, second_half)]);
+        first.chain(second).collect()
+    } else {
+        let second =
+            (0..sheet_count).flat_map(|s| [slot(s * 2, second_half), slot(s * 2 + 1, second_half)]);
+        first.chain(second).collect()
+    }
+}
+
+/// 全书的阅读顺序，各册依次相接
+///
+/// # 参数
+/// * `page_count` - 源文件页数
+/// * `binding_rule` - 装订规则
+pub fn reading_order(page_count: u16, binding_rule: &BindingRule) -> Vec<ReadingPage> {
+    booklet::plan_booklets(page_count, binding_rule)
+        .iter()
+        .flat_map(|booklet| {
+            let sides = pdf_creator::calc_sheet_sides(booklet, binding_rule);
+            booklet_reading_order(
+                booklet.booklet_num,
+                &sides,
+                page_count,
+                binding_rule.binding_at_middle,
+            )
+        })
+        .collect()
+}
+
+/// 生成折叠后的阅读顺序预览图
+///
+/// 预览图由 `render_preview` 绘制，保存为PNG。
+///
+/// # 参数
+/// * `src_pdf` - 源页面
+/// * `binding_rule` - 装订规则，自动裁剪需要先解析为手动裁剪
+/// * `thumb_width` - 每页缩略图的宽度(px)
+///
+/// # 返回
+/// 返回预览图路径：输出目录下的 `{源文件名}_preview.png`，已存在时按覆盖策略处理，
+/// 跳过时返回 `None`
+pub fn create_preview(
+    src_pdf: &dyn PageSource,
+    binding_rule: &BindingRule,
+    thumb_width: u32,
+) -> Result<Option<PathBuf>, BookletError> {
+    let sheet = render_preview(src_pdf, binding_rule, thumb_width)?;
+    let (width, height) = sheet.dimensions();
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png)
+        .write_image(
+            sheet.as_raw(),
+            width,
+            height,
+            image::ExtendedColorType::Rgba8,
+        )
+        .map_err(std::io::Error::other)?;
+    let name = output::source_name(&binding_rule.input_path)?;
+    std::fs::create_dir_all(&binding_rule.output_dir)?;
+    let target_path = binding_rule
+        .output_dir
+        .join(format!("{}_preview.png", name));
+    let Some(path) = output::write_resolved(&target_path, &png, binding_rule.overwrite)? else {
+        log::info!(path:% = target_path.display(); "文件已存在，跳过预览图");
+        return Ok(None);
+    };
+    log::info!(path:% = path.display(); "预览图: {}", path.display());
+    Ok(Some(path))
+}
+
+/// 绘制折叠后的阅读顺序预览图
+///
+/// 按读者看到的跨页排列缩略图：第1页单独在右侧，之后是2-3、4-5……跨页。
+/// 空白页显示为带叉的灰色页面，页面上方的色条标记所属册子。
+/// 缩略图按装订规则裁剪，不做扫描清理。
+///
+/// # 参数
+/// * `src_pdf` - 源页面，需要能够渲染
+/// * `binding_rule` - 装订规则，自动裁剪需要先解析为手动裁剪
+/// * `thumb_width` - 每页缩略图的宽度(px)
+pub fn render_preview(
+    src_pdf: &dyn PageSource,
+    binding_rule: &BindingRule,
+    thumb_width: u32,
+) -> Result<RgbaImage, BookletError> {
+    let page_count = src_pdf.get_page_count();
+    let pages = reading_order(page_count, binding_rule);
+    let (body_start, body_end) = binding_rule.body_page_range(page_count);
+    let cell_w = thumb_width;
+    let cell_h = (thumb_width as f64 * std::f64::consts::SQRT_2).round() as u32;
+
+    // 第1页单独成为一个跨页的右页，之后每两页一个跨页
+    let spread_count = (pages.len() as u32 + 1).div_ceil(2);
+    let rows = spread_count.div_ceil(SPREADS_PER_ROW);
+    let cols = spread_count.min(SPREADS_PER_ROW);
+    let width = GAP + cols * (cell_w * 2 + GAP);
+    let height = GAP + rows * (cell_h + STRIPE + GAP);
+    let mut sheet = RgbaImage::from_pixel(width, height, BACKGROUND);
+
+    for (pos, page) in pages.iter().enumerate() {
+        let slot = pos as u32 + 1;
+        let spread = slot / 2;
+        let (row, col) = (spread / SPREADS_PER_ROW, spread % SPREADS_PER_ROW);
+        let x = GAP + col * (cell_w * 2 + GAP) + (slot % 2) * cell_w;
+        let y = GAP + row * (cell_h + STRIPE + GAP);
+        let stripe_color = STRIPE_COLORS[(page.booklet_num as usize + 1) % 2];
+        fill_rect(&mut sheet, x, y, cell_w, STRIPE, stripe_color);
+        let y = y + STRIPE;
+        match page.page_idx {
+            Some(page_idx) => {
+                fill_rect(&mut sheet, x, y, cell_w, cell_h, PAPER);
+                let (w, h, rgba) = src_pdf.get_page_preview(page_idx, cell_w as i32 * 2)?;
+                let is_body = page_idx >= body_start && page_idx < body_end;
+                let (w, h, rgba) = match binding_rule.crop.crop_box(page_idx) {
+                    Some(crop_box) if is_body => crop::crop_rgba(w, h, rgba, crop_box),
+                    _ => (w, h, rgba),
+                };
+                let thumb = fit_thumbnail(w, h, rgba, cell_w, cell_h);
+                let dx = (cell_w - thumb.width()) / 2;
+                let dy = (cell_h - thumb.height()) / 2;
+                imageops::overlay(&mut sheet, &thumb, (x + dx) as i64, (y + dy) as i64);
+            }
+            None => {
+                fill_rect(&mut sheet, x, y, cell_w, cell_h, BLANK);
+                draw_line(&mut sheet, (x, y), (x + cell_w - 1, y + cell_h - 1));
+                draw_line(&mut sheet, (x + cell_w - 1, y), (x, y + cell_h - 1));
+            }
+        }
+        // 跨页中缝
+        if slot % 2 == 1 {
+            draw_line(&mut sheet, (x, y), (x, y + cell_h - 1));
+        }
+        log::trace!(position = pos + 1, page_idx = page.page_idx; "预览第{}页", pos + 1);
+    }
+
+    let blank_pages = pages.iter().filter(|page| page.page_idx.is_none()).count();
+    log::debug!(
+        pages = pages.len(), blank_pages;
+        "预览共{}页（空白{}页）",
+        pages.len(),
+        blank_pages
+    );
+    Ok(sheet)
+}
+
+/// 把图像等比缩小到不超过 `max_w` x `max_h`
+fn fit_thumbnail(width: u32, height: u32, rgba: Vec<u8>, max_w: u32, max_h: u32) -> RgbaImage {
+    let img = RgbaImage::from_raw(width, height, rgba).expect("RGBA数据与尺寸不符");
+    let scale = (max_w as f64 / width as f64).min(max_h as f64 / height as f64);
+    let w = ((width as f64 * scale).round() as u32).clamp(1, max_w);
+    let h = ((height as f64 * scale).round() as u32).clamp(1, max_h);
+    imageops::resize(&img, w, h, FilterType::Triangle)
+}
+
+fn fill_rect(img: &mut RgbaImage, x: u32, y: u32, w: u32, h: u32, color: Rgba<u8>) {
+    for py in y..(y + h).min(img.height()) {
+        for px in x..(x + w).min(img.width()) {
+            img.put_pixel(px, py, color);
+        }
+    }
+}
+
+/// 画1像素宽的直线
+fn draw_line(img: &mut RgbaImage, from: (u32, u32), to: (u32, u32)) {
+    let (x0, y0) = (from.0 as f64, from.1 as f64);
+    let (x1, y1) = (to.0 as f64, to.1 as f64);
+    let steps = (x1 - x0).abs().max((y1 - y0).abs()).max(1.0) as u32;
+    for i in 0..=steps {
+        let t = i as f64 / steps as f64;
+        let x = (x0 + (x1 - x0) * t).round() as u32;
+        let y = (y0 + (y1 - y0) * t).round() as u32;
+        if x < img.width() && y < img.height() {
+            img.put_pixel(x, y, LINE);
+        }
+    }
+}
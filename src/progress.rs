@@ -0,0 +1,184 @@
+use std::cell::Cell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+use crate::error::BookletError;
+
+/// 进度信息，每渲染完一个源页面报告一次
+#[derive(Clone, Debug)]
+pub struct Progress {
+    /// 当前册子编号（从1开始）
+    pub booklet_num: u16,
+    /// 册子总数
+    pub booklet_count: u16,
+    /// 当前册中的纸张编号（从1开始）
+    pub sheet_num: usize,
+    /// 当前册的纸张数
+    pub sheet_count: usize,
+    /// 刚渲染完的源页面索引（从0开始）
+    pub page_idx: u16,
+    /// 已渲染的页面数
+    pub done_pages: usize,
+    /// 需要渲染的页面总数（不含空白页）
+    pub total_pages: usize,
+    /// 完成百分比
+    pub percent: f64,
+    /// 预计剩余时间
+    pub eta: Option<Duration>,
+}
+
+/// 进度监听器
+///
+/// 闭包 `Fn(&Progress)` 和 `Sender<Progress>` 都可以直接作为监听器使用。
+/// `LogProgress` 把进度写入日志。
+pub trait ProgressListener {
+    fn on_progress(&self, progress: &Progress);
+}
+
+impl<F: Fn(&Progress)> ProgressListener for F {
+    fn on_progress(&self, progress: &Progress) {
+        self(progress)
+    }
+}
+
+impl ProgressListener for Sender<Progress> {
+    fn on_progress(&self, progress: &Progress) {
+        // 接收方已关闭时不再报告
+        let _ = self.send(progress.clone());
+    }
+}
+
+/// 把进度写入日志（info级别）
+pub struct LogProgress;
+
+impl ProgressListener for LogProgress {
+    fn on_progress(&self, progress: &Progress) {
+        let eta = progress
+            .eta
+            .map(|eta| format!("{:02}:{:02}", eta.as_secs() / 60, eta.as_secs() % 60))
+            .unwrap_or_else(|| "--:--".to_string());
+        log::info!(
+            booklet = progress.booklet_num,
+            sheet = progress.sheet_num,
+            page = progress.page_idx + 1,
+            done_pages = progress.done_pages,
+            total_pages = progress.total_pages,
+            percent = progress.percent,
+            eta_secs = progress.eta.map(|eta| eta.as_secs());
+            "[{:5.1}% {}/{}] 第{}/{}册 第{}/{}张 第{}页, 剩余约{}",
+            progress.percent,
+            progress.done_pages,
+            progress.total_pages,
+            progress.booklet_num,
+            progress.booklet_count,
+            progress.sheet_num,
+            progress.sheet_count,
+            progress.page_idx + 1,
+            eta
+        );
+    }
+}
+
+/// 取消令牌，可以克隆后交给其他线程，调用 `cancel` 后生成过程会在下一页之前停止
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 请求取消
+    #[cfg_attr(not(feature = "gui"), allow(dead_code))]
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// 是否已请求取消
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// 进度跟踪，计算百分比和剩余时间并通知监听器
+pub struct ProgressTracker<'a> {
+    listener: &'a dyn ProgressListener,
+    cancel: &'a CancelToken,
+    started: Instant,
+    booklet_count: u16,
+    total_pages: usize,
+    done_pages: Cell<usize>,
+    booklet_num: Cell<u16>,
+    sheet_count: Cell<usize>,
+}
+
+impl<'a> ProgressTracker<'a> {
+    /// # 参数
+    /// * `booklet_count` - 册子总数
+    /// * `total_pages` - 需要渲染的页面总数
+    pub fn new(
+        listener: &'a dyn ProgressListener,
+        cancel: &'a CancelToken,
+        booklet_count: u16,
+        total_pages: usize,
+    ) -> Self {
+        Self {
+            listener,
+            cancel,
+            started: Instant::now(),
+            booklet_count,
+            total_pages,
+            done_pages: Cell::new(0),
+            booklet_num: Cell::new(0),
+            sheet_count: Cell::new(0),
+        }
+    }
+
+    /// 开始生成新的一册
+    pub fn begin_booklet(&self, booklet_num: u16, sheet_count: usize) {
+        self.booklet_num.set(booklet_num);
+        self.sheet_count.set(sheet_count);
+    }
+
+    /// 检查是否已取消
+    pub fn check_cancelled(&self) -> Result<(), BookletError> {
+        if self.cancel.is_cancelled() {
+            Err(BookletError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// 跳过若干页面（不报告进度），用于跳过整册的情况
+    pub fn skip_pages(&self, count: usize) {
+        self.done_pages.set(self.done_pages.get() + count);
+    }
+
+    /// 一个源页面渲染完成
+    ///
+    /// # 参数
+    /// * `sheet_num` - 当前册中的纸张编号（从1开始）
+    /// * `page_idx` - 源页面索引
+    pub fn page_done(&self, sheet_num: usize, page_idx: u16) {
+        let done_pages = self.done_pages.get() + 1;
+        self.done_pages.set(done_pages);
+        let elapsed = self.started.elapsed();
+        let remain_pages = self.total_pages.saturating_sub(done_pages);
+        let eta = elapsed
+            .checked_div(done_pages as u32)
+            .map(|per_page| per_page * remain_pages as u32);
+        self.listener.on_progress(&Progress {
+            booklet_num: self.booklet_num.get(),
+            booklet_count: self.booklet_count,
+            sheet_num,
+            sheet_count: self.sheet_count.get(),
+            page_idx,
+            done_pages,
+            total_pages: self.total_pages,
+            percent: done_pages as f64 * 100.0 / self.total_pages.max(1) as f64,
+            eta,
+        });
+    }
+}
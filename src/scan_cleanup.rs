@@ -0,0 +1,497 @@
+use crate::crop::luminance;
+use image::imageops::{self, FilterType};
+use image::{Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+/// 灰度低于该值的像素视为黑色（文字、噪点）
+const DARK_LEVEL: u8 = 128;
+
+/// 二值化方式
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Binarize {
+    /// 固定阈值，灰度低于该值为黑色
+    Fixed(u8),
+    /// 大津法自动计算阈值
+    Otsu,
+}
+
+/// 扫描页面清理流程，每一步都可单独开启
+///
+/// 处理顺序：纠偏 → 背景变白 → 对比度 → gamma → 去噪点 → 二值化
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanCleanup {
+    /// 纠偏，参数为最大检测角度（度）
+    pub deskew: Option<f64>,
+    /// 背景变白，灰度不低于该值的颜色变为白色，其余按比例提亮
+    pub whiten_background: Option<u8>,
+    /// 对比度系数，大于1增强对比度
+    pub contrast: Option<f32>,
+    /// gamma值，大于1提亮中间调
+    pub gamma: Option<f32>,
+    /// 去噪点，移除像素数不超过该值的孤立黑点
+    pub despeckle: Option<u32>,
+    /// 二值化
+    pub binarize: Option<Binarize>,
+}
+
+impl ScanCleanup {
+    /// 适合扫描书籍的默认参数
+    pub fn scanned_book() -> Self {
+        Self {
+            deskew: Some(5.0),
+            whiten_background: Some(210),
+            contrast: Some(1.2),
+            gamma: None,
+            despeckle: Some(4),
+            binarize: None,
+        }
+    }
+
+    /// 是否开启了任意一步处理
+    pub fn is_enabled(&self) -> bool {
+        *self != ScanCleanup::default()
+    }
+
+    /// 按顺序执行开启的处理步骤
+    pub fn apply(&self, img: &mut RgbaImage) {
+        if let Some(max_angle) = self.deskew {
+            deskew(img, max_angle);
+        }
+        if let Some(level) = self.whiten_background {
+            whiten_background(img, level);
+        }
+        if let Some(factor) = self.contrast {
+            adjust_contrast(img, factor);
+        }
+        if let Some(gamma) = self.gamma {
+            adjust_gamma(img, gamma);
+        }
+        if let Some(max_size) = self.despeckle {
+            despeckle(img, max_size);
+        }
+        if let Some(method) = self.binarize {
+            binarize(img, method);
+        }
+    }
+
+    /// 处理RGBA数据
+    ///
+    /// # 返回
+    /// 返回 (width, height, rgba_bytes) 元组
+    pub fn process(&self, width: u32, height: u32, rgba: Vec<u8>) -> (u32, u32, Vec<u8>) {
+        let mut img = RgbaImage::from_raw(width, height, rgba).expect("RGBA数据与尺寸不符");
+        self.apply(&mut img);
+        (img.width(), img.height(), img.into_raw())
+    }
+}
+
+/// 检测并纠正页面倾斜
+///
+/// # 返回
+/// 返回纠正的角度（度）
+pub fn deskew(img: &mut RgbaImage, max_angle: f64) -> f64 {
+    let angle = detect_skew(img, max_angle);
+    // 小于0.05°的倾斜肉眼不可见，不值得重采样
+    if angle.abs() >= 0.05 {
+        *img = rotate(img, angle);
+    }
+    angle
+}
+
+/// 检测页面倾斜角度
+///
+/// 把黑色像素按候选角度旋转后分别投影到横轴和纵轴，投影越集中（平方和越大）
+/// 说明文字行越整齐。先以0.5°步长粗查，再以0.05°步长细查。
+/// 页面渲染时旋转了90°，文字行可能是竖直的，所以两个方向的投影都计算。
+///
+/// # 返回
+/// 返回使页面摆正需要旋转的角度（度），与 `rotate` 的方向一致
+pub fn detect_skew(img: &RgbaImage, max_angle: f64) -> f64 {
+    // 缩小后再分析，足以检测角度
+    let scale = (1000.0 / img.width().max(img.height()) as f64).min(1.0);
+    let small;
+    let img = if scale < 1.0 {
+        small = imageops::resize(
+            img,
+            ((img.width() as f64 * scale) as u32).max(1),
+            ((img.height() as f64 * scale) as u32).max(1),
+            FilterType::Triangle,
+        );
+        &small
+    } else {
+        img
+    };
+    let (cx, cy) = (img.width() as f64 / 2.0, img.height() as f64 / 2.0);
+    let points: Vec<(f64, f64)> = img
+        .enumerate_pixels()
+        .filter(|(_, _, px)| luminance(&px.0) < DARK_LEVEL)
+        .map(|(x, y, _)| (x as f64 - cx, y as f64 - cy))
+        .collect();
+    if points.len() < 100 {
+        return 0.0;
+    }
+    let radius = (cx * cx + cy * cy).sqrt().ceil() as usize + 1;
+    let score = |angle: f64| {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let mut rows = vec![0u32; radius * 2];
+        let mut cols = vec![0u32; radius * 2];
+        for (x, y) in &points {
+            let rx = x * cos - y * sin;
+            let ry = x * sin + y * cos;
+            cols[(rx + radius as f64) as usize] += 1;
+            rows[(ry + radius as f64) as usize] += 1;
+        }
+        rows.iter()
+            .chain(cols.iter())
+            .map(|&c| (c as u64) * (c as u64))
+            .sum::<u64>()
+    };
+    let search = |from: f64, to: f64, step: f64| {
+        let steps = ((to - from) / step).round() as i32;
+        (0..=steps)
+            .map(|i| from + i as f64 * step)
+            .max_by_key(|&angle| score(angle))
+            .unwrap_or(0.0)
+    };
+    let coarse = search(-max_angle, max_angle, 0.5);
+    search(coarse - 0.5, coarse + 0.5, 0.05)
+}
+
+/// 绕中心旋转图像，超出原图的区域填充白色
+///
+/// 坐标系与图像一致（y轴向下），正角度使内容顺时针旋转。
+pub fn rotate(img: &RgbaImage, angle: f64) -> RgbaImage {
+    let (w, h) = img.dimensions();
+    let (cx, cy) = (w as f64 / 2.0, h as f64 / 2.0);
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let white = Rgba([255, 255, 255, 255]);
+    RgbaImage::from_fn(w, h, |x, y| {
+        // 反向映射到原图坐标
+        let (dx, dy) = (x as f64 + 0.5 - cx, y as f64 + 0.5 - cy);
+        let sx = dx * cos + dy * sin + cx - 0.5;
+        let sy = -dx * sin + dy * cos + cy - 0.5;
+        if sx < 0.0 || sy < 0.0 || sx > (w - 1) as f64 || sy > (h - 1) as f64 {
+            return white;
+        }
+        let (x0, y0) = (sx.floor() as u32, sy.floor() as u32);
+        let (x1, y1) = ((x0 + 1).min(w - 1), (y0 + 1).min(h - 1));
+        let (fx, fy) = (sx - x0 as f64, sy - y0 as f64);
+        let (p00, p10) = (img.get_pixel(x0, y0), img.get_pixel(x1, y0));
+        let (p01, p11) = (img.get_pixel(x0, y1), img.get_pixel(x1, y1));
+        let mut px = [0u8; 4];
+        for (i, v) in px.iter_mut().enumerate() {
+            let top = p00[i] as f64 * (1.0 - fx) + p10[i] as f64 * fx;
+            let bottom = p01[i] as f64 * (1.0 - fx) + p11[i] as f64 * fx;
+            *v = (top * (1.0 - fy) + bottom * fy).round() as u8;
+        }
+        Rgba(px)
+    })
+}
+
+/// 背景变白
+///
+/// 各颜色通道按 `level` 拉伸，不低于 `level` 的值变为255，
+/// 纸张底色和透印变白，文字颜色基本不变。
+pub fn whiten_background(img: &mut RgbaImage, level: u8) {
+    if level == 0 {
+        return;
+    }
+    let lut = build_lut(|v| v * 255.0 / level as f64);
+    apply_lut(img, &lut);
+}
+
+/// 调整对比度，以中间灰度为中心拉伸
+pub fn adjust_contrast(img: &mut RgbaImage, factor: f32) {
+    let factor = factor as f64;
+    let lut = build_lut(|v| (v - 127.5) * factor + 127.5);
+    apply_lut(img, &lut);
+}
+
+/// gamma校正
+pub fn adjust_gamma(img: &mut RgbaImage, gamma: f32) {
+    if gamma <= 0.0 {
+        return;
+    }
+    let exponent = 1.0 / gamma as f64;
+    let lut = build_lut(|v| (v / 255.0).powf(exponent) * 255.0);
+    apply_lut(img, &lut);
+}
+
+/// 去噪点
+///
+/// 查找8连通的黑色像素区域，像素数不超过 `max_size` 的区域变为白色。
+///
+/// # 返回
+/// 返回移除的噪点数量
+pub fn despeckle(img: &mut RgbaImage, max_size: u32) -> usize {
+    let (w, h) = (img.width() as usize, img.height() as usize);
+    let dark: Vec<bool> = img
+        .pixels()
+        .map(|px| luminance(&px.0) < DARK_LEVEL)
+        .collect();
+    let mut visited = vec![false; w * h];
+    let mut removed = 0;
+    let mut stack = Vec::new();
+    let mut component = Vec::new();
+    for start in 0..w * h {
+        if !dark[start] || visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        stack.push(start);
+        component.clear();
+        while let Some(idx) = stack.pop() {
+            component.push(idx);
+            let (x, y) = (idx % w, idx / w);
+            for ny in y.saturating_sub(1)..=(y + 1).min(h - 1) {
+                for nx in x.saturating_sub(1)..=(x + 1).min(w - 1) {
+                    let n = ny * w + nx;
+                    if dark[n] && !visited[n] {
+                        visited[n] = true;
+                        stack.push(n);
+                    }
+                }
+            }
+        }
+        if component.len() <= max_size as usize {
+            removed += 1;
+            for &idx in &component {
+                let px = img.get_pixel_mut((idx % w) as u32, (idx / w) as u32);
+                px[0] = 255;
+                px[1] = 255;
+                px[2] = 255;
+            }
+        }
+    }
+    removed
+}
+
+/// 二值化，所有像素变为纯黑或纯白
+///
+/// # 返回
+/// 返回使用的阈值
+pub fn binarize(img: &mut RgbaImage, method: Binarize) -> u8 {
+    let threshold = match method {
+        Binarize::Fixed(threshold) => threshold,
+        Binarize::Otsu => otsu_threshold(img),
+    };
+    for px in img.pixels_mut() {
+        let v = if luminance(&px.0) < threshold { 0 } else { 255 };
+        px[0] = v;
+        px[1] = v;
+        px[2] = v;
+    }
+    threshold
+}
+
+/// 用大津法计算二值化阈值
+pub fn otsu_threshold(img: &RgbaImage) -> u8 {
+    let mut histogram = [0u64; 256];
+    for px in img.pixels() {
+        histogram[luminance(&px.0) as usize] += 1;
+    }
+    let total: u64 = histogram.iter().sum();
+    let sum_all: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(v, &c)| v as f64 * c as f64)
+        .sum();
+    let (mut weight_bg, mut sum_bg) = (0u64, 0f64);
+    let (mut best_threshold, mut best_variance) = (0u8, 0f64);
+    for (v, &count) in histogram.iter().enumerate() {
+        weight_bg += count;
+        if weight_bg == 0 {
+            continue;
+        }
+        let weight_fg = total - weight_bg;
+        if weight_fg == 0 {
+            break;
+        }
+        sum_bg += v as f64 * count as f64;
+        let mean_bg = sum_bg / weight_bg as f64;
+        let mean_fg = (sum_all - sum_bg) / weight_fg as f64;
+        let variance = weight_bg as f64 * weight_fg as f64 * (mean_bg - mean_fg).powi(2);
+        if variance > best_variance {
+            best_variance = variance;
+            // 灰度不高于v的一类为黑色，阈值取v+1
+            best_threshold = (v + 1).min(255) as u8;
+        }
+    }
+    best_threshold
+}
+
+/// 生成颜色查找表，结果限制在0~255
+fn build_lut(f: impl Fn(f64) -> f64) -> [u8; 256] {
+    let mut lut = [0u8; 256];
+    for (v, out) in lut.iter_mut().enumerate() {
+        *out = f(v as f64).round().clamp(0.0, 255.0) as u8;
+    }
+    lut
+}
+
+/// 对RGB通道应用查找表，透明度不变
+fn apply_lut(img: &mut RgbaImage, lut: &[u8; 256]) {
+    for px in img.pixels_mut() {
+        px[0] = lut[px[0] as usize];
+        px[1] = lut[px[1] as usize];
+        px[2] = lut[px[2] as usize];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 检测角度的允许误差(°)：细查步长0.05°，加上像素量化的误差
+    const SKEW_TOLERANCE: f64 = 0.15;
+
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+    const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+    /// 模拟文字页面：白底上若干行断续的黑色横条
+    fn text_page() -> RgbaImage {
+        let mut img = RgbaImage::from_pixel(400, 300, WHITE);
+        for line in 0..10 {
+            let y = 30 + line * 24;
+            for x in 40..360 {
+                // 每个“字”宽12px，字间空4px
+                if x % 16 < 12 {
+                    for dy in 0..6 {
+                        img.put_pixel(x, y + dy, BLACK);
+                    }
+                }
+            }
+        }
+        img
+    }
+
+    fn is_dark(img: &RgbaImage, x: u32, y: u32) -> bool {
+        luminance(&img.get_pixel(x, y).0) < DARK_LEVEL
+    }
+
+    #[test]
+    fn detect_skew_of_straight_page_is_zero() {
+        assert!(detect_skew(&text_page(), 5.0).abs() <= SKEW_TOLERANCE);
+    }
+
+    #[test]
+    fn detect_skew_finds_rotation() {
+        for angle in [2.0, -3.5] {
+            let skewed = rotate(&text_page(), angle);
+            let detected = detect_skew(&skewed, 5.0);
+            assert!(
+                (detected + angle).abs() <= SKEW_TOLERANCE,
+                "旋转{}°，检测出{}°",
+                angle,
+                detected
+            );
+        }
+    }
+
+    #[test]
+    fn deskew_straightens_page() {
+        let mut img = rotate(&text_page(), 2.5);
+        let corrected = deskew(&mut img, 5.0);
+        assert!(
+            (corrected + 2.5).abs() <= SKEW_TOLERANCE,
+            "检测出{}°",
+            corrected
+        );
+        assert!(detect_skew(&img, 5.0).abs() <= SKEW_TOLERANCE);
+    }
+
+    #[test]
+    fn detect_skew_ignores_blank_page() {
+        let img = RgbaImage::from_pixel(200, 200, WHITE);
+        assert_eq!(detect_skew(&img, 5.0), 0.0);
+    }
+
+    #[test]
+    fn despeckle_removes_only_small_spots() {
+        let mut img = RgbaImage::from_pixel(50, 50, WHITE);
+        // 两个单像素噪点
+        img.put_pixel(5, 5, BLACK);
+        img.put_pixel(40, 10, BLACK);
+        // 2×2的噪点，对角相连的像素也算同一个区域
+        img.put_pixel(20, 20, BLACK);
+        img.put_pixel(21, 21, BLACK);
+        img.put_pixel(20, 21, BLACK);
+        img.put_pixel(21, 20, BLACK);
+        // 10×3的笔画
+        for x in 10..20 {
+            for y in 40..43 {
+                img.put_pixel(x, y, BLACK);
+            }
+        }
+        assert_eq!(despeckle(&mut img, 4), 3);
+        assert!(!is_dark(&img, 5, 5));
+        assert!(!is_dark(&img, 40, 10));
+        assert!(!is_dark(&img, 21, 21));
+        assert!((10..20).all(|x| (40..43).all(|y| is_dark(&img, x, y))));
+    }
+
+    #[test]
+    fn despeckle_keeps_alpha() {
+        let mut img = RgbaImage::from_pixel(5, 5, WHITE);
+        img.put_pixel(2, 2, Rgba([0, 0, 0, 100]));
+        assert_eq!(despeckle(&mut img, 1), 1);
+        assert_eq!(img.get_pixel(2, 2), &Rgba([255, 255, 255, 100]));
+    }
+
+    #[test]
+    fn whiten_background_clears_paper_and_keeps_text() {
+        let mut img = RgbaImage::from_fn(4, 1, |x, _| match x {
+            // 纸张底色、页边的阴影、文字和透印
+            0 => Rgba([230, 225, 215, 255]),
+            1 => Rgba([210, 210, 210, 255]),
+            2 => Rgba([0, 0, 0, 255]),
+            _ => Rgba([105, 105, 105, 200]),
+        });
+        whiten_background(&mut img, 210);
+        assert_eq!(img.get_pixel(0, 0), &WHITE);
+        assert_eq!(img.get_pixel(1, 0), &WHITE);
+        assert_eq!(img.get_pixel(2, 0), &BLACK);
+        assert_eq!(img.get_pixel(3, 0), &Rgba([128, 128, 128, 200]));
+    }
+
+    #[test]
+    fn contrast_and_gamma() {
+        let mut img = RgbaImage::from_fn(3, 1, |x, _| {
+            let v = [64, 128, 192][x as usize];
+            Rgba([v, v, v, 255])
+        });
+        adjust_contrast(&mut img, 2.0);
+        let values: Vec<u8> = img.pixels().map(|px| px[0]).collect();
+        assert_eq!(values, [1, 129, 255]);
+
+        let mut img = RgbaImage::from_pixel(1, 1, Rgba([64, 64, 64, 255]));
+        adjust_gamma(&mut img, 2.0);
+        assert_eq!(img.get_pixel(0, 0)[0], 128);
+    }
+
+    #[test]
+    fn binarize_with_otsu_splits_two_levels() {
+        let mut img = RgbaImage::from_fn(10, 10, |x, _| {
+            if x < 3 {
+                Rgba([40, 40, 40, 255])
+            } else {
+                Rgba([200, 200, 200, 255])
+            }
+        });
+        let threshold = binarize(&mut img, Binarize::Otsu);
+        assert!(threshold > 40 && threshold <= 200);
+        assert!(img.pixels().all(|px| px[0] == 0 || px[0] == 255));
+        assert_eq!(img.get_pixel(0, 0), &BLACK);
+        assert_eq!(img.get_pixel(9, 9), &WHITE);
+    }
+
+    #[test]
+    fn disabled_cleanup_leaves_image_unchanged() {
+        let img = rotate(&text_page(), 2.0);
+        let (w, h, rgba) = ScanCleanup::default().process(img.width(), img.height(), img.to_vec());
+        assert_eq!((w, h), img.dimensions());
+        assert_eq!(rgba, img.into_raw());
+    }
+}
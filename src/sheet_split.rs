@@ -0,0 +1,327 @@
+use std::fmt;
+use std::fmt::Write as _;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::booklet::{BindingRule, BookletRange};
+use crate::error::BookletError;
+use crate::output;
+use crate::page_source::PageSource;
+use crate::pdf_creator::{self, SheetSide};
+use crate::pdf_info::{self, InfoEntry};
+use crate::pdf_vector;
+
+/// 纸张分组依据
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum SheetGrouping {
+    /// 不分组
+    #[default]
+    None,
+    /// 按是否含有彩色页面分为彩色和黑白两组，会开启彩色页面检测
+    Color,
+    /// 按是否含有图像分为有图和纯文字两组
+    Images,
+}
+
+impl SheetGrouping {
+    /// 两组的名称：第一组为含有该属性的纸张，第二组为其余纸张
+    pub fn group_names(&self) -> [&'static str; 2] {
+        match self {
+            SheetGrouping::None => ["全部", "全部"],
+            SheetGrouping::Color => ["彩色", "黑白"],
+            SheetGrouping::Images => ["有图", "纯文字"],
+        }
+    }
+}
+
+impl fmt::Display for SheetGrouping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SheetGrouping::None => "不分组",
+            SheetGrouping::Color => "彩色/黑白",
+            SheetGrouping::Images => "有图/纯文字",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// 按纸张属性分组输出
+///
+/// 不同的纸张用不同的打印机打印时使用：生成插回表 `{源文件名}_sheets.csv`，
+/// 按叠放顺序列出每张纸所在的组和在组内的序号，可以按需为每组生成单独的PDF文件，
+/// 打印后按插回表把各组的纸张放回原来的位置
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SheetSplit {
+    /// 分组依据
+    pub group_by: SheetGrouping,
+    /// 为每组生成单独的PDF文件 `{源文件名}_{组名}.pdf`，包含该组纸张的正反面
+    pub separate_files: bool,
+}
+
+impl SheetSplit {
+    /// 是否分组
+    pub fn is_enabled(&self) -> bool {
+        self.group_by != SheetGrouping::None
+    }
+}
+
+impl fmt::Display for SheetSplit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.group_by)?;
+        if self.is_enabled() && self.separate_files {
+            write!(f, "，每组单独成文件")?;
+        }
+        Ok(())
+    }
+}
+
+/// 叠放顺序中的一张纸
+pub struct SheetEntry {
+    /// 册子编号
+    pub booklet_num: u16,
+    /// 册内的纸张编号（从1开始）
+    pub sheet: usize,
+    /// 所在的组，0为含有该属性的纸张
+    pub group: usize,
+    /// 在组内的序号（从1开始）
+    pub group_position: usize,
+    /// 纸张上的源页面索引，不含空白页
+    pub pages: Vec<u16>,
+}
+
+/// 检测每一页是否含有图像
+///
+/// 不知道是否含有图像的页面（如不用pdfium读取的PDF）按含有图像处理并给出警告
+///
+/// # 返回
+/// 返回每页是否含有图像
+pub fn classify_images(src_pdf: &dyn PageSource) -> Vec<bool> {
+    let mut unknown = 0;
+    let page_images: Vec<bool> = (0..src_pdf.get_page_count())
+        .map(|page_idx| {
+            src_pdf.has_images(page_idx).unwrap_or_else(|| {
+                unknown += 1;
+                true
+            })
+        })
+        .collect();
+    if unknown > 0 {
+        log::warn!("{}页无法判断是否含有图像，按有图处理", unknown);
+    }
+    let image_count = page_images.iter().filter(|has| **has).count();
+    log::info!(image_pages = image_count; "含有图像的页面{}页", image_count);
+    page_images
+}
+
+/// 按叠放顺序列出全部纸张的分组
+///
+/// 一张纸的正反面中只要有一页带有该属性，整张纸就属于第一组
+///
+/// # 参数
+/// * `booklets` - 全部册子
+/// * `binding_rule` - 装订规则
+/// * `page_marks` - 每页是否带有分组的属性
+pub fn group_sheets(
+    booklets: &[BookletRange],
+    binding_rule: &BindingRule,
+    page_marks: &[bool],
+) -> Vec<SheetEntry> {
+    let mut group_sizes = [0; 2];
+    let mut entries = Vec::new();
+    for booklet in booklets {
+        let sides = pdf_creator::calc_sheet_sides(booklet, binding_rule);
+        for (i, sheet_sides) in sides.chunks(2).enumerate() {
+            let pages = sheet_pages(sheet_sides);
+            let marked = pages
+                .iter()
+                .any(|&page_idx| page_marks.get(page_idx as usize) == Some(&true));
+            let group = if marked { 0 } else { 1 };
+            group_sizes[group] += 1;
+            entries.push(SheetEntry {
+                booklet_num: booklet.booklet_num,
+                sheet: i + 1,
+                group,
+                group_position: group_sizes[group],
+                pages,
+            });
+        }
+    }
+    entries
+}
+
+/// 一张纸正反面上的源页面索引（升序），不含空白页
+fn sheet_pages(sheet_sides: &[SheetSide]) -> Vec<u16> {
+    let mut pages: Vec<u16> = sheet_sides
+        .iter()
+        .flat_map(|side| [side.low, side.high])
+        .filter(|&page_idx| page_idx != u16::MAX)
+        .collect();
+    pages.sort_unstable();
+    pages
+}
+
+/// 生成的一组纸张的PDF文件
+pub struct GroupFile {
+    /// 所在的组，0为含有该属性的纸张
+    pub group: usize,
+    /// 实际的文件路径，按覆盖策略可能加了序号；跳过时为已有的文件
+    pub path: PathBuf,
+    /// 纸张数
+    pub sheets: usize,
+    /// 文件已存在而跳过
+    pub skipped: bool,
+}
+
+/// 插回表的路径 `{输出目录}/{源文件名}_sheets.csv`
+pub fn sheet_list_path(binding_rule: &BindingRule) -> Result<PathBuf, BookletError> {
+    let name = output::source_name(&binding_rule.input_path)?;
+    Ok(binding_rule.output_dir.join(format!("{}_sheets.csv", name)))
+}
+
+/// 一组纸张的PDF文件路径 `{输出目录}/{源文件名}_{组名}.pdf`
+pub fn group_file_path(binding_rule: &BindingRule, group: usize) -> Result<PathBuf, BookletError> {
+    let name = output::source_name(&binding_rule.input_path)?;
+    let group_name = binding_rule.sheet_split.group_by.group_names()[group];
+    Ok(binding_rule
+        .output_dir
+        .join(format!("{}_{}.pdf", name, group_name)))
+}
+
+/// 写出插回表，已存在时按覆盖策略处理
+///
+/// 按叠放顺序每张纸一行：序号、册、张、组、组文件、组内序号、页码。
+/// 文件开头带UTF-8 BOM，便于用表格软件打开
+///
+/// # 参数
+/// * `entries` - 按叠放顺序排列的纸张
+/// * `group_files` - 本次生成的分组文件，组文件一列使用其中的实际文件名
+/// * `binding_rule` - 装订规则
+///
+/// # 返回
+/// 返回实际写入的路径，跳过时返回 `None`
+pub fn write_sheet_list(
+    entries: &[SheetEntry],
+    group_files: &[GroupFile],
+    binding_rule: &BindingRule,
+) -> Result<Option<PathBuf>, BookletError> {
+    let group_names = binding_rule.sheet_split.group_by.group_names();
+    let mut csv = String::from("\u{feff}序号,册,张,组,组文件,组内序号,页码\n");
+    for (i, entry) in entries.iter().enumerate() {
+        let file = group_files
+            .iter()
+            .find(|file| file.group == entry.group)
+            .and_then(|file| file.path.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let pages: Vec<String> = entry
+            .pages
+            .iter()
+            .map(|page_idx| (page_idx + 1).to_string())
+            .collect();
+        let _ = writeln!(
+            csv,
+            "{},{},{},{},{},{},\"{}\"",
+            i + 1,
+            entry.booklet_num,
+            entry.sheet,
+            group_names[entry.group],
+            file,
+            entry.group_position,
+            pages.join(",")
+        );
+    }
+    let path = sheet_list_path(binding_rule)?;
+    let written = output::write_resolved(&path, csv.as_bytes(), binding_rule.overwrite)?;
+    if written.is_none() {
+        log::info!(path:% = path.display(); "文件已存在，跳过插回表");
+    }
+    Ok(written)
+}
+
+/// 为每组生成单独的PDF文件，纸张的正反面从本次生成的各册文件中复制
+///
+/// 没有纸张的组不生成文件。有纸张所在的册子本次没有生成（如输出文件已存在而跳过）时，
+/// 已有的文件可能是按其他设置生成的，不复制，返回错误且不生成任何分组文件。
+/// 分组文件已存在时按覆盖策略处理
+///
+/// # 参数
+/// * `src_pdf` - 源页面，用于文档信息
+/// * `entries` - 按叠放顺序排列的纸张
+/// * `booklet_paths` - 本次生成的各册文件，与册子编号对应
+/// * `binding_rule` - 装订规则
+///
+/// # 返回
+/// 返回每组的文件
+pub fn write_group_files(
+    src_pdf: &dyn PageSource,
+    entries: &[SheetEntry],
+    booklet_paths: &[(u16, &Path)],
+    binding_rule: &BindingRule,
+) -> Result<Vec<GroupFile>, BookletError> {
+    let title = pdf_creator::book_title(src_pdf, binding_rule);
+    let group_names = binding_rule.sheet_split.group_by.group_names();
+    let now = pdf_info::format_date(chrono::Local::now());
+    // 先确定每组的全部页面，缺少册子时不生成任何文件
+    let mut group_pages = vec![Vec::new(); group_names.len()];
+    for entry in entries {
+        let path = booklet_paths
+            .iter()
+            .find(|(booklet_num, _)| *booklet_num == entry.booklet_num)
+            .map(|(_, path)| *path)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!(
+                        "第{}册本次没有生成（输出文件已存在，已跳过），不能生成分组文件，\
+                         请覆盖已有的册子后重新生成",
+                        entry.booklet_num
+                    ),
+                )
+            })?;
+        let front = (entry.sheet as u16 - 1) * 2;
+        if let Some(pages) = group_pages.get_mut(entry.group) {
+            pages.extend([(path, front), (path, front + 1)]);
+        }
+    }
+    let mut files = Vec::new();
+    for (group, (group_name, pages)) in group_names.iter().zip(&group_pages).enumerate() {
+        if pages.is_empty() {
+            continue;
+        }
+        let sheets = pages.len() / 2;
+        let target_path = group_file_path(binding_rule, group)?;
+        let Some(path) = output::resolve_output_path(&target_path, binding_rule.overwrite)? else {
+            log::info!(path:% = target_path.display(); "文件已存在，跳过{}组", group_name);
+            files.push(GroupFile {
+                group,
+                path: target_path,
+                sheets,
+                skipped: true,
+            });
+            continue;
+        };
+        let info: Vec<InfoEntry> = vec![
+            ("Title", format!("{} — {}", title, group_name)),
+            (
+                "Creator",
+                format!("{} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            ),
+            ("CreationDate", now.clone()),
+            ("SheetGroup", group_name.to_string()),
+        ];
+        let data = pdf_vector::copy_pages(pages, &info)?;
+        output::write_atomic(&path, &data)?;
+        files.push(GroupFile {
+            group,
+            path,
+            sheets,
+            skipped: false,
+        });
+    }
+    Ok(files)
+}
@@ -0,0 +1,845 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use oxidize_pdf::{Color, Document, Font, Image, Page};
+use serde::{Deserialize, Serialize};
+
+use crate::booklet::BindingRule;
+use crate::encoding;
+use crate::error::BookletError;
+use crate::output;
+use crate::page_source::PageSource;
+use crate::pdf_creator::{self, MM_TO_PT};
+
+/// 嵌入字体在PDF中的名称
+const FONT_NAME: &str = "StampFont";
+
+/// 水印在页面上的位置
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum StampPosition {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    #[default]
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl StampPosition {
+    /// 水平和垂直方向的位置，-1为左/下，0为居中，1为右/上
+    fn grid(&self) -> (i8, i8) {
+        match self {
+            StampPosition::TopLeft => (-1, 1),
+            StampPosition::Top => (0, 1),
+            StampPosition::TopRight => (1, 1),
+            StampPosition::Left => (-1, 0),
+            StampPosition::Center => (0, 0),
+            StampPosition::Right => (1, 0),
+            StampPosition::BottomLeft => (-1, -1),
+            StampPosition::Bottom => (0, -1),
+            StampPosition::BottomRight => (1, -1),
+        }
+    }
+}
+
+impl fmt::Display for StampPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            StampPosition::TopLeft => "左上",
+            StampPosition::Top => "上",
+            StampPosition::TopRight => "右上",
+            StampPosition::Left => "左",
+            StampPosition::Center => "中间",
+            StampPosition::Right => "右",
+            StampPosition::BottomLeft => "左下",
+            StampPosition::Bottom => "下",
+            StampPosition::BottomRight => "右下",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// 页眉页脚的对齐方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum TextAlign {
+    Left,
+    #[default]
+    Center,
+    Right,
+    /// 靠外侧：奇数页靠右，偶数页靠左
+    Outside,
+}
+
+impl TextAlign {
+    /// 在页面上实际的对齐方式：靠外侧时按页面在排版顺序中的位置，
+    /// 第1、3……页（右页）靠右，第2、4……页（左页）靠左
+    ///
+    /// # 参数
+    /// * `page_idx` - 页面索引（从0开始）
+    fn for_page(self, page_idx: u16) -> TextAlign {
+        match self {
+            TextAlign::Outside if page_idx.is_multiple_of(2) => TextAlign::Right,
+            TextAlign::Outside => TextAlign::Left,
+            align => align,
+        }
+    }
+}
+
+impl fmt::Display for TextAlign {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TextAlign::Left => "靠左",
+            TextAlign::Center => "居中",
+            TextAlign::Right => "靠右",
+            TextAlign::Outside => "靠外侧",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// 加水印或页眉页脚的页面
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PageFilter {
+    /// 全部页面
+    #[default]
+    All,
+    /// 正文页面，不含封面封底
+    Body,
+    /// 奇数页（按源页码）
+    Odd,
+    /// 偶数页（按源页码）
+    Even,
+    /// 指定的源页码范围（从1开始，包含两端）
+    Pages(Vec<(u16, u16)>),
+}
+
+impl PageFilter {
+    /// 解析命令行中的页面范围：`all`、`body`、`odd`、`even` 或页码范围（如 `1-10,15`）
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.trim() {
+            "all" => return Ok(PageFilter::All),
+            "body" => return Ok(PageFilter::Body),
+            "odd" => return Ok(PageFilter::Odd),
+            "even" => return Ok(PageFilter::Even),
+            _ => {}
+        }
+        let invalid = || format!("无效的页面范围 \"{}\"", s);
+        s.split(',')
+            .map(|range| {
+                let (first, last) = range.split_once('-').unwrap_or((range, range));
+                let first = first.trim().parse::<u16>().map_err(|_| invalid())?;
+                let last = last.trim().parse::<u16>().map_err(|_| invalid())?;
+                if first == 0 || first > last {
+                    return Err(invalid());
+                }
+                Ok((first, last))
+            })
+            .collect::<Result<_, _>>()
+            .map(PageFilter::Pages)
+    }
+
+    /// 页面是否符合条件
+    ///
+    /// 奇偶页和页码范围按源页码计，单独的封面文件中的页面没有源页码，只符合 `All`
+    ///
+    /// # 参数
+    /// * `page_idx` - 页面索引（从0开始）
+    /// * `body` - 正文的页面索引范围 `[start, end)`
+    /// * `numbered` - 有源页码的页面索引范围 `[start, end)`，见 `BindingRule::numbered_page_range`
+    pub fn matches(&self, page_idx: u16, body: (u16, u16), numbered: (u16, u16)) -> bool {
+        let page_num =
+            (page_idx >= numbered.0 && page_idx < numbered.1).then(|| page_idx - numbered.0 + 1);
+        match self {
+            PageFilter::All => true,
+            PageFilter::Body => page_idx >= body.0 && page_idx < body.1,
+            PageFilter::Odd => page_num.is_some_and(|num| num % 2 == 1),
+            PageFilter::Even => page_num.is_some_and(|num| num.is_multiple_of(2)),
+            PageFilter::Pages(ranges) => page_num.is_some_and(|num| {
+                ranges
+                    .iter()
+                    .any(|&(first, last)| num >= first && num <= last)
+            }),
+        }
+    }
+}
+
+impl fmt::Display for PageFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PageFilter::All => write!(f, "全部页面"),
+            PageFilter::Body => write!(f, "正文"),
+            PageFilter::Odd => write!(f, "奇数页"),
+            PageFilter::Even => write!(f, "偶数页"),
+            PageFilter::Pages(ranges) => {
+                let ranges: Vec<String> = ranges
+                    .iter()
+                    .map(|(first, last)| {
+                        if first == last {
+                            first.to_string()
+                        } else {
+                            format!("{}-{}", first, last)
+                        }
+                    })
+                    .collect();
+                write!(f, "第{}页", ranges.join(","))
+            }
+        }
+    }
+}
+
+/// 文字或图像水印
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Watermark {
+    /// 水印文字，如 `DRAFT`、`内部资料`
+    pub text: String,
+    /// 水印图像（PNG等，可以带透明度），指定时代替文字
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<PathBuf>,
+    /// 文字大小(pt)；图像水印为图像宽度占页面宽度的百分比
+    pub size: f64,
+    /// 文字颜色 RGB
+    pub color: [u8; 3],
+    /// 不透明度，0为完全透明，1为不透明
+    pub opacity: f64,
+    /// 逆时针旋转角度(°)
+    pub rotation: f64,
+    /// 在页面上的位置
+    pub position: StampPosition,
+    /// 不在中间时与页面边缘的距离(mm)
+    pub margin: f64,
+    /// 加水印的页面
+    pub pages: PageFilter,
+}
+
+impl Default for Watermark {
+    fn default() -> Self {
+        Self {
+            text: String::new(),
+            image: None,
+            size: 48.0,
+            color: [128, 128, 128],
+            opacity: 0.3,
+            rotation: 45.0,
+            position: StampPosition::default(),
+            margin: 10.0,
+            pages: PageFilter::default(),
+        }
+    }
+}
+
+/// 页眉或页脚
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RunningText {
+    /// 内容模板，为空时不加，占位符见 `Stamper::render_template`
+    pub template: String,
+    /// 对齐方式
+    pub align: TextAlign,
+    /// 文字大小(pt)
+    pub size: f64,
+    /// 与页面上边缘（页眉）或下边缘（页脚）的距离(mm)
+    pub margin: f64,
+    /// 加页眉页脚的页面
+    pub pages: PageFilter,
+}
+
+impl Default for RunningText {
+    fn default() -> Self {
+        Self {
+            template: String::new(),
+            align: TextAlign::default(),
+            size: 8.0,
+            margin: 4.0,
+            pages: PageFilter::Body,
+        }
+    }
+}
+
+impl RunningText {
+    fn is_enabled(&self) -> bool {
+        !self.template.is_empty()
+    }
+}
+
+/// 水印和页眉页脚，位图排版时叠加在每个页面上
+///
+/// 按页面的方向绘制，随页面一起旋转，大小和距离按纸上的尺寸计算
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Stamps {
+    /// 水印，可以有多个
+    pub watermarks: Vec<Watermark>,
+    /// 页眉
+    pub header: RunningText,
+    /// 页脚
+    pub footer: RunningText,
+    /// 页眉页脚中的页码为源页码加上这个值，如 `-4` 让第5页印为第1页；结果小于1时不印页码
+    pub page_number_offset: i32,
+    /// 嵌入的TrueType字体文件，文字中有中文等非西文字符时需要指定
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font_file: Option<PathBuf>,
+}
+
+impl Stamps {
+    /// 是否有水印或页眉页脚
+    pub fn is_enabled(&self) -> bool {
+        !self.watermarks.is_empty() || self.header.is_enabled() || self.footer.is_enabled()
+    }
+
+    /// 检查水印的不透明度在0到1之间
+    pub fn check(&self) -> Result<(), BookletError> {
+        for (i, watermark) in self.watermarks.iter().enumerate() {
+            if !(0.0..=1.0).contains(&watermark.opacity) {
+                return Err(BookletError::InvalidRule(format!(
+                    "第{}个水印的不透明度 {} 无效，应在0到1之间",
+                    i + 1,
+                    watermark.opacity
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Stamps {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.is_enabled() {
+            return write!(f, "无");
+        }
+        let mut items: Vec<String> = self
+            .watermarks
+            .iter()
+            .map(|watermark| match &watermark.image {
+                Some(path) => format!("水印图像 {}", path.display()),
+                None => format!("水印 \"{}\"", watermark.text),
+            })
+            .collect();
+        if self.header.is_enabled() {
+            items.push(format!("页眉 \"{}\"", self.header.template));
+        }
+        if self.footer.is_enabled() {
+            items.push(format!("页脚 \"{}\"", self.footer.template));
+        }
+        write!(f, "{}", items.join("，"))
+    }
+}
+
+/// 嵌入的字体
+struct StampFont {
+    data: Vec<u8>,
+    /// 用于计算文字宽度
+    metrics: oxidize_pdf::fonts::Font,
+}
+
+/// 排版一本书时准备好的水印和页眉页脚
+///
+/// 创建时读取水印图像和字体、检查模板，生成每一页时只需绘制
+pub struct Stamper {
+    stamps: Stamps,
+    /// 水印图像和宽高比（高/宽），与 `stamps.watermarks` 一一对应，文字水印为 `None`
+    images: Vec<Option<(Image, f64)>>,
+    font: Option<StampFont>,
+    title: String,
+    chapters: Vec<(u16, String)>,
+    body: (u16, u16),
+    /// 有源页码的页面索引范围 `[start, end)`
+    numbered: (u16, u16),
+}
+
+impl Stamper {
+    /// 按装订规则准备水印和页眉页脚
+    ///
+    /// # 返回
+    /// 没有水印和页眉页脚时返回 `None`；图像或字体无法读取、模板有误或文字需要字体但没有指定时返回错误
+    pub fn new(
+        src_pdf: &dyn PageSource,
+        binding_rule: &BindingRule,
+    ) -> Result<Option<Self>, BookletError> {
+        let stamps = &binding_rule.stamps;
+        if !stamps.is_enabled() {
+            return Ok(None);
+        }
+        let images = stamps
+            .watermarks
+            .iter()
+            .map(|watermark| watermark.image.as_ref().map(load_image).transpose())
+            .collect::<Result<_, _>>()?;
+        let font = match &stamps.font_file {
+            Some(path) => {
+                let data = std::fs::read(path)?;
+                let metrics = oxidize_pdf::fonts::Font::from_bytes(FONT_NAME, data.clone())
+                    .map_err(|e| {
+                        BookletError::InvalidRule(format!("无法读取字体 {}: {}", path.display(), e))
+                    })?;
+                Some(StampFont { data, metrics })
+            }
+            None => None,
+        };
+        let page_count = src_pdf.get_page_count();
+        let stamper = Self {
+            stamps: stamps.clone(),
+            images,
+            font,
+            title: pdf_creator::book_title(src_pdf, binding_rule),
+            chapters: src_pdf.get_chapters(),
+            body: binding_rule.body_page_range(page_count),
+            numbered: binding_rule.numbered_page_range(page_count),
+        };
+        stamper.check_texts()?;
+        Ok(Some(stamper))
+    }
+
+    /// 检查模板，没有指定字体时检查文字是否都能用标准字体显示
+    fn check_texts(&self) -> Result<(), BookletError> {
+        let mut texts: Vec<&str> = self
+            .stamps
+            .watermarks
+            .iter()
+            .filter(|watermark| watermark.image.is_none())
+            .map(|watermark| watermark.text.as_str())
+            .collect();
+        for running in [&self.stamps.header, &self.stamps.footer] {
+            if !running.is_enabled() {
+                continue;
+            }
+            self.render_template(&running.template, 0, 1)
+                .map_err(BookletError::InvalidRule)?;
+            texts.push(&running.template);
+            if running.template.contains("{title}") {
+                texts.push(&self.title);
+            }
+            if running.template.contains("{chapter}") {
+                texts.extend(self.chapters.iter().map(|(_, title)| title.as_str()));
+            }
+        }
+        // 标准字体只能显示 WinAnsi 编码的字符
+        if self.font.is_none()
+            && texts
+                .iter()
+                .any(|text| text.chars().any(|c| c as u32 > 0xFF))
+        {
+            return Err(BookletError::InvalidRule(
+                "水印或页眉页脚中有中文等非西文字符，需要用 font_file 指定字体".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// 把字体嵌入到文档中，每个文档都要调用一次
+    pub fn register_font(&self, doc: &mut Document) -> Result<(), BookletError> {
+        if let Some(font) = &self.font {
+            doc.add_font_from_bytes(FONT_NAME, font.data.clone())?;
+        }
+        Ok(())
+    }
+
+    /// 生成页眉页脚的内容
+    ///
+    /// 占位符：
+    /// * `{title}` - 书名（源文件的标题，没有时为文件名）
+    /// * `{chapter}` - 本页所在的章节（本页或之前最近的一级书签），没有书签时为空
+    /// * `{page}` - 页码（源页码加上 `page_number_offset`），`{page:3}` 指定补零位数，
+    ///   单独的封面文件中的页面为空
+    /// * `{pages}` - 总页数（最后一个源页码的页码）
+    /// * `{booklet}` - 册子编号
+    ///
+    /// # 参数
+    /// * `template` - 模板
+    /// * `page_idx` - 页面索引（从0开始）
+    /// * `booklet_num` - 册子编号
+    ///
+    /// # 返回
+    /// 模板有误时返回错误说明
+    fn render_template(
+        &self,
+        template: &str,
+        page_idx: u16,
+        booklet_num: u16,
+    ) -> Result<String, String> {
+        let offset = self.stamps.page_number_offset as i64;
+        let (first, end) = self.numbered;
+        let page_number = |page_idx: u16| {
+            if page_idx < first || page_idx >= end {
+                return None;
+            }
+            let number = (page_idx - first) as i64 + 1 + offset;
+            (number >= 1).then_some(number as usize)
+        };
+        output::expand_template(template, "页眉页脚模板", |key, spec| match key {
+            "title" => Ok(self.title.clone()),
+            "chapter" => Ok(self
+                .chapters
+                .iter()
+                .rev()
+                .find(|(start, _)| *start <= page_idx)
+                .map(|(_, title)| title.clone())
+                .unwrap_or_default()),
+            "page" => page_number(page_idx).map_or(Ok(String::new()), |number| {
+                output::pad_number(key, spec, number, 0)
+            }),
+            "pages" => page_number(end.saturating_sub(1)).map_or(Ok(String::new()), |number| {
+                output::pad_number(key, spec, number, 0)
+            }),
+            "booklet" => output::pad_number(key, spec, booklet_num as usize, 0),
+            _ => Err(format!("未知的页眉页脚占位符 {{{}}}", key)),
+        })
+    }
+
+    /// 在一个页面上绘制水印和页眉页脚
+    ///
+    /// # 参数
+    /// * `page` - A4纸的一面
+    /// * `page_idx` - 页面索引（从0开始）
+    /// * `rect` - 页面图像在纸上的区域 (x, y, width, height)
+    /// * `reverse_image` - 页面是否旋转了270°（天头朝左），否则为90°（天头朝右）
+    /// * `booklet_num` - 册子编号
+    pub fn draw(
+        &self,
+        page: &mut Page,
+        page_idx: u16,
+        rect: (f64, f64, f64, f64),
+        reverse_image: bool,
+        booklet_num: u16,
+    ) -> Result<(), BookletError> {
+        let (x, y, w, h) = rect;
+        // 纸上的区域是旋转后的页面，页面的宽高对应区域的高宽
+        let (page_w, page_h) = (h, w);
+        // 页面坐标（以页面左下角为原点）到纸上坐标的变换
+        let matrix = if reverse_image {
+            [0.0, 1.0, -1.0, 0.0, x + w, y]
+        } else {
+            [0.0, -1.0, 1.0, 0.0, x, y + h]
+        };
+        for (i, watermark) in self.stamps.watermarks.iter().enumerate() {
+            if !watermark.pages.matches(page_idx, self.body, self.numbered) {
+                continue;
+            }
+            page.graphics().save_state().transform(
+                matrix[0], matrix[1], matrix[2], matrix[3], matrix[4], matrix[5],
+            );
+            match &self.images[i] {
+                Some((image, aspect)) => {
+                    let image_w = page_w * watermark.size / 100.0;
+                    let image_h = image_w * aspect;
+                    let (cx, cy) = anchor(watermark, page_w, page_h, image_w, image_h);
+                    let name = format!("Stamp{}", i);
+                    page.add_image(&name, image.clone());
+                    let graphics = page.graphics();
+                    graphics
+                        .translate(cx, cy)
+                        .rotate(watermark.rotation.to_radians());
+                    graphics.set_alpha(watermark.opacity)?;
+                    page.draw_image(&name, -image_w / 2.0, -image_h / 2.0, image_w, image_h)?;
+                }
+                None => {
+                    let text_w = self.text_width(&watermark.text, watermark.size);
+                    let (cx, cy) = anchor(watermark, page_w, page_h, text_w, watermark.size);
+                    let [r, g, b] = watermark.color;
+                    let graphics = page.graphics();
+                    graphics
+                        .translate(cx, cy)
+                        .rotate(watermark.rotation.to_radians())
+                        .set_fill_color(Color::rgb(
+                            r as f64 / 255.0,
+                            g as f64 / 255.0,
+                            b as f64 / 255.0,
+                        ));
+                    graphics.set_alpha(watermark.opacity)?;
+                    self.draw_text(
+                        page,
+                        &watermark.text,
+                        watermark.size,
+                        -text_w / 2.0,
+                        // 基线在文字中心以下约三分之一字号处
+                        -watermark.size * 0.35,
+                    )?;
+                }
+            }
+            page.graphics().restore_state();
+        }
+
+        for (running, at_top) in [(&self.stamps.header, true), (&self.stamps.footer, false)] {
+            if !running.is_enabled() || !running.pages.matches(page_idx, self.body, self.numbered) {
+                continue;
+            }
+            let text = self
+                .render_template(&running.template, page_idx, booklet_num)
+                .map_err(BookletError::InvalidRule)?;
+            if text.trim().is_empty() {
+                continue;
+            }
+            let text_w = self.text_width(&text, running.size);
+            let margin = running.margin * MM_TO_PT;
+            let text_x = match running.align.for_page(page_idx) {
+                TextAlign::Left => margin,
+                TextAlign::Right => page_w - margin - text_w,
+                _ => (page_w - text_w) / 2.0,
+            };
+            let text_y = if at_top {
+                page_h - margin - running.size * 0.75
+            } else {
+                margin
+            };
+            page.graphics()
+                .save_state()
+                .transform(
+                    matrix[0], matrix[1], matrix[2], matrix[3], matrix[4], matrix[5],
+                )
+                .set_fill_color(Color::black());
+            self.draw_text(page, &text, running.size, text_x, text_y)?;
+            page.graphics().restore_state();
+        }
+        Ok(())
+    }
+
+    /// 用嵌入的字体（没有时为Helvetica）在当前坐标系中写一行文字
+    fn draw_text(
+        &self,
+        page: &mut Page,
+        text: &str,
+        size: f64,
+        x: f64,
+        y: f64,
+    ) -> Result<(), BookletError> {
+        let font = if self.font.is_some() {
+            Font::Custom(FONT_NAME.to_string())
+        } else {
+            Font::Helvetica
+        };
+        page.graphics().set_font(font, size).draw_text(text, x, y)?;
+        Ok(())
+    }
+
+    /// 文字宽度(pt)
+    fn text_width(&self, text: &str, size: f64) -> f64 {
+        match &self.font {
+            Some(font) => font.metrics.measure_text(text, size as f32).width as f64,
+            None => oxidize_pdf::measure_text(text, Font::Helvetica, size),
+        }
+    }
+}
+
+/// 读取水印图像
+///
+/// # 返回
+/// 返回PDF图像和宽高比（高/宽）
+fn load_image(path: &PathBuf) -> Result<(Image, f64), BookletError> {
+    let img = image::open(path)
+        .map_err(|e| {
+            BookletError::InvalidRule(format!("无法读取水印图像 {}: {}", path.display(), e))
+        })?
+        .into_rgba8();
+    let (width, height) = img.dimensions();
+    let aspect = height as f64 / width as f64;
+    Ok((
+        encoding::encode_transparent_image(width, height, img.as_raw())?,
+        aspect,
+    ))
+}
+
+/// 水印中心在页面上的位置
+///
+/// # 参数
+/// * `watermark` - 水印
+/// * `page_w`, `page_h` - 页面在纸上的宽高(pt)
+/// * `stamp_w`, `stamp_h` - 水印不旋转时的宽高(pt)
+fn anchor(
+    watermark: &Watermark,
+    page_w: f64,
+    page_h: f64,
+    stamp_w: f64,
+    stamp_h: f64,
+) -> (f64, f64) {
+    let margin = watermark.margin * MM_TO_PT;
+    let (col, row) = watermark.position.grid();
+    let place = |side: i8, page_len: f64, stamp_len: f64| match side {
+        -1 => margin + stamp_len / 2.0,
+        1 => page_len - margin - stamp_len / 2.0,
+        _ => page_len / 2.0,
+    };
+    (place(col, page_w, stamp_w), place(row, page_h, stamp_h))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 共12页，第1页为封面，最后一页为封底，正文第1页在第5页开始第一章
+    fn stamper(stamps: Stamps) -> Stamper {
+        Stamper {
+            images: stamps.watermarks.iter().map(|_| None).collect(),
+            stamps,
+            font: None,
+            title: "Title".to_string(),
+            chapters: vec![(1, "Preface".to_string()), (4, "Chapter 1".to_string())],
+            body: (1, 11),
+            numbered: (0, 12),
+        }
+    }
+
+    fn footer(template: &str) -> Stamps {
+        Stamps {
+            footer: RunningText {
+                template: template.to_string(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn watermark(text: &str) -> Watermark {
+        Watermark {
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn template_placeholders() {
+        let stamper = stamper(footer(""));
+        let render = |template: &str, page_idx| stamper.render_template(template, page_idx, 2);
+        assert_eq!(
+            render("{title} · {chapter} · {page}/{pages} · {booklet}", 5).unwrap(),
+            "Title · Chapter 1 · 6/12 · 2"
+        );
+        assert_eq!(render("{chapter}", 0).unwrap(), "");
+        assert_eq!(render("{chapter}", 3).unwrap(), "Preface");
+        assert_eq!(render("{page:3}", 5).unwrap(), "006");
+        assert_eq!(render("{booklet:2}", 0).unwrap(), "02");
+        assert!(render("{section}", 0).is_err());
+        assert!(render("{page:x}", 0).is_err());
+    }
+
+    #[test]
+    fn page_number_offset() {
+        let mut stamper = stamper(Stamps {
+            page_number_offset: -4,
+            ..footer("{page}")
+        });
+        let render = |stamper: &Stamper, page_idx| stamper.render_template("{page}", page_idx, 1);
+        // 第5页印为第1页，之前的页面不印页码
+        assert_eq!(render(&stamper, 4).unwrap(), "1");
+        assert_eq!(render(&stamper, 3).unwrap(), "");
+        assert_eq!(stamper.render_template("{pages}", 0, 1).unwrap(), "8");
+        // 封面封底在单独的文件中时不编号
+        stamper.numbered = (1, 11);
+        stamper.stamps.page_number_offset = 0;
+        assert_eq!(render(&stamper, 0).unwrap(), "");
+        assert_eq!(render(&stamper, 1).unwrap(), "1");
+        assert_eq!(render(&stamper, 11).unwrap(), "");
+        assert_eq!(stamper.render_template("{pages}", 0, 1).unwrap(), "10");
+    }
+
+    #[test]
+    fn texts_need_font_outside_latin1() {
+        let with_watermark = |text| Stamps {
+            watermarks: vec![watermark(text)],
+            ..Default::default()
+        };
+        assert!(stamper(with_watermark("DRAFT")).check_texts().is_ok());
+        assert!(stamper(with_watermark("Café ©")).check_texts().is_ok());
+        assert!(matches!(
+            stamper(with_watermark("内部资料")).check_texts(),
+            Err(BookletError::InvalidRule(_))
+        ));
+        // 书名和章节名只在模板用到时检查
+        let mut chinese_title = stamper(footer("{page}"));
+        chinese_title.title = "书名".to_string();
+        assert!(chinese_title.check_texts().is_ok());
+        chinese_title.stamps.footer.template = "{title}".to_string();
+        assert!(chinese_title.check_texts().is_err());
+        let mut chinese_chapter = stamper(footer("{chapter}"));
+        chinese_chapter.chapters.push((8, "第二章".to_string()));
+        assert!(chinese_chapter.check_texts().is_err());
+        // 图像水印没有文字
+        let image = Stamps {
+            watermarks: vec![Watermark {
+                image: Some(PathBuf::from("logo.png")),
+                ..watermark("内部资料")
+            }],
+            ..Default::default()
+        };
+        assert!(stamper(image).check_texts().is_ok());
+        // 模板有误
+        assert!(matches!(
+            stamper(footer("{unknown}")).check_texts(),
+            Err(BookletError::InvalidRule(_))
+        ));
+    }
+
+    #[test]
+    fn page_filter_odd_even_and_ranges() {
+        let body = (1, 11);
+        let numbered = (0, 12);
+        let matched = |filter: PageFilter| -> Vec<u16> {
+            (0..12)
+                .filter(|&page_idx| filter.matches(page_idx, body, numbered))
+                .collect()
+        };
+        assert_eq!(matched(PageFilter::All).len(), 12);
+        assert_eq!(matched(PageFilter::Body), (1..11).collect::<Vec<_>>());
+        assert_eq!(matched(PageFilter::Odd), vec![0, 2, 4, 6, 8, 10]);
+        assert_eq!(matched(PageFilter::Even), vec![1, 3, 5, 7, 9, 11]);
+        assert_eq!(
+            matched(PageFilter::parse("2-3, 12").unwrap()),
+            vec![1, 2, 11]
+        );
+        assert_eq!(PageFilter::parse(" odd ").unwrap(), PageFilter::Odd);
+        assert!(PageFilter::parse("0-3").is_err());
+        assert!(PageFilter::parse("5-3").is_err());
+        assert!(PageFilter::parse("first").is_err());
+    }
+
+    #[test]
+    fn outside_align_follows_left_and_right_pages() {
+        assert_eq!(TextAlign::Outside.for_page(0), TextAlign::Right);
+        assert_eq!(TextAlign::Outside.for_page(1), TextAlign::Left);
+        assert_eq!(TextAlign::Outside.for_page(10), TextAlign::Right);
+        assert_eq!(TextAlign::Outside.for_page(11), TextAlign::Left);
+        assert_eq!(TextAlign::Center.for_page(1), TextAlign::Center);
+        assert_eq!(TextAlign::Left.for_page(0), TextAlign::Left);
+    }
+
+    #[test]
+    fn watermark_opacity_is_checked() {
+        let with_opacity = |opacity| Stamps {
+            watermarks: vec![Watermark {
+                opacity,
+                ..watermark("DRAFT")
+            }],
+            ..Default::default()
+        };
+        assert!(with_opacity(0.0).check().is_ok());
+        assert!(with_opacity(1.0).check().is_ok());
+        assert!(with_opacity(1.5).check().is_err());
+        assert!(with_opacity(-0.1).check().is_err());
+        assert!(with_opacity(f64::NAN).check().is_err());
+    }
+
+    #[test]
+    fn page_filter_skips_cover_file_pages() {
+        // 共12页，第1页和最后一页来自封面文件，源页码为1-10
+        let body = (1, 11);
+        let numbered = (1, 11);
+        let odd = PageFilter::Odd;
+        assert!(!odd.matches(0, body, numbered));
+        assert!(odd.matches(1, body, numbered));
+        assert!(!odd.matches(2, body, numbered));
+        assert!(!PageFilter::Even.matches(11, body, numbered));
+        let pages = PageFilter::parse("1-2,10").unwrap();
+        let matched: Vec<u16> = (0..12)
+            .filter(|&page_idx| pages.matches(page_idx, body, numbered))
+            .collect();
+        assert_eq!(matched, vec![1, 2, 10]);
+        assert!(PageFilter::All.matches(0, body, numbered));
+    }
+}
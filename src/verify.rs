@@ -0,0 +1,600 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Cursor;
+
+use oxidize_pdf::Page;
+use oxidize_pdf::parser::{PdfObject, PdfReader};
+
+use crate::booklet::BindingRule;
+use crate::cover::CoverPrint;
+use crate::error::BookletError;
+use crate::pdf_creator::{self, SheetSide};
+use crate::preview::{self, ReadingPage};
+
+/// 诊断信息中最多列出的错位数量
+const MAX_MISPLACED: usize = 10;
+
+/// 排版校验发现的问题
+#[derive(Clone, Debug)]
+pub enum Issue {
+    /// 源页面没有出现在任何纸上
+    Missing { page_idx: u16 },
+    /// 源页面出现了多次
+    Duplicated {
+        page_idx: u16,
+        positions: Vec<ReadingPage>,
+    },
+    /// 阅读顺序中的位置与预期不符
+    Misplaced {
+        /// 阅读位置（从1开始）
+        position: usize,
+        expected: Option<u16>,
+        found: ReadingPage,
+    },
+    /// 册子文件的页数与纸面数不符
+    PageCount {
+        booklet_num: u16,
+        expected: usize,
+        found: usize,
+    },
+    /// 纸面不是不旋转的A4纸
+    SheetSize {
+        booklet_num: u16,
+        /// 纸面序号（从0开始）
+        side: usize,
+        width: f64,
+        height: f64,
+        rotation: i32,
+    },
+    /// 纸面上放置的源页面与计划不符
+    SidePages {
+        booklet_num: u16,
+        /// 纸面序号（从0开始）
+        side: usize,
+        expected: Vec<u16>,
+        found: Vec<u16>,
+    },
+    /// 总页数（含空白页）与预期不符
+    Length { expected: usize, found: usize },
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::Missing { page_idx } => write!(f, "缺少第{}页", page_idx + 1),
+            Issue::Duplicated {
+                page_idx,
+                positions,
+            } => {
+                let positions: Vec<String> = positions.iter().map(|p| p.to_string()).collect();
+                write!(
+                    f,
+                    "第{}页重复出现{}次: {}",
+                    page_idx + 1,
+                    positions.len(),
+                    positions.join("、")
+                )
+            }
+            Issue::Misplaced {
+                position,
+                expected,
+                found,
+            } => write!(
+                f,
+                "阅读顺序第{}页（{}）应为{}，实际为{}",
+                position,
+                found,
+                describe_page(*expected),
+                describe_page(found.page_idx)
+            ),
+            Issue::PageCount {
+                booklet_num,
+                expected,
+                found,
+            } => write!(
+                f,
+                "第{}册的文件有{}面，预期{}面",
+                booklet_num, found, expected
+            ),
+            Issue::SheetSize {
+                booklet_num,
+                side,
+                width,
+                height,
+                rotation,
+            } => write!(
+                f,
+                "第{}册{}的大小为{:.0}×{:.0}pt、旋转{}°，应为不旋转的A4纸",
+                booklet_num,
+                describe_side(*side),
+                width,
+                height,
+                rotation
+            ),
+            Issue::SidePages {
+                booklet_num,
+                side,
+                expected,
+                found,
+            } => write!(
+                f,
+                "第{}册{}放置了{}，应为{}",
+                booklet_num,
+                describe_side(*side),
+                describe_pages(found),
+                describe_pages(expected)
+            ),
+            Issue::Length { expected, found } => {
+                write!(f, "共{}个页面位置，预期{}个", found, expected)
+            }
+        }
+    }
+}
+
+fn describe_page(page_idx: Option<u16>) -> String {
+    match page_idx {
+        Some(page_idx) => format!("第{}页", page_idx + 1),
+        None => "空白页".to_string(),
+    }
+}
+
+/// 纸面序号（从0开始）转为“第n张正面/背面”
+fn describe_side(side: usize) -> String {
+    format!(
+        "第{}张{}",
+        side / 2 + 1,
+        if side % 2 == 1 { "背面" } else { "正面" }
+    )
+}
+
+fn describe_pages(pages: &[u16]) -> String {
+    if pages.is_empty() {
+        return "空白页".to_string();
+    }
+    let pages: Vec<String> = pages.iter().map(|p| format!("第{}页", p + 1)).collect();
+    pages.join("、")
+}
+
+/// 排版校验结果
+#[derive(Clone, Debug, Default)]
+pub struct VerifyReport {
+    pub issues: Vec<Issue>,
+    /// 未列出的错位数量
+    pub omitted: usize,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl fmt::Display for VerifyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "发现{}个问题", self.issues.len() + self.omitted)?;
+        for issue in &self.issues {
+            write!(f, "\n  - {}", issue)?;
+        }
+        if self.omitted > 0 {
+            write!(f, "\n  - 另有{}处错位未列出", self.omitted)?;
+        }
+        Ok(())
+    }
+}
+
+/// 预期的阅读顺序：源页面依次排列，空白页只出现在预定位置
+///
+/// 不使用排版计划的分段和封面函数，按装订规则重新推导，计划中的错误才能被发现：
+///
+/// * 封面是开头的 `front_pages` 页，封底是末尾的 `back_pages` 页，页数不够时优先保证封面
+/// * 封面封底背面按设置各有一页空白
+/// * 封面封底单独成册时排在最前，正文在强制分册处分为几段（指定了每册纸张数时不分段），
+///   与正文一起排版时封面在第一段开头、封底在最后一段末尾
+/// * 每一段从新的一册开始，填充的空白页在这一段的正文末尾（封底之前），
+///   一直填到这一段最后一册的末尾
+///
+/// 每册的页数只用来确定一段在哪一册结束：一段占用的册子是从这一段开始、
+/// 页数刚好能放下这一段的那几册。
+///
+/// # 参数
+/// * `page_count` - 源文件页数
+/// * `binding_rule` - 装订规则
+/// * `booklet_lengths` - 按计划各册依次的页面位置数（含空白页）
+pub fn expected_reading_order(
+    page_count: u16,
+    binding_rule: &BindingRule,
+    booklet_lengths: &[usize],
+) -> Vec<Option<u16>> {
+    let cover = &binding_rule.cover;
+    let front = cover.front_pages.min(page_count);
+    let back = cover.back_pages.min(page_count - front);
+    let mut front_seq: Vec<Option<u16>> = (0..front).map(Some).collect();
+    let mut back_seq: Vec<Option<u16>> = Vec::new();
+    if cover.blank_inside {
+        if front > 0 {
+            front_seq.push(None);
+        }
+        if back > 0 {
+            back_seq.push(None);
+        }
+    }
+    back_seq.extend((page_count - back..page_count).map(Some));
+
+    // 每一段：(填充空白页之前的部分, 填充空白页之后的部分)
+    let mut groups: Vec<(Vec<_>, Vec<_>)> = Vec::new();
+    let has_cover = front > 0 || back > 0;
+    if has_cover && cover.print == CoverPrint::Separate {
+        groups.push((front_seq.clone(), back_seq.clone()));
+    }
+    let (body_start, body_end) = (front, page_count - back);
+    let mut starts = vec![body_start];
+    if binding_rule.booklet_sheet_counts.is_empty() {
+        // 断点是源页码，单独的封面文件中的封面页不计
+        let offset = if cover.file.is_some() { front } else { 0 };
+        for &page in &binding_rule.booklet_breaks {
+            let page_idx = page.saturating_sub(1) + offset;
+            if page_idx > body_start && page_idx < body_end && !starts.contains(&page_idx) {
+                starts.push(page_idx);
+            }
+        }
+        starts.sort_unstable();
+    }
+    let body_first = groups.len();
+    for (i, &start) in starts.iter().enumerate() {
+        let end = starts.get(i + 1).copied().unwrap_or(body_end);
+        groups.push(((start..end).map(Some).collect(), Vec::new()));
+    }
+    if cover.print == CoverPrint::WithBody {
+        groups[body_first].0.splice(0..0, front_seq);
+        groups.last_mut().expect("至少有一段").1 = back_seq;
+    }
+
+    let mut order = Vec::new();
+    let mut lengths = booklet_lengths.iter();
+    for (before, after) in groups {
+        let content = before.len() + after.len();
+        let mut length = 0;
+        while length < content {
+            match lengths.next() {
+                Some(booklet_len) => length += booklet_len,
+                None => break,
+            }
+        }
+        order.extend(before);
+        order.resize(order.len() + length.saturating_sub(content), None);
+        order.extend(after);
+    }
+    order
+}
+
+/// 校验排版计划
+///
+/// 模拟折叠（或裁开叠放）按计划排出的每一册纸张，还原读者看到的页面顺序，
+/// 与源文件页面顺序加上预定的空白页比较。只检查计划，不读取文件；
+/// 写出的文件是否与计划一致由 `verify_output` 检查。
+///
+/// # 参数
+/// * `page_count` - 源文件页数
+/// * `binding_rule` - 装订规则
+pub fn verify_layout(page_count: u16, binding_rule: &BindingRule) -> VerifyReport {
+    let actual = preview::reading_order(page_count, binding_rule);
+    let mut booklet_lengths: Vec<usize> = Vec::new();
+    for (i, page) in actual.iter().enumerate() {
+        if i == 0 || actual[i - 1].booklet_num != page.booklet_num {
+            booklet_lengths.push(0);
+        }
+        *booklet_lengths.last_mut().expect("已加入当前册") += 1;
+    }
+    let expected = expected_reading_order(page_count, binding_rule, &booklet_lengths);
+    compare_reading_order(&expected, &actual)
+}
+
+/// 比较预期和实际的阅读顺序，缺页、重复和错位分别列出
+fn compare_reading_order(expected: &[Option<u16>], actual: &[ReadingPage]) -> VerifyReport {
+    let mut issues = Vec::new();
+    if expected.len() != actual.len() {
+        issues.push(Issue::Length {
+            expected: expected.len(),
+            found: actual.len(),
+        });
+    }
+
+    let mut positions: HashMap<u16, Vec<ReadingPage>> = HashMap::new();
+    for page in actual {
+        if let Some(page_idx) = page.page_idx {
+            positions.entry(page_idx).or_default().push(*page);
+        }
+    }
+    for page_idx in expected.iter().flatten() {
+        match positions.get(page_idx) {
+            None => issues.push(Issue::Missing {
+                page_idx: *page_idx,
+            }),
+            Some(found) if found.len() > 1 => issues.push(Issue::Duplicated {
+                page_idx: *page_idx,
+                positions: found.clone(),
+            }),
+            _ => {}
+        }
+    }
+
+    let misplaced: Vec<Issue> = actual
+        .iter()
+        .zip(expected)
+        .enumerate()
+        .filter(|(_, (found, expected))| found.page_idx != **expected)
+        .map(|(i, (found, expected))| Issue::Misplaced {
+            position: i + 1,
+            expected: *expected,
+            found: *found,
+        })
+        .collect();
+    let omitted = misplaced.len().saturating_sub(MAX_MISPLACED);
+    issues.extend(misplaced.into_iter().take(MAX_MISPLACED));
+    VerifyReport { issues, omitted }
+}
+
+/// 校验生成的册子文件与排版计划是否一致
+///
+/// 读取生成的PDF，检查页数等于纸面数、每面都是不旋转的A4纸，并从每面的资源字典
+/// 还原出放置的源页面（页面图像和表单对象按 `pdf_creator::page_xobject_name` 命名），
+/// 与计划中这一面的低页和高页比较。
+///
+/// # 参数
+/// * `data` - 生成的PDF文件内容
+/// * `booklet_num` - 册子编号
+/// * `sides` - 计划中本册每面纸上的页面
+/// * `page_count` - 源文件页数
+pub fn verify_output(
+    data: &[u8],
+    booklet_num: u16,
+    sides: &[SheetSide],
+    page_count: u16,
+) -> Result<VerifyReport, BookletError> {
+    let doc = PdfReader::new(Cursor::new(data))?.into_document();
+    let found_count = doc.page_count()? as usize;
+    let mut issues = Vec::new();
+    if found_count != sides.len() {
+        issues.push(Issue::PageCount {
+            booklet_num,
+            expected: sides.len(),
+            found: found_count,
+        });
+    }
+    let a4 = Page::a4();
+    for (i, side) in sides.iter().enumerate().take(found_count) {
+        let page = doc.get_page(i as u32)?;
+        let (width, height) = (page.width(), page.height());
+        let rotation = page.rotation.rem_euclid(360);
+        if (width - a4.width()).abs() > 1.0 || (height - a4.height()).abs() > 1.0 || rotation != 0 {
+            issues.push(Issue::SheetSize {
+                booklet_num,
+                side: i,
+                width,
+                height,
+                rotation,
+            });
+        }
+        let mut expected: Vec<u16> = [side.low, side.high]
+            .into_iter()
+            .filter(|&page_idx| page_idx < page_count)
+            .collect();
+        expected.sort_unstable();
+        let xobjects = match page.get_resources().and_then(|res| res.get("XObject")) {
+            Some(xobjects) => doc.resolve(xobjects)?,
+            None => PdfObject::Null,
+        };
+        let mut found: Vec<u16> = xobjects
+            .as_dict()
+            .map(|xobjects| {
+                xobjects
+                    .0
+                    .keys()
+                    .filter_map(|name| pdf_creator::parse_page_xobject_name(&name.0))
+                    .collect()
+            })
+            .unwrap_or_default();
+        found.sort_unstable();
+        if found != expected {
+            issues.push(Issue::SidePages {
+                booklet_num,
+                side: i,
+                expected,
+                found,
+            });
+        }
+    }
+    Ok(VerifyReport { issues, omitted: 0 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::booklet;
+    use crate::cover::CoverSpec;
+    use crate::distribution::SheetRange;
+
+    fn rule(max_sheets: u32) -> BindingRule {
+        BindingRule {
+            sheets_per_booklet: SheetRange::up_to(max_sheets),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn plans_pass_verification() {
+        let mut rules = vec![rule(2), rule(5)];
+        for print in [CoverPrint::WithBody, CoverPrint::Separate, CoverPrint::Omit] {
+            rules.push(BindingRule {
+                cover: CoverSpec {
+                    front_pages: 2,
+                    back_pages: 1,
+                    print,
+                    ..Default::default()
+                },
+                booklet_breaks: vec![9, 20],
+                ..rule(2)
+            });
+        }
+        rules.push(BindingRule {
+            cover: CoverSpec {
+                file: Some(std::path::PathBuf::from("cover.pdf")),
+                ..CoverSpec::single(CoverPrint::WithBody)
+            },
+            booklet_breaks: vec![4, 9],
+            ..rule(2)
+        });
+        rules.push(BindingRule {
+            booklet_sheet_counts: vec![3, 4],
+            cover: CoverSpec::single(CoverPrint::WithBody),
+            ..rule(2)
+        });
+        for binding_rule in &rules {
+            for page_count in [1, 3, 10, 25] {
+                if booklet::check_sheet_counts(page_count, binding_rule).is_err() {
+                    continue;
+                }
+                let report = verify_layout(page_count, binding_rule);
+                assert!(
+                    report.is_ok(),
+                    "{}页 {:?}: {}",
+                    page_count,
+                    binding_rule.cover,
+                    report
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn expected_order_from_rule() {
+        let binding_rule = BindingRule {
+            cover: CoverSpec::single(CoverPrint::WithBody),
+            booklet_breaks: vec![4],
+            ..rule(2)
+        };
+        // 封面、空白页、第2~3页填满一册；第4~9页、填充、空白页、封底
+        let expected = expected_reading_order(10, &binding_rule, &[4, 12]);
+        let pages = |range: std::ops::Range<u16>| range.map(Some).collect::<Vec<_>>();
+        let mut want = vec![Some(0), None, Some(1), Some(2)];
+        want.extend(pages(3..9));
+        want.extend([None, None, None, None, None, Some(9)]);
+        assert_eq!(expected, want);
+    }
+
+    #[test]
+    fn breaks_skip_cover_file_pages() {
+        let binding_rule = BindingRule {
+            cover: CoverSpec {
+                file: Some(std::path::PathBuf::from("cover.pdf")),
+                ..CoverSpec::single(CoverPrint::WithBody)
+            },
+            booklet_breaks: vec![3],
+            ..rule(2)
+        };
+        // 封面文件的封面在第1页，源文件的第3页在拼接后的第4页，即第二册的开头
+        let expected = expected_reading_order(10, &binding_rule, &[4, 12]);
+        assert_eq!(&expected[..4], &[Some(0), None, Some(1), Some(2)]);
+        assert_eq!(expected[4], Some(3));
+        assert!(verify_layout(10, &binding_rule).is_ok());
+    }
+
+    /// 按计划的阅读顺序，修改后再比较
+    fn check_modified(modify: impl FnOnce(&mut Vec<ReadingPage>)) -> VerifyReport {
+        let binding_rule = rule(2);
+        let mut actual = preview::reading_order(10, &binding_rule);
+        let lengths = [8, 4];
+        let expected = expected_reading_order(10, &binding_rule, &lengths);
+        assert!(compare_reading_order(&expected, &actual).is_ok());
+        modify(&mut actual);
+        compare_reading_order(&expected, &actual)
+    }
+
+    fn count(report: &VerifyReport, pred: impl Fn(&Issue) -> bool) -> usize {
+        report.issues.iter().filter(|issue| pred(issue)).count()
+    }
+
+    #[test]
+    fn missing_page_is_reported() {
+        let report = check_modified(|actual| actual[4].page_idx = None);
+        assert!(matches!(
+            report.issues[..],
+            [
+                Issue::Missing { page_idx: 4 },
+                Issue::Misplaced { position: 5, .. }
+            ]
+        ));
+    }
+
+    #[test]
+    fn duplicated_page_is_reported() {
+        let report = check_modified(|actual| actual[5].page_idx = Some(2));
+        assert_eq!(
+            count(
+                &report,
+                |issue| matches!(issue, Issue::Duplicated { page_idx: 2, positions } if positions.len() == 2)
+            ),
+            1
+        );
+        assert_eq!(
+            count(&report, |issue| matches!(
+                issue,
+                Issue::Missing { page_idx: 5 }
+            )),
+            1
+        );
+        assert_eq!(
+            count(&report, |issue| matches!(issue, Issue::Misplaced { .. })),
+            1
+        );
+    }
+
+    #[test]
+    fn swapped_pages_are_reported() {
+        let report = check_modified(|actual| {
+            let (a, b) = (actual[2].page_idx, actual[7].page_idx);
+            actual[2].page_idx = b;
+            actual[7].page_idx = a;
+        });
+        assert!(matches!(
+            report.issues[..],
+            [
+                Issue::Misplaced {
+                    position: 3,
+                    expected: Some(2),
+                    found: ReadingPage {
+                        page_idx: Some(7),
+                        ..
+                    }
+                },
+                Issue::Misplaced {
+                    position: 8,
+                    expected: Some(7),
+                    found: ReadingPage {
+                        page_idx: Some(2),
+                        ..
+                    }
+                }
+            ]
+        ));
+    }
+
+    #[test]
+    fn extra_blank_booklet_changes_length() {
+        let report = check_modified(|actual| {
+            let mut blank = actual[actual.len() - 1];
+            blank.page_idx = None;
+            actual.extend([blank; 4]);
+        });
+        assert_eq!(
+            count(&report, |issue| matches!(
+                issue,
+                Issue::Length {
+                    expected: 12,
+                    found: 16
+                }
+            )),
+            1
+        );
+    }
+}